@@ -0,0 +1,15 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the bundled protoc so building doesn't depend on a system install
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+
+    // The server half is only used by the stand-in Geyser server in tests
+    tonic_build::configure()
+        .build_client(true)
+        .build_server(true)
+        .compile(&["proto/geyser.proto"], &["proto"])?;
+
+    // Migrations are embedded by `sqlx::migrate!`
+    println!("cargo:rerun-if-changed=migrations");
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+-- Baseline schema. Every statement is idempotent, so databases created before
+-- versioned migrations existed are adopted as they are.
+
+CREATE TABLE IF NOT EXISTS programs (
+    id TEXT PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS instructions (
+    id TEXT PRIMARY KEY,
+    instruction_id TEXT NOT NULL,
+    instruction_data BYTEA NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS discriminators (
+    id TEXT PRIMARY KEY,
+    discriminator_id TEXT NOT NULL,
+    discriminator_data BYTEA NOT NULL,
+    instruction_id TEXT NOT NULL REFERENCES instructions(id),
+    user_id TEXT NOT NULL REFERENCES users(id),
+    program_id TEXT NOT NULL REFERENCES programs(id)
+);
+
+CREATE TABLE IF NOT EXISTS program_cursors (
+    program_id TEXT PRIMARY KEY REFERENCES programs(id),
+    signature TEXT NOT NULL,
+    slot BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS backfill_jobs (
+    program_id TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    stop_slot BIGINT,
+    before_signature TEXT,
+    last_slot BIGINT,
+    processed BIGINT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Columns added after the initial schema
+ALTER TABLE discriminators
+    ADD COLUMN IF NOT EXISTS name TEXT,
+    ADD COLUMN IF NOT EXISTS kind TEXT NOT NULL DEFAULT 'unknown',
+    ADD COLUMN IF NOT EXISTS source TEXT,
+    ADD COLUMN IF NOT EXISTS args_layout JSONB,
+    ADD COLUMN IF NOT EXISTS via_cpi BOOLEAN,
+    ADD COLUMN IF NOT EXISTS stack_height INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_discriminators_program_id ON discriminators(program_id);
+
+CREATE INDEX IF NOT EXISTS idx_discriminators_program_kind ON discriminators(program_id, kind);
@@ -0,0 +1,33 @@
+-- Catalogue programs per cluster. Everything stored so far was indexed from devnet.
+
+ALTER TABLE programs ADD COLUMN cluster TEXT NOT NULL DEFAULT 'devnet';
+ALTER TABLE discriminators ADD COLUMN cluster TEXT NOT NULL DEFAULT 'devnet';
+ALTER TABLE program_cursors ADD COLUMN cluster TEXT NOT NULL DEFAULT 'devnet';
+ALTER TABLE backfill_jobs ADD COLUMN cluster TEXT NOT NULL DEFAULT 'devnet';
+
+-- Programs are identified by cluster and ID
+ALTER TABLE discriminators DROP CONSTRAINT IF EXISTS discriminators_program_id_fkey;
+ALTER TABLE program_cursors DROP CONSTRAINT IF EXISTS program_cursors_program_id_fkey;
+
+ALTER TABLE programs DROP CONSTRAINT programs_pkey, ADD PRIMARY KEY (cluster, id);
+ALTER TABLE program_cursors DROP CONSTRAINT program_cursors_pkey, ADD PRIMARY KEY (cluster, program_id);
+ALTER TABLE backfill_jobs DROP CONSTRAINT backfill_jobs_pkey, ADD PRIMARY KEY (cluster, program_id);
+
+ALTER TABLE discriminators
+    ADD CONSTRAINT discriminators_program_fkey FOREIGN KEY (cluster, program_id) REFERENCES programs(cluster, id);
+ALTER TABLE program_cursors
+    ADD CONSTRAINT program_cursors_program_fkey FOREIGN KEY (cluster, program_id) REFERENCES programs(cluster, id);
+
+-- Discriminator row IDs are `<cluster>_<program_id>_<discriminator_id>`
+UPDATE discriminators SET id = cluster || '_' || id;
+
+-- Writers always name the cluster
+ALTER TABLE programs ALTER COLUMN cluster DROP DEFAULT;
+ALTER TABLE discriminators ALTER COLUMN cluster DROP DEFAULT;
+ALTER TABLE program_cursors ALTER COLUMN cluster DROP DEFAULT;
+ALTER TABLE backfill_jobs ALTER COLUMN cluster DROP DEFAULT;
+
+DROP INDEX IF EXISTS idx_discriminators_program_id;
+DROP INDEX IF EXISTS idx_discriminators_program_kind;
+CREATE INDEX idx_discriminators_program_kind ON discriminators(cluster, program_id, kind);
+CREATE INDEX idx_discriminators_discriminator_id ON discriminators(cluster, discriminator_id);
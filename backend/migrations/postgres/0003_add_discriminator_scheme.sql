@@ -0,0 +1,4 @@
+-- Programs can declare how their discriminators are encoded. NULL falls back to the
+-- preconfigured scheme of a native program, or to Anchor's 8-byte sighash.
+
+ALTER TABLE programs ADD COLUMN discriminator_scheme TEXT;
@@ -0,0 +1,54 @@
+-- Baseline schema, matching the PostgreSQL one with later columns folded into the tables.
+-- Every statement is idempotent, so database files created before versioned migrations
+-- existed are adopted as they are.
+
+CREATE TABLE IF NOT EXISTS programs (
+    id TEXT PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS instructions (
+    id TEXT PRIMARY KEY,
+    instruction_id TEXT NOT NULL,
+    instruction_data BLOB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS discriminators (
+    id TEXT PRIMARY KEY,
+    discriminator_id TEXT NOT NULL,
+    discriminator_data BLOB NOT NULL,
+    instruction_id TEXT NOT NULL REFERENCES instructions(id),
+    user_id TEXT NOT NULL REFERENCES users(id),
+    program_id TEXT NOT NULL REFERENCES programs(id),
+    name TEXT,
+    kind TEXT NOT NULL DEFAULT 'unknown',
+    source TEXT,
+    args_layout TEXT,
+    via_cpi BOOLEAN,
+    stack_height INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS program_cursors (
+    program_id TEXT PRIMARY KEY REFERENCES programs(id),
+    signature TEXT NOT NULL,
+    slot BIGINT NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS backfill_jobs (
+    program_id TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    stop_slot BIGINT,
+    before_signature TEXT,
+    last_slot BIGINT,
+    processed BIGINT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_discriminators_program_id ON discriminators(program_id);
+
+CREATE INDEX IF NOT EXISTS idx_discriminators_program_kind ON discriminators(program_id, kind);
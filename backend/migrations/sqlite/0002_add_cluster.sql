@@ -0,0 +1,74 @@
+-- Catalogue programs per cluster. Everything stored so far was indexed from devnet.
+-- SQLite cannot change primary or foreign keys in place, so the affected tables are rebuilt.
+
+CREATE TABLE programs_v2 (
+    cluster TEXT NOT NULL,
+    id TEXT NOT NULL,
+    PRIMARY KEY (cluster, id)
+);
+INSERT INTO programs_v2 (cluster, id) SELECT 'devnet', id FROM programs;
+
+CREATE TABLE discriminators_v2 (
+    id TEXT PRIMARY KEY,
+    discriminator_id TEXT NOT NULL,
+    discriminator_data BLOB NOT NULL,
+    instruction_id TEXT NOT NULL REFERENCES instructions(id),
+    user_id TEXT NOT NULL REFERENCES users(id),
+    cluster TEXT NOT NULL,
+    program_id TEXT NOT NULL,
+    name TEXT,
+    kind TEXT NOT NULL DEFAULT 'unknown',
+    source TEXT,
+    args_layout TEXT,
+    via_cpi BOOLEAN,
+    stack_height INTEGER,
+    FOREIGN KEY (cluster, program_id) REFERENCES programs_v2(cluster, id)
+);
+-- Discriminator row IDs are `<cluster>_<program_id>_<discriminator_id>`
+INSERT INTO discriminators_v2
+    (id, discriminator_id, discriminator_data, instruction_id, user_id, cluster, program_id,
+     name, kind, source, args_layout, via_cpi, stack_height)
+SELECT 'devnet_' || id, discriminator_id, discriminator_data, instruction_id, user_id, 'devnet', program_id,
+       name, kind, source, args_layout, via_cpi, stack_height
+FROM discriminators;
+
+CREATE TABLE program_cursors_v2 (
+    cluster TEXT NOT NULL,
+    program_id TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    slot BIGINT NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (cluster, program_id),
+    FOREIGN KEY (cluster, program_id) REFERENCES programs_v2(cluster, id)
+);
+INSERT INTO program_cursors_v2 (cluster, program_id, signature, slot, updated_at)
+SELECT 'devnet', program_id, signature, slot, updated_at FROM program_cursors;
+
+CREATE TABLE backfill_jobs_v2 (
+    cluster TEXT NOT NULL,
+    program_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    stop_slot BIGINT,
+    before_signature TEXT,
+    last_slot BIGINT,
+    processed BIGINT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (cluster, program_id)
+);
+INSERT INTO backfill_jobs_v2 (cluster, program_id, status, stop_slot, before_signature, last_slot, processed, last_error, updated_at)
+SELECT 'devnet', program_id, status, stop_slot, before_signature, last_slot, processed, last_error, updated_at FROM backfill_jobs;
+
+-- Referencing tables go first, then renaming updates the references to the new tables
+DROP TABLE discriminators;
+DROP TABLE program_cursors;
+DROP TABLE programs;
+DROP TABLE backfill_jobs;
+
+ALTER TABLE programs_v2 RENAME TO programs;
+ALTER TABLE discriminators_v2 RENAME TO discriminators;
+ALTER TABLE program_cursors_v2 RENAME TO program_cursors;
+ALTER TABLE backfill_jobs_v2 RENAME TO backfill_jobs;
+
+CREATE INDEX idx_discriminators_program_kind ON discriminators(cluster, program_id, kind);
+CREATE INDEX idx_discriminators_discriminator_id ON discriminators(cluster, discriminator_id);
@@ -0,0 +1,10 @@
+-- API keys let bots upload without a wallet signature. Only the SHA-256 of a key is stored.
+
+CREATE TABLE api_keys (
+    key_hash TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users(id),
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_api_keys_name ON api_keys(name);
@@ -0,0 +1,4 @@
+-- Labels uploaded by a program's verified upgrade authority. They are listed first and
+-- cannot be overwritten by community uploads.
+
+ALTER TABLE discriminators ADD COLUMN authoritative BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Admin API keys may also sync on-chain IDLs and run backfills, which fetch from the RPC on
+-- the service's behalf. Existing keys keep upload access only.
+
+ALTER TABLE api_keys ADD COLUMN admin BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,677 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse};
+use futures_util::future::LocalBoxFuture;
+use log::{info, error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::cluster::Clusters;
+use crate::graph_disc::{hash_key, DatabaseInterface};
+use crate::query::{resolve_cluster, ClusterQuery};
+use crate::solana_connection::{SolanaConnection, SolanaError};
+
+// How long a nonce can be signed, and how long the session it opens lasts
+const NONCE_TTL: Duration = Duration::from_secs(5 * 60);
+const SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Nonces are handed out without authentication, so how many may wait to be signed is capped;
+// past a cap, the oldest nonce is dropped so that a flood of requests cannot lock wallets out
+const MAX_PENDING_PER_PUBKEY: usize = 5;
+const MAX_PENDING: usize = 10_000;
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+const API_KEY_PREFIX: &str = "dd_";
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AuthError {
+    #[error("Invalid public key: {0}")]
+    InvalidPubkey(String),
+
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Unknown or expired nonce")]
+    UnknownNonce,
+
+    #[error("Signature does not match the public key")]
+    SignatureMismatch,
+
+    #[error("The program's upgrade authority changed since the challenge was issued")]
+    AuthorityChanged,
+}
+
+// A (cluster, program id) pair
+type ProgramKey = (String, String);
+
+// A nonce waiting to be signed by the wallet that requested it. Challenges to prove a
+// program's upgrade authority also name the program.
+struct PendingLogin {
+    pubkey: Pubkey,
+    program: Option<ProgramKey>,
+    expires_at: Instant,
+}
+
+impl PendingLogin {
+    fn message(&self, nonce: &str) -> String {
+        match &self.program {
+            Some((cluster, program_id)) => authority_message(cluster, program_id, &self.pubkey, nonce),
+            None => login_message(&self.pubkey, nonce),
+        }
+    }
+}
+
+struct Session {
+    pubkey: Pubkey,
+    // Programs whose upgrade authority this wallet proved to hold
+    authority_of: HashSet<ProgramKey>,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginChallenge {
+    pub nonce: String,
+    // Exact text the wallet signs
+    pub message: String,
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionToken {
+    pub token: String,
+    pub pubkey: String,
+    pub expires_in: u64,
+}
+
+// Wallet logins in progress and the sessions they opened. Both are short-lived and kept in
+// process memory, so a restart signs everyone out; bots use API keys instead.
+#[derive(Default)]
+pub struct Auth {
+    nonces: Mutex<HashMap<String, PendingLogin>>,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+// Text a wallet signs to prove it controls `pubkey`
+pub fn login_message(pubkey: &Pubkey, nonce: &str) -> String {
+    format!("Sign in to Discriminator Directory\nPubkey: {}\nNonce: {}", pubkey, nonce)
+}
+
+// Text the upgrade authority of a program signs to have its labels marked authoritative
+pub fn authority_message(cluster: &str, program_id: &str, authority: &Pubkey, nonce: &str) -> String {
+    format!(
+        "Verify upgrade authority for Discriminator Directory\nProgram: {}\nCluster: {}\nAuthority: {}\nNonce: {}",
+        program_id, cluster, authority, nonce
+    )
+}
+
+// Drop the pending nonce closest to expiry among those matching `filter`
+fn evict_oldest(nonces: &mut HashMap<String, PendingLogin>, filter: impl Fn(&PendingLogin) -> bool) {
+    let oldest = nonces.iter()
+        .filter(|(_, login)| filter(login))
+        .min_by_key(|(_, login)| login.expires_at)
+        .map(|(nonce, _)| nonce.clone());
+    if let Some(nonce) = oldest {
+        nonces.remove(&nonce);
+    }
+}
+
+fn parse_signature(signature: &str) -> Result<Signature, AuthError> {
+    Signature::from_str(signature).map_err(|e| AuthError::InvalidSignature(e.to_string()))
+}
+
+// A new random API key; only its hash is stored
+pub fn generate_api_key() -> String {
+    format!("{}{}{}", API_KEY_PREFIX, Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn hash_api_key(api_key: &str) -> String {
+    hash_key(api_key)
+}
+
+impl Auth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn issue_nonce(&self, pubkey: &str) -> Result<LoginChallenge, AuthError> {
+        let pubkey = Pubkey::from_str(pubkey).map_err(|e| AuthError::InvalidPubkey(e.to_string()))?;
+        Ok(self.challenge(pubkey, None))
+    }
+
+    // Challenge the current upgrade authority of a program, as read from the chain
+    pub fn issue_authority_challenge(&self, cluster: &str, program_id: &str, authority: Pubkey) -> LoginChallenge {
+        self.challenge(authority, Some((cluster.to_string(), program_id.to_string())))
+    }
+
+    fn challenge(&self, pubkey: Pubkey, program: Option<ProgramKey>) -> LoginChallenge {
+        let nonce = Uuid::new_v4().simple().to_string();
+        let now = Instant::now();
+        let login = PendingLogin { pubkey, program, expires_at: now + NONCE_TTL };
+        let message = login.message(&nonce);
+
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.retain(|_, login| login.expires_at > now);
+        if nonces.values().filter(|login| login.pubkey == pubkey).count() >= MAX_PENDING_PER_PUBKEY {
+            evict_oldest(&mut nonces, |login| login.pubkey == pubkey);
+        }
+        if nonces.len() >= MAX_PENDING {
+            warn!("{} nonces are waiting to be signed; dropping the oldest", nonces.len());
+            evict_oldest(&mut nonces, |_| true);
+        }
+        nonces.insert(nonce.clone(), login);
+
+        LoginChallenge {
+            message,
+            nonce,
+            expires_in: NONCE_TTL.as_secs(),
+        }
+    }
+
+    // Consume a nonce issued to `pubkey` for `program`, checking the signature of its message
+    fn redeem(&self, nonce: &str, pubkey: &Pubkey, program: Option<&ProgramKey>, signature: &Signature) -> Result<(), AuthError> {
+        let login = self.nonces.lock().unwrap().remove(nonce).ok_or(AuthError::UnknownNonce)?;
+        if login.program.as_ref() != program || login.expires_at <= Instant::now() {
+            return Err(AuthError::UnknownNonce);
+        }
+        if login.pubkey != *pubkey {
+            return Err(match program {
+                Some(_) => AuthError::AuthorityChanged,
+                None => AuthError::UnknownNonce,
+            });
+        }
+        if !signature.verify(pubkey.as_ref(), login.message(nonce).as_bytes()) {
+            return Err(AuthError::SignatureMismatch);
+        }
+        Ok(())
+    }
+
+    fn open_session(&self, pubkey: Pubkey, authority_of: HashSet<ProgramKey>) -> SessionToken {
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions.insert(token.clone(), Session { pubkey, authority_of, expires_at: now + SESSION_TTL });
+
+        SessionToken {
+            token,
+            pubkey: pubkey.to_string(),
+            expires_in: SESSION_TTL.as_secs(),
+        }
+    }
+
+    // Check the wallet's signature of the login message and open a session.
+    // A nonce is consumed by its first verification attempt, successful or not.
+    pub fn verify(&self, pubkey: &str, nonce: &str, signature: &str) -> Result<SessionToken, AuthError> {
+        let pubkey = Pubkey::from_str(pubkey).map_err(|e| AuthError::InvalidPubkey(e.to_string()))?;
+        self.redeem(nonce, &pubkey, None, &parse_signature(signature)?)?;
+
+        info!("Signed in wallet {}", pubkey);
+        Ok(self.open_session(pubkey, HashSet::new()))
+    }
+
+    // Check the upgrade authority's signature of a program challenge. `authority` is the
+    // program's current upgrade authority, re-read from the chain. The proof is added to
+    // `session` when it belongs to the same wallet, else it opens a new session.
+    pub fn verify_authority(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        nonce: &str,
+        signature: &str,
+        authority: &Pubkey,
+        session: Option<&str>,
+    ) -> Result<SessionToken, AuthError> {
+        let program = (cluster.to_string(), program_id.to_string());
+        self.redeem(nonce, authority, Some(&program), &parse_signature(signature)?)?;
+        info!("Verified {} as the upgrade authority of {} on {}", authority, program_id, cluster);
+
+        let now = Instant::now();
+        if let Some(token) = session {
+            let mut sessions = self.sessions.lock().unwrap();
+            if let Some(session) = sessions.get_mut(token).filter(|session| session.pubkey == *authority && session.expires_at > now) {
+                session.authority_of.insert(program);
+                return Ok(SessionToken {
+                    token: token.to_string(),
+                    pubkey: authority.to_string(),
+                    expires_in: session.expires_at.duration_since(now).as_secs(),
+                });
+            }
+        }
+        Ok(self.open_session(*authority, HashSet::from([program])))
+    }
+
+    // The contributor a session token was issued to
+    pub fn session_contributor(&self, token: &str) -> Option<Contributor> {
+        self.sessions.lock().unwrap()
+            .get(token)
+            .filter(|session| session.expires_at > Instant::now())
+            .map(|session| Contributor {
+                user_id: session.pubkey.to_string(),
+                authority_of: session.authority_of.clone(),
+                admin: false,
+            })
+    }
+}
+
+// The verified identity behind a request: the pubkey of a signed-in wallet, or the user an
+// API key was issued to. Uploads are attributed to it in the `users` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contributor {
+    pub user_id: String,
+    authority_of: HashSet<ProgramKey>,
+    // Only API keys created with `api-key create --admin` are admins; wallets never are
+    admin: bool,
+}
+
+impl Contributor {
+    // Whether the contributor proved to hold the program's upgrade authority, and still holds
+    // it. The proof lasts as long as the session, so the authority is re-read from the chain
+    // in case it was transferred since.
+    pub async fn is_authority(&self, solana_client: &SolanaConnection, program_id: &str) -> bool {
+        if !self.authority_of.contains(&(solana_client.cluster().to_string(), program_id.to_string())) {
+            return false;
+        }
+        match solana_client.get_upgrade_authority(program_id).await {
+            Ok(Some(authority)) => authority.to_string() == self.user_id,
+            Ok(None) => false,
+            Err(e) => {
+                warn!("Failed to re-check the upgrade authority of {}: {}", program_id, e);
+                false
+            },
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+}
+
+// A contributor holding an admin API key. Extracting it is the check: requests without
+// credentials get a 401, and those of other contributors a 403.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Admin(pub Contributor);
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers().get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string)
+}
+
+fn unauthorized(message: &str) -> actix_web::Error {
+    InternalError::from_response(
+        message.to_string(),
+        HttpResponse::Unauthorized().json(json!({"error": message})),
+    ).into()
+}
+
+fn forbidden(message: &str) -> actix_web::Error {
+    InternalError::from_response(
+        message.to_string(),
+        HttpResponse::Forbidden().json(json!({"error": message})),
+    ).into()
+}
+
+impl FromRequest for Contributor {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let bearer = bearer_token(req);
+        let api_key = req.headers().get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let auth = req.app_data::<web::Data<Auth>>().cloned();
+        let db = req.app_data::<web::Data<dyn DatabaseInterface>>().cloned();
+
+        Box::pin(async move {
+            if let Some(token) = bearer {
+                return auth.and_then(|auth| auth.session_contributor(&token))
+                    .ok_or_else(|| unauthorized("Invalid or expired session"));
+            }
+
+            if let Some(api_key) = api_key {
+                let Some(db) = db else {
+                    return Err(unauthorized("API keys are not accepted here"));
+                };
+                return match db.get_api_key(&hash_api_key(&api_key)).await {
+                    Ok(Some(key)) => Ok(Contributor { user_id: key.user_id, authority_of: HashSet::new(), admin: key.admin }),
+                    Ok(None) => Err(unauthorized("Invalid API key")),
+                    Err(e) => {
+                        error!("Failed to look up API key: {}", e);
+                        Err(actix_web::error::ErrorInternalServerError("Failed to look up API key"))
+                    },
+                };
+            }
+
+            Err(unauthorized("Sign in with a wallet or pass an API key"))
+        })
+    }
+}
+
+impl FromRequest for Admin {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let contributor = Contributor::from_request(req, payload);
+        Box::pin(async move {
+            let contributor = contributor.await?;
+            if !contributor.is_admin() {
+                return Err(forbidden("An admin API key is required"));
+            }
+            Ok(Admin(contributor))
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NonceRequest {
+    pub pubkey: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub pubkey: String,
+    pub nonce: String,
+    // Base58 ed25519 signature of the challenge message
+    pub signature: String,
+}
+
+pub async fn nonce_endpoint(auth: web::Data<Auth>, request: web::Json<NonceRequest>) -> HttpResponse {
+    match auth.issue_nonce(&request.pubkey) {
+        Ok(challenge) => HttpResponse::Ok().json(challenge),
+        Err(e) => auth_error_response(e),
+    }
+}
+
+fn auth_error_response(e: AuthError) -> HttpResponse {
+    match e {
+        AuthError::InvalidPubkey(_) | AuthError::InvalidSignature(_) => {
+            HttpResponse::BadRequest().json(json!({"error": e.to_string()}))
+        },
+        _ => HttpResponse::Unauthorized().json(json!({"error": e.to_string()})),
+    }
+}
+
+pub async fn verify_endpoint(auth: web::Data<Auth>, request: web::Json<VerifyRequest>) -> HttpResponse {
+    match auth.verify(&request.pubkey, &request.nonce, &request.signature) {
+        Ok(session) => HttpResponse::Ok().json(session),
+        Err(e) => auth_error_response(e),
+    }
+}
+
+// The upgrade authority of a program, or the response explaining why there is none
+async fn upgrade_authority(clusters: &Clusters, cluster: Option<&str>, program_id: &str) -> Result<(String, Pubkey), HttpResponse> {
+    let solana_client = resolve_cluster(clusters, cluster)?;
+    match solana_client.get_upgrade_authority(program_id).await {
+        Ok(Some(authority)) => Ok((solana_client.cluster().to_string(), authority)),
+        Ok(None) => Err(HttpResponse::Conflict().json(json!({
+            "error": format!("Program {} is immutable and has no upgrade authority", program_id)
+        }))),
+        Err(e @ (SolanaError::NotUpgradeable(_) | SolanaError::PubkeyParseError(_))) => {
+            Err(HttpResponse::BadRequest().json(json!({"error": e.to_string()})))
+        },
+        Err(e) => {
+            error!("Failed to read the upgrade authority of {}: {}", program_id, e);
+            Err(HttpResponse::InternalServerError().json(json!({"error": "Failed to read the upgrade authority"})))
+        },
+    }
+}
+
+// Challenge the upgrade authority of a program to sign a nonce
+pub async fn authority_nonce_endpoint(
+    auth: web::Data<Auth>,
+    clusters: web::Data<Clusters>,
+    program_id: web::Path<String>,
+    query: web::Query<ClusterQuery>,
+) -> HttpResponse {
+    let program_id = program_id.into_inner();
+    let (cluster, authority) = match upgrade_authority(&clusters, query.cluster.as_deref(), &program_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let challenge = auth.issue_authority_challenge(&cluster, &program_id, authority);
+    HttpResponse::Ok().json(json!({
+        "authority": authority.to_string(),
+        "nonce": challenge.nonce,
+        "message": challenge.message,
+        "expires_in": challenge.expires_in,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorityVerifyRequest {
+    pub nonce: String,
+    // Base58 ed25519 signature of the challenge message by the upgrade authority
+    pub signature: String,
+}
+
+// Check the upgrade authority's signature. Its session may then upload authoritative labels
+// for the program; a session of the same wallet passed as a bearer token is extended.
+pub async fn authority_verify_endpoint(
+    auth: web::Data<Auth>,
+    clusters: web::Data<Clusters>,
+    program_id: web::Path<String>,
+    query: web::Query<ClusterQuery>,
+    request: web::Json<AuthorityVerifyRequest>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let program_id = program_id.into_inner();
+    // The authority may have been transferred since the challenge was issued
+    let (cluster, authority) = match upgrade_authority(&clusters, query.cluster.as_deref(), &program_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let session = bearer_token(&req);
+    match auth.verify_authority(&cluster, &program_id, &request.nonce, &request.signature, &authority, session.as_deref()) {
+        Ok(session) => HttpResponse::Ok().json(json!({
+            "token": session.token,
+            "pubkey": session.pubkey,
+            "expires_in": session.expires_in,
+            "cluster": cluster,
+            "program_id": program_id,
+        })),
+        Err(e) => auth_error_response(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use actix_web::{http::StatusCode, App};
+    use solana_sdk::signature::{Keypair, Signer};
+    use crate::memory_db::MemoryDatabase;
+
+    #[test]
+    fn opens_a_session_for_a_signed_nonce() {
+        let auth = Auth::new();
+        let wallet = Keypair::new();
+        let pubkey = wallet.pubkey().to_string();
+
+        let challenge = auth.issue_nonce(&pubkey).unwrap();
+        let signature = wallet.sign_message(challenge.message.as_bytes()).to_string();
+        let session = auth.verify(&pubkey, &challenge.nonce, &signature).unwrap();
+        assert_eq!(auth.session_contributor(&session.token).unwrap().user_id, pubkey);
+
+        // Nonces are single-use
+        assert_eq!(auth.verify(&pubkey, &challenge.nonce, &signature).unwrap_err(), AuthError::UnknownNonce);
+
+        // Another wallet cannot answer the challenge
+        let challenge = auth.issue_nonce(&pubkey).unwrap();
+        let forged = Keypair::new().sign_message(challenge.message.as_bytes()).to_string();
+        assert_eq!(auth.verify(&pubkey, &challenge.nonce, &forged).unwrap_err(), AuthError::SignatureMismatch);
+
+        assert!(matches!(auth.issue_nonce("not-a-pubkey"), Err(AuthError::InvalidPubkey(_))));
+        assert_eq!(auth.session_contributor("unknown"), None);
+    }
+
+    #[test]
+    fn caps_pending_nonces() {
+        let auth = Auth::new();
+        let wallet = Keypair::new();
+        let pubkey = wallet.pubkey().to_string();
+
+        let sign = |challenge: &LoginChallenge| wallet.sign_message(challenge.message.as_bytes()).to_string();
+
+        // Past the cap, a wallet's oldest nonce gives way to the new one
+        let challenges: Vec<_> = (0..=MAX_PENDING_PER_PUBKEY).map(|_| auth.issue_nonce(&pubkey).unwrap()).collect();
+        assert_eq!(auth.nonces.lock().unwrap().len(), MAX_PENDING_PER_PUBKEY);
+        assert_eq!(auth.verify(&pubkey, &challenges[0].nonce, &sign(&challenges[0])).unwrap_err(), AuthError::UnknownNonce);
+        auth.verify(&pubkey, &challenges[1].nonce, &sign(&challenges[1])).unwrap();
+
+        // Flooding the directory with nonces for other wallets cannot lock a wallet out
+        let auth = Auth::new();
+        let stale = auth.issue_nonce(&pubkey).unwrap();
+        for _ in 0..MAX_PENDING {
+            auth.issue_nonce(&Pubkey::new_unique().to_string()).unwrap();
+        }
+        assert_eq!(auth.nonces.lock().unwrap().len(), MAX_PENDING);
+        assert_eq!(auth.verify(&pubkey, &stale.nonce, &sign(&stale)).unwrap_err(), AuthError::UnknownNonce);
+        let challenge = auth.issue_nonce(&pubkey).unwrap();
+        assert!(auth.verify(&pubkey, &challenge.nonce, &sign(&challenge)).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn authoritative_labels_need_the_upgrade_authority() {
+        use actix_web::test;
+        use crate::chain_fixture::{ChainFixture, FixtureChainSource};
+        use crate::graph_disc::Discriminator;
+        use crate::query::{query_discriminators_endpoint, upload_discriminator_endpoint};
+        use crate::solana_connection::SolanaConnection;
+
+        let authority = Keypair::new();
+        let program_id = Pubkey::new_unique().to_string();
+        let immutable_id = Pubkey::new_unique();
+        let fixture = ChainFixture::default()
+            .with_upgradeable_program(&Pubkey::from_str(&program_id).unwrap(), Some(&authority.pubkey()))
+            .with_upgradeable_program(&immutable_id, None);
+        let solana_client = SolanaConnection::with_source(Arc::new(FixtureChainSource::new(fixture)));
+
+        let db: Arc<dyn DatabaseInterface> = Arc::new(MemoryDatabase::new());
+        let api_key = generate_api_key();
+        db.create_api_key(&hash_api_key(&api_key), "community", "community", false).await.unwrap();
+
+        let auth = web::Data::new(Auth::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![solana_client])))
+                .app_data(auth.clone())
+                .route("/auth/authority/{program_id}/nonce", web::post().to(authority_nonce_endpoint))
+                .route("/auth/authority/{program_id}/verify", web::post().to(authority_verify_endpoint))
+                .route("/upload_discriminator/{program_id}", web::post().to(upload_discriminator_endpoint::<dyn DatabaseInterface>))
+                .route("/query_discriminators/{program_id}", web::get().to(query_discriminators_endpoint::<dyn DatabaseInterface>))
+        ).await;
+        let upload = |header: (&str, String), discriminator: &str, name: &str| test::TestRequest::post()
+            .uri(&format!("/upload_discriminator/{}", program_id))
+            .insert_header(header)
+            .set_json(json!({"discriminator": discriminator, "name": name}))
+            .to_request();
+        let challenge = || test::TestRequest::post().uri(&format!("/auth/authority/{}/nonce", program_id)).to_request();
+        let verify = |nonce: &str, signature: String| test::TestRequest::post()
+            .uri(&format!("/auth/authority/{}/verify", program_id))
+            .set_json(json!({"nonce": nonce, "signature": signature}))
+            .to_request();
+
+        let community = (API_KEY_HEADER, api_key.clone());
+        test::call_service(&app, upload(community.clone(), "0101010101010101", "first")).await;
+        test::call_service(&app, upload(community.clone(), "0202020202020202", "community_swap")).await;
+
+        // Someone else cannot answer the authority's challenge
+        let issued: serde_json::Value = test::call_and_read_body_json(&app, challenge()).await;
+        assert_eq!(issued["authority"], authority.pubkey().to_string());
+        let forged = Keypair::new().sign_message(issued["message"].as_str().unwrap().as_bytes()).to_string();
+        let response = test::call_service(&app, verify(issued["nonce"].as_str().unwrap(), forged)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let issued: serde_json::Value = test::call_and_read_body_json(&app, challenge()).await;
+        let signature = authority.sign_message(issued["message"].as_str().unwrap().as_bytes()).to_string();
+        let session: serde_json::Value = test::call_and_read_body_json(&app, verify(issued["nonce"].as_str().unwrap(), signature)).await;
+        let official = ("Authorization", format!("Bearer {}", session["token"].as_str().unwrap()));
+        assert!(test::call_service(&app, upload(official.clone(), "0202020202020202", "swap")).await.status().is_success());
+
+        // Community uploads no longer change the authority's label, which is listed first
+        test::call_service(&app, upload(community, "0202020202020202", "fake_swap")).await;
+        let request = test::TestRequest::get().uri(&format!("/query_discriminators/{}", program_id)).to_request();
+        let discriminators: Vec<Discriminator> = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(discriminators[0].name.as_deref(), Some("swap"));
+        assert!(discriminators[0].authoritative);
+        assert_eq!(discriminators[0].user_id, authority.pubkey().to_string());
+        assert!(!discriminators[1].authoritative);
+
+        let request = test::TestRequest::post().uri(&format!("/auth/authority/{}/nonce", immutable_id)).to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::CONFLICT);
+
+        // Once the authority is transferred, the session's proof no longer counts
+        let fixture = ChainFixture::default()
+            .with_upgradeable_program(&Pubkey::from_str(&program_id).unwrap(), Some(&Keypair::new().pubkey()));
+        let solana_client = SolanaConnection::with_source(Arc::new(FixtureChainSource::new(fixture)));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![solana_client])))
+                .app_data(auth.clone())
+                .route("/upload_discriminator/{program_id}", web::post().to(upload_discriminator_endpoint::<dyn DatabaseInterface>))
+        ).await;
+        assert!(test::call_service(&app, upload(official, "0303030303030303", "withdraw")).await.status().is_success());
+        let stored = db.get_discriminator("mainnet", &program_id, "0303030303030303").await.unwrap().unwrap();
+        assert!(!stored.authoritative);
+    }
+
+    async fn whoami(contributor: Contributor) -> HttpResponse {
+        HttpResponse::Ok().body(contributor.user_id)
+    }
+
+    #[actix_web::test]
+    async fn identifies_contributors_by_session_or_api_key() {
+        use actix_web::test;
+
+        let db: Arc<dyn DatabaseInterface> = Arc::new(MemoryDatabase::new());
+        let api_key = generate_api_key();
+        db.create_api_key(&hash_api_key(&api_key), "indexer-bot", "bot-user", false).await.unwrap();
+
+        let auth = web::Data::new(Auth::new());
+        let wallet = Keypair::new();
+        let challenge = auth.issue_nonce(&wallet.pubkey().to_string()).unwrap();
+        let signature = wallet.sign_message(challenge.message.as_bytes()).to_string();
+        let session = auth.verify(&wallet.pubkey().to_string(), &challenge.nonce, &signature).unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(auth.clone())
+                .route("/whoami", web::get().to(whoami))
+        ).await;
+        let request = |header: Option<(&str, String)>| {
+            let request = test::TestRequest::get().uri("/whoami");
+            match header {
+                Some(header) => request.insert_header(header).to_request(),
+                None => request.to_request(),
+            }
+        };
+
+        let body = test::call_and_read_body(&app, request(Some(("Authorization", format!("Bearer {}", session.token))))).await;
+        assert_eq!(body, wallet.pubkey().to_string());
+        let body = test::call_and_read_body(&app, request(Some((API_KEY_HEADER, api_key)))).await;
+        assert_eq!(body, "bot-user");
+
+        for header in [None, Some(("Authorization", "Bearer forged".to_string())), Some((API_KEY_HEADER, "dd_forged".to_string()))] {
+            assert_eq!(test::call_service(&app, request(header)).await.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        assert_eq!(db.revoke_api_keys("indexer-bot").await.unwrap(), 1);
+    }
+}
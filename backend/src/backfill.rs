@@ -0,0 +1,240 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use log::{info, error, warn, debug};
+use solana_sdk::signature::Signature;
+use tokio_retry::{strategy::ExponentialBackoff, Retry};
+
+use crate::graph_disc::{BackfillJob, BackfillStatus, DatabaseError, DatabaseInterface};
+use crate::solana_connection::{ChainSource, SolanaConnection};
+
+// Attempts per transaction before the job is marked as failed
+const FETCH_ATTEMPTS: usize = 4;
+
+impl BackfillJob {
+    pub fn new(cluster: &str, program_id: &str, stop_slot: Option<u64>) -> Self {
+        BackfillJob {
+            cluster: cluster.to_string(),
+            program_id: program_id.to_string(),
+            status: BackfillStatus::Running,
+            stop_slot,
+            before_signature: None,
+            last_slot: None,
+            processed: 0,
+            last_error: None,
+        }
+    }
+}
+
+// Run a backfill job in the background unless one is already running for the program.
+// `solana_client` must be connected to the job's cluster. Returns false if the job was not started.
+pub fn spawn_backfill<T>(solana_client: &SolanaConnection, db: Arc<T>, job: BackfillJob) -> bool
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    if !solana_client.claim_backfill(&job.program_id) {
+        return false;
+    }
+
+    let solana_client = solana_client.clone();
+    tokio::spawn(async move {
+        let program_id = job.program_id.clone();
+        if let Err(e) = run_backfill(&solana_client, db.as_ref(), job).await {
+            error!("Backfill for program {} stopped: {}", program_id, e);
+        }
+        solana_client.release_backfill(&program_id);
+    });
+
+    true
+}
+
+// The job to run for a program: an unfinished one resumes where it stopped,
+// a finished one starts over from the newest signature
+pub async fn next_backfill_job<T>(
+    db: &T,
+    cluster: &str,
+    program_id: &str,
+    stop_slot: Option<u64>,
+) -> Result<BackfillJob, DatabaseError>
+where
+    T: DatabaseInterface + ?Sized,
+{
+    match db.get_backfill_job(cluster, program_id).await? {
+        Some(mut job) if job.status != BackfillStatus::Completed => {
+            if stop_slot.is_some() {
+                job.stop_slot = stop_slot;
+            }
+            Ok(job)
+        },
+        _ => Ok(BackfillJob::new(cluster, program_id, stop_slot)),
+    }
+}
+
+// Walk a program's signatures backwards from where the job left off, saving progress
+// after every transaction so the job can resume after a crash
+pub async fn run_backfill<T>(
+    solana_client: &SolanaConnection,
+    db: &T,
+    mut job: BackfillJob,
+) -> Result<BackfillJob, DatabaseError>
+where
+    T: DatabaseInterface + ?Sized,
+{
+    info!("Backfilling program {} on {} from {:?}", job.program_id, job.cluster, job.before_signature);
+    job.status = BackfillStatus::Running;
+    job.last_error = None;
+    db.save_backfill_job(&job).await?;
+
+    loop {
+        let before = match job.before_signature.as_deref().map(Signature::from_str).transpose() {
+            Ok(before) => before,
+            Err(e) => return fail(db, job, format!("Invalid resume signature: {}", e)).await,
+        };
+
+        let page = match solana_client.get_signatures_page(&job.program_id, before, None).await {
+            Ok(page) => page,
+            Err(e) => return fail(db, job, e.to_string()).await,
+        };
+
+        // An empty page means the program's first transaction was reached
+        if page.is_empty() {
+            return complete(db, job).await;
+        }
+        debug!("Backfilling {} signatures for program {}", page.len(), job.program_id);
+
+        for signature in page {
+            if job.stop_slot.is_some_and(|stop_slot| signature.slot < stop_slot) {
+                return complete(db, job).await;
+            }
+
+            match Signature::from_str(&signature.signature) {
+                Ok(tx_signature) => {
+                    let strategy = ExponentialBackoff::from_millis(500).take(FETCH_ATTEMPTS - 1);
+                    let transaction = match Retry::spawn(strategy, || solana_client.get_transaction(&tx_signature)).await {
+                        Ok(transaction) => transaction,
+                        Err(e) => {
+                            let message = format!("Failed to get transaction {}: {}", signature.signature, e);
+                            return fail(db, job, message).await;
+                        }
+                    };
+
+                    solana_client.process_transaction(db, &signature.signature, &transaction).await;
+                },
+                Err(e) => warn!("Failed to parse signature {}: {}", signature.signature, e),
+            }
+
+            job.before_signature = Some(signature.signature);
+            job.last_slot = Some(signature.slot);
+            job.processed += 1;
+            db.save_backfill_job(&job).await?;
+        }
+    }
+}
+
+async fn complete<T>(db: &T, mut job: BackfillJob) -> Result<BackfillJob, DatabaseError>
+where
+    T: DatabaseInterface + ?Sized,
+{
+    info!("Backfill for program {} completed after {} transactions", job.program_id, job.processed);
+    job.status = BackfillStatus::Completed;
+    db.save_backfill_job(&job).await?;
+    Ok(job)
+}
+
+async fn fail<T>(db: &T, mut job: BackfillJob, message: String) -> Result<BackfillJob, DatabaseError>
+where
+    T: DatabaseInterface + ?Sized,
+{
+    error!("Backfill for program {} failed: {}", job.program_id, message);
+    job.status = BackfillStatus::Failed;
+    job.last_error = Some(message);
+    db.save_backfill_job(&job).await?;
+    Ok(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::{v0, MessageHeader};
+    use solana_sdk::pubkey::Pubkey;
+    use crate::chain_fixture::{confirmed_transaction, ChainFixture, FixtureChainSource};
+    use crate::memory_db::MemoryDatabase;
+
+    // Paused time lets the fetch retries back off without waiting
+    #[tokio::test(start_paused = true)]
+    async fn walks_back_to_the_stop_slot_and_resumes_after_a_failure() {
+        let program_id = Pubkey::new_from_array([7; 32]);
+        let program = program_id.to_string();
+        let recorded = || -> ChainFixture {
+            let json = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/chain.json")).unwrap();
+            serde_json::from_str(&json).unwrap()
+        };
+        // Signatures at slots 103, 102 and 101, newest first
+        let signatures = recorded().signatures[&program].clone();
+
+        // The transaction at slot 10x invokes the program with discriminator [x; 8]
+        let connection = |fetchable: &[usize]| {
+            let mut fixture = recorded();
+            for &i in fetchable {
+                let tag = (signatures[i].slot - 100) as u8;
+                let message = v0::Message {
+                    header: MessageHeader {
+                        num_required_signatures: 1,
+                        num_readonly_signed_accounts: 0,
+                        num_readonly_unsigned_accounts: 1,
+                    },
+                    account_keys: vec![Pubkey::new_unique(), program_id],
+                    recent_blockhash: Hash::default(),
+                    instructions: vec![CompiledInstruction::new_from_raw_parts(1, vec![tag; 9], vec![0])],
+                    address_table_lookups: vec![],
+                };
+                let transaction = serde_json::to_value(confirmed_transaction(message, json!({}))).unwrap();
+                fixture.transactions.insert(signatures[i].signature.clone(), transaction);
+            }
+            SolanaConnection::with_source(Arc::new(FixtureChainSource::new(fixture)))
+        };
+        async fn stored(db: &MemoryDatabase, program: &str, tag: u8) -> bool {
+            db.get_discriminator("mainnet", program, &hex::encode([tag; 8])).await.unwrap().is_some()
+        }
+
+        // Signatures older than the stop slot are left alone
+        let db = MemoryDatabase::new();
+        let job = next_backfill_job(&db, "mainnet", &program, Some(102)).await.unwrap();
+        let job = run_backfill(&connection(&[0, 1, 2]), &db, job).await.unwrap();
+        assert_eq!(job.status, BackfillStatus::Completed);
+        assert_eq!(job.processed, 2);
+        assert_eq!(job.last_slot, Some(102));
+        assert!(stored(&db, &program, 2).await);
+        assert!(!stored(&db, &program, 1).await);
+
+        // A finished job starts over from the newest signature
+        let job = next_backfill_job(&db, "mainnet", &program, None).await.unwrap();
+        assert_eq!(job.before_signature, None);
+        assert_eq!(job.processed, 0);
+
+        // Once retries run out the job stops after the last transaction it processed
+        let db = MemoryDatabase::new();
+        let job = next_backfill_job(&db, "mainnet", &program, None).await.unwrap();
+        let job = run_backfill(&connection(&[0]), &db, job).await.unwrap();
+        assert_eq!(job.status, BackfillStatus::Failed);
+        assert_eq!(job.processed, 1);
+        assert_eq!(job.before_signature.as_ref(), Some(&signatures[0].signature));
+        assert!(job.last_error.as_deref().unwrap().contains(&signatures[1].signature));
+
+        // and resumes from there, keeping its progress
+        let job = next_backfill_job(&db, "mainnet", &program, None).await.unwrap();
+        assert_eq!(job.status, BackfillStatus::Failed);
+        assert_eq!(job.before_signature.as_ref(), Some(&signatures[0].signature));
+        let job = run_backfill(&connection(&[1, 2]), &db, job).await.unwrap();
+        assert_eq!(job.status, BackfillStatus::Completed);
+        assert_eq!(job.processed, 3);
+        assert_eq!(job.last_slot, Some(101));
+        assert_eq!(job.last_error, None);
+        assert!(stored(&db, &program, 3).await && stored(&db, &program, 2).await && stored(&db, &program, 1).await);
+        let saved = db.get_backfill_job("mainnet", &program).await.unwrap().unwrap();
+        assert_eq!(saved.status, BackfillStatus::Completed);
+    }
+}
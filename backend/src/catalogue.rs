@@ -0,0 +1,143 @@
+use serde::Deserialize;
+use log::{info, debug};
+
+use crate::graph_disc::{DatabaseError, DatabaseInterface, DiscriminatorKind, DiscriminatorMetadata};
+use crate::idl::{ArgsLayout, IdlField, IdlTypeDef};
+use crate::scheme::DiscriminatorScheme;
+
+// Instructions of the native and SPL programs, shipped with the binary.
+// Bump `version` whenever an entry changes so reseeded rows can be told apart.
+const NATIVE_PROGRAMS: &str = include_str!("../catalogue/native_programs.json");
+
+#[derive(Debug, Deserialize)]
+pub struct Catalogue {
+    pub version: u32,
+    pub programs: Vec<CatalogueProgram>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CatalogueProgram {
+    pub program_id: String,
+    pub name: String,
+    pub scheme: DiscriminatorScheme,
+    pub instructions: Vec<CatalogueInstruction>,
+    #[serde(default)]
+    pub types: Vec<IdlTypeDef>,
+}
+
+// An instruction and its numeric tag. `args` is left out where the arguments cannot be
+// described as Borsh, e.g. bincode strings with their u64 length prefix.
+#[derive(Debug, Deserialize)]
+pub struct CatalogueInstruction {
+    pub name: String,
+    #[serde(default)]
+    pub tag: u32,
+    #[serde(default)]
+    pub args: Option<Vec<IdlField>>,
+}
+
+impl Catalogue {
+    pub fn native_programs() -> Result<Self, DatabaseError> {
+        serde_json::from_str(NATIVE_PROGRAMS)
+            .map_err(|e| DatabaseError::DataParsingError(format!("Invalid instruction catalogue: {}", e)))
+    }
+
+    // Recorded as the source of every seeded discriminator
+    pub fn source(&self) -> String {
+        format!("catalogue-v{}", self.version)
+    }
+}
+
+// Store the scheme and named instructions of every catalogued program on a cluster.
+// Seeding is idempotent; rerunning it with a newer catalogue updates names and layouts.
+pub async fn seed_catalogue<T>(
+    db: &T,
+    cluster: &str,
+    catalogue: &Catalogue,
+    user_id: &str,
+) -> Result<usize, DatabaseError>
+where
+    T: DatabaseInterface + ?Sized,
+{
+    let source = catalogue.source();
+    let mut count = 0;
+
+    for program in &catalogue.programs {
+        debug!("Seeding {} instructions of {} on {}", program.instructions.len(), program.name, cluster);
+        db.set_discriminator_scheme(cluster, &program.program_id, program.scheme).await?;
+
+        for instruction in &program.instructions {
+            let discriminator_data = program.scheme.encode_tag(instruction.tag).ok_or_else(|| {
+                DatabaseError::DataParsingError(format!(
+                    "Tag {} of {} cannot be encoded with the {} scheme", instruction.tag, instruction.name, program.scheme
+                ))
+            })?;
+
+            let metadata = DiscriminatorMetadata {
+                name: Some(instruction.name.clone()),
+                kind: DiscriminatorKind::Instruction,
+                source: Some(source.clone()),
+                // Native programs have no upgrade authority to vouch for them; the catalogue is
+                // curated from their sources instead, so community uploads may not rename them
+                authoritative: true,
+                args_layout: instruction.args.clone().map(|args| ArgsLayout::new(args, &program.types)),
+                invocation: None,
+            };
+
+            db.set_discriminator_metadata(cluster, &program.program_id, discriminator_data, user_id, metadata).await?;
+            count += 1;
+        }
+    }
+
+    info!("Seeded {} instructions of {} programs from {} on {}", count, catalogue.programs.len(), source, cluster);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode_args;
+    use crate::memory_db::MemoryDatabase;
+
+    #[tokio::test]
+    async fn seeds_native_program_instructions() {
+        let db = MemoryDatabase::new();
+        let catalogue = Catalogue::native_programs().unwrap();
+        let count = seed_catalogue(&db, "devnet", &catalogue, "catalogue").await.unwrap();
+        assert_eq!(count, catalogue.programs.iter().map(|program| program.instructions.len()).sum::<usize>());
+
+        let system = db.query_discriminators_and_instructions("devnet", "11111111111111111111111111111111", None).await.unwrap();
+        let transfer = system.iter().find(|d| d.discriminator_data == vec![2, 0, 0, 0]).unwrap();
+        assert_eq!(transfer.name.as_deref(), Some("transfer"));
+        assert_eq!(transfer.source.as_deref(), Some("catalogue-v1"));
+
+        let layout = transfer.args_layout.as_ref().unwrap();
+        let args = decode_args(layout, &1_000_000u64.to_le_bytes()).unwrap();
+        assert_eq!(args["lamports"], 1_000_000);
+
+        let token_program = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        let community = DiscriminatorMetadata {
+            name: Some("drain".to_string()),
+            kind: DiscriminatorKind::Instruction,
+            ..Default::default()
+        };
+        db.upload_discriminator("devnet", token_program, vec![12], Vec::new(), "community", community).await.unwrap();
+        let token = db.get_discriminator("devnet", token_program, "0c").await.unwrap().unwrap();
+        assert_eq!(token.name.as_deref(), Some("transferChecked"));
+        assert!(token.authoritative);
+
+        let memo = db.query_discriminators_and_instructions("devnet", "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr", None).await.unwrap();
+        assert_eq!(memo[0].name.as_deref(), Some("memo"));
+        assert!(memo[0].discriminator_data.is_empty());
+
+        // Reseeding leaves a single row per instruction and keeps the instruction data seen on chain
+        let observed = 5_000u64.to_le_bytes().to_vec();
+        db.upload_discriminator("devnet", "11111111111111111111111111111111", vec![2, 0, 0, 0], observed.clone(), "signature", Default::default()).await.unwrap();
+        seed_catalogue(&db, "devnet", &catalogue, "catalogue").await.unwrap();
+        let reseeded = db.query_discriminators_and_instructions("devnet", "11111111111111111111111111111111", None).await.unwrap();
+        assert_eq!(reseeded.len(), system.len());
+        let transfer = reseeded.iter().find(|d| d.discriminator_data == vec![2, 0, 0, 0]).unwrap();
+        assert_eq!(transfer.name.as_deref(), Some("transfer"));
+        assert_eq!(transfer.instruction.instruction_data, observed);
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_client::rpc_response::{RpcConfirmedTransactionStatusWithSignature, RpcKeyedAccount};
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+
+use crate::solana_connection::{ChainSource, SolanaError, SIGNATURE_PAGE_LIMIT};
+
+// Chain data recorded from an RPC node, in the shape the node returns it:
+// `getProgramAccounts` / `getAccountInfo` results as keyed accounts,
+// `getSignaturesForAddress` results (newest first) and `getTransaction` results.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainFixture {
+    #[serde(default)]
+    pub program_accounts: HashMap<String, Vec<RpcKeyedAccount>>,
+    #[serde(default)]
+    pub accounts: Vec<RpcKeyedAccount>,
+    #[serde(default)]
+    pub signatures: HashMap<String, Vec<RpcConfirmedTransactionStatusWithSignature>>,
+    #[serde(default)]
+    pub transactions: HashMap<String, Value>,
+}
+
+// Chain source that answers from a recorded fixture instead of a live node
+pub struct FixtureChainSource {
+    fixture: ChainFixture,
+}
+
+impl FixtureChainSource {
+    pub fn new(fixture: ChainFixture) -> Self {
+        FixtureChainSource { fixture }
+    }
+
+    // Load a fixture recorded as JSON
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SolanaError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| SolanaError::RpcError(format!("Failed to read fixture {}: {}", path.display(), e)))?;
+        let fixture = serde_json::from_str(&json)
+            .map_err(|e| SolanaError::RpcError(format!("Invalid fixture {}: {}", path.display(), e)))?;
+        Ok(Self::new(fixture))
+    }
+}
+
+#[cfg(test)]
+impl ChainFixture {
+    // Add a program deployed with the upgradeable BPF loader, and its ProgramData account
+    pub fn with_upgradeable_program(mut self, program_id: &Pubkey, authority: Option<&Pubkey>) -> Self {
+        let loader = solana_sdk::bpf_loader_upgradeable::id();
+        let (programdata_address, _) = Pubkey::find_program_address(&[program_id.as_ref()], &loader);
+
+        let mut program = 2u32.to_le_bytes().to_vec();
+        program.extend_from_slice(programdata_address.as_ref());
+
+        let mut programdata = 3u32.to_le_bytes().to_vec();
+        programdata.extend_from_slice(&0u64.to_le_bytes());
+        match authority {
+            Some(authority) => {
+                programdata.push(1);
+                programdata.extend_from_slice(authority.as_ref());
+            },
+            None => programdata.extend_from_slice(&[0; 33]),
+        }
+        // The program's ELF follows the header
+        programdata.extend_from_slice(b"\x7fELF");
+
+        for (address, data) in [(program_id, program), (&programdata_address, programdata)] {
+            let keyed = serde_json::json!({
+                "pubkey": address.to_string(),
+                "account": {
+                    "data": [base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &data), "base64"],
+                    "executable": address == program_id,
+                    "lamports": 1_000_000,
+                    "owner": loader.to_string(),
+                    "rentEpoch": 0,
+                    "space": data.len(),
+                },
+            });
+            self.accounts.push(serde_json::from_value(keyed).unwrap());
+        }
+        self
+    }
+}
+
+// Encode a confirmed transaction with the given meta, as `getTransaction` returns it
+#[cfg(test)]
+pub fn confirmed_transaction(
+    message: solana_sdk::message::v0::Message,
+    meta: Value,
+) -> EncodedConfirmedTransactionWithStatusMeta {
+    use base64::Engine;
+    use solana_sdk::message::VersionedMessage;
+    use solana_sdk::transaction::VersionedTransaction;
+
+    let transaction = VersionedTransaction {
+        signatures: vec![Signature::default()],
+        message: VersionedMessage::V0(message),
+    };
+    let mut meta_json = serde_json::json!({
+        "err": null,
+        "status": { "Ok": null },
+        "fee": 5000,
+        "preBalances": [],
+        "postBalances": [],
+        "logMessages": [],
+        "innerInstructions": [],
+        "loadedAddresses": { "writable": [], "readonly": [] },
+    });
+    meta_json.as_object_mut().unwrap().extend(meta.as_object().unwrap().clone());
+
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bincode::serialize(&transaction).unwrap());
+    serde_json::from_value(serde_json::json!({
+        "slot": 1,
+        "blockTime": null,
+        "version": 0,
+        "transaction": [encoded, "base64"],
+        "meta": meta_json,
+    })).unwrap()
+}
+
+fn decode_keyed_account(keyed: &RpcKeyedAccount) -> Result<(Pubkey, Account), SolanaError> {
+    let pubkey = Pubkey::from_str(&keyed.pubkey)
+        .map_err(|e| SolanaError::PubkeyParseError(e.to_string()))?;
+    let account = keyed.account.decode::<Account>()
+        .ok_or_else(|| SolanaError::RpcError(format!("Undecodable account {} in fixture", keyed.pubkey)))?;
+    Ok((pubkey, account))
+}
+
+#[async_trait]
+impl ChainSource for FixtureChainSource {
+    async fn get_program_accounts(&self, program_id: &str) -> Result<Vec<(Pubkey, Account)>, SolanaError> {
+        Pubkey::from_str(program_id).map_err(|e| SolanaError::PubkeyParseError(e.to_string()))?;
+
+        self.fixture.program_accounts.get(program_id)
+            .map(|accounts| accounts.iter().map(decode_keyed_account).collect())
+            .unwrap_or_else(|| Ok(Vec::new()))
+    }
+
+    async fn get_signatures_page(
+        &self,
+        program_id: &str,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, SolanaError> {
+        Pubkey::from_str(program_id).map_err(|e| SolanaError::PubkeyParseError(e.to_string()))?;
+        let Some(signatures) = self.fixture.signatures.get(program_id) else {
+            return Ok(Vec::new());
+        };
+
+        // Same bounds as the RPC method: strictly older than `before`, strictly newer than `until`
+        let start = match before {
+            Some(before) => {
+                let before = before.to_string();
+                signatures.iter()
+                    .position(|signature| signature.signature == before)
+                    .map_or(signatures.len(), |position| position + 1)
+            },
+            None => 0,
+        };
+        let until = until.map(|until| until.to_string());
+
+        Ok(signatures[start..].iter()
+            .take_while(|signature| Some(&signature.signature) != until.as_ref())
+            .take(SIGNATURE_PAGE_LIMIT)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, SolanaError> {
+        let address = address.to_string();
+        self.fixture.accounts.iter()
+            .chain(self.fixture.program_accounts.values().flatten())
+            .find(|keyed| keyed.pubkey == address)
+            .map(|keyed| decode_keyed_account(keyed).map(|(_, account)| account))
+            .transpose()
+    }
+
+    async fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransactionWithStatusMeta, SolanaError> {
+        let transaction = self.fixture.transactions.get(&signature.to_string())
+            .ok_or_else(|| SolanaError::RpcError(format!("Transaction {} not in fixture", signature)))?;
+
+        // Decoded on every call, since the encoded form cannot be cloned
+        EncodedConfirmedTransactionWithStatusMeta::deserialize(transaction)
+            .map_err(|e| SolanaError::RpcError(format!("Invalid transaction {} in fixture: {}", signature, e)))
+    }
+
+    // The slot of the newest recorded signature
+    async fn get_slot(&self) -> Result<u64, SolanaError> {
+        Ok(self.fixture.signatures.values().flatten().map(|signature| signature.slot).max().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(byte: u8) -> Signature {
+        Signature::from([byte; 64])
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_chain_data() {
+        let source = FixtureChainSource::load(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/chain.json")).unwrap();
+        let program = Pubkey::new_from_array([7; 32]).to_string();
+        let account = Pubkey::new_from_array([8; 32]);
+
+        let accounts = source.get_program_accounts(&program).await.unwrap();
+        assert_eq!(accounts[0].0, account);
+        assert_eq!(accounts[0].1.data, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert!(source.get_account(&account).await.unwrap().is_some());
+
+        let slots = |page: Vec<RpcConfirmedTransactionStatusWithSignature>| {
+            page.iter().map(|signature| signature.slot).collect::<Vec<_>>()
+        };
+        assert_eq!(slots(source.get_signatures_page(&program, None, None).await.unwrap()), vec![103, 102, 101]);
+        assert_eq!(slots(source.get_signatures_page(&program, Some(signature(3)), None).await.unwrap()), vec![102, 101]);
+        assert_eq!(slots(source.get_signatures_page(&program, None, Some(signature(1))).await.unwrap()), vec![103, 102]);
+        assert!(source.get_transaction(&signature(3)).await.is_err());
+        assert_eq!(source.get_slot().await.unwrap(), 103);
+    }
+}
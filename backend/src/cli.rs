@@ -0,0 +1,204 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+
+use crate::auth::{generate_api_key, hash_api_key};
+use crate::backfill::{next_backfill_job, run_backfill};
+use crate::catalogue::{seed_catalogue, Catalogue};
+use crate::config::{ConfigArgs, DatabaseConfig};
+use crate::graph_disc::{BackfillStatus, DatabaseInterface, GraphDatabase};
+use crate::idl::{self, Idl};
+use crate::migrations::SchemaMigrations;
+use crate::solana_connection::SolanaConnection;
+
+// Command line of the backend binary
+#[derive(Debug, Parser)]
+#[command(name = "discriminator-directory", about = "Discriminator Directory backend")]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP API and the indexer (the default)
+    Serve,
+    /// Run the indexer without the HTTP API
+    Index,
+    /// Backfill the transaction history of a program, then exit
+    Backfill {
+        /// Program to backfill
+        program_id: String,
+        /// Cluster of the program (the default cluster if omitted)
+        #[arg(long)]
+        cluster: Option<String>,
+        /// Stop at transactions older than this slot
+        #[arg(long)]
+        stop_slot: Option<u64>,
+    },
+    /// Apply or inspect schema migrations
+    Migrate {
+        #[command(subcommand)]
+        action: Option<MigrateAction>,
+    },
+    /// Store the discriminators of an Anchor IDL file
+    Import {
+        /// Anchor IDL JSON file
+        path: PathBuf,
+        /// Program the IDL belongs to (read from the IDL if omitted)
+        #[arg(long)]
+        program_id: Option<String>,
+        /// Cluster of the program (the default cluster if omitted)
+        #[arg(long)]
+        cluster: Option<String>,
+        /// User recorded as the contributor
+        #[arg(long, default_value = "import")]
+        user_id: String,
+    },
+    /// Store the built-in catalogue of native and SPL program instructions
+    Seed {
+        /// Cluster to seed (every configured cluster if omitted)
+        #[arg(long)]
+        cluster: Option<String>,
+    },
+    /// Manage the API keys bots upload with
+    ApiKey {
+        #[command(subcommand)]
+        action: ApiKeyAction,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+pub enum MigrateAction {
+    /// Apply every pending migration (the default)
+    Run,
+    /// List the migrations of this build and whether they are applied
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ApiKeyAction {
+    /// Issue a key and print it; only its hash is stored
+    Create {
+        /// Label to revoke the key by
+        name: String,
+        /// User uploads made with the key are attributed to (the key's name if omitted)
+        #[arg(long)]
+        user: Option<String>,
+        /// Also allow the key to sync on-chain IDLs and run backfills
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Revoke every key with this name
+    Revoke {
+        name: String,
+    },
+}
+
+// Open the configured database without touching its schema
+async fn connect_for_migrations(database: &DatabaseConfig) -> Result<Box<dyn SchemaMigrations>, Box<dyn Error>> {
+    if database.url.starts_with("memory:") {
+        return Err("The in-memory database has no schema to migrate".into());
+    }
+
+    if database.url.starts_with("sqlite:") {
+        #[cfg(feature = "sqlite")]
+        return Ok(Box::new(crate::sqlite_db::SqliteDatabase::connect(&database.url, database.max_connections).await?));
+        #[cfg(not(feature = "sqlite"))]
+        return Err("SQLite support requires building with the `sqlite` feature".into());
+    }
+
+    Ok(Box::new(GraphDatabase::connect(&database.url, database.max_connections).await?))
+}
+
+pub async fn migrate(database: &DatabaseConfig, action: MigrateAction) -> Result<(), Box<dyn Error>> {
+    let db = connect_for_migrations(database).await?;
+
+    match action {
+        MigrateAction::Run => {
+            let applied = db.run_migrations().await?;
+            if applied.is_empty() {
+                println!("Schema is up to date");
+            }
+            for version in applied {
+                println!("Applied migration {}", version);
+            }
+        },
+        MigrateAction::Status => {
+            for migration in db.migration_status().await? {
+                let state = if migration.applied { "applied" } else { "pending" };
+                println!("{:>4}  {:<8} {}", migration.version, state, migration.description);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+// Run a backfill in the foreground, resuming an unfinished job for the program
+pub async fn backfill(
+    db: &dyn DatabaseInterface,
+    solana_client: &SolanaConnection,
+    program_id: &str,
+    stop_slot: Option<u64>,
+) -> Result<(), Box<dyn Error>> {
+    let job = next_backfill_job(db, solana_client.cluster(), program_id, stop_slot).await?;
+    let job = run_backfill(solana_client, db, job).await?;
+
+    println!("Processed {} transactions of program {} on {}", job.processed, job.program_id, job.cluster);
+    match job.status {
+        BackfillStatus::Failed => Err(job.last_error.unwrap_or_else(|| "Backfill failed".to_string()).into()),
+        _ => Ok(()),
+    }
+}
+
+pub async fn import(
+    db: &dyn DatabaseInterface,
+    cluster: &str,
+    path: &Path,
+    program_id: Option<&str>,
+    user_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let idl: Idl = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let program_id = match program_id.or(idl.program_address()) {
+        Some(program_id) => program_id.to_string(),
+        None => return Err("The IDL has no program address; pass --program-id".into()),
+    };
+
+    let count = idl::ingest_idl(db, cluster, &program_id, &idl, user_id, "idl", false).await?;
+    println!("Imported {} discriminators for program {} on {}", count, program_id, cluster);
+    Ok(())
+}
+
+pub async fn seed(db: &dyn DatabaseInterface, clusters: &[&str]) -> Result<(), Box<dyn Error>> {
+    let catalogue = Catalogue::native_programs()?;
+    for cluster in clusters {
+        let count = seed_catalogue(db, cluster, &catalogue, "catalogue").await?;
+        println!("Seeded {} instructions from {} on {}", count, catalogue.source(), cluster);
+    }
+    Ok(())
+}
+
+pub async fn api_key(db: &dyn DatabaseInterface, action: ApiKeyAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        ApiKeyAction::Create { name, user, admin } => {
+            let user_id = user.unwrap_or_else(|| name.clone());
+            let api_key = generate_api_key();
+            db.create_api_key(&hash_api_key(&api_key), &name, &user_id, admin).await?;
+            println!("Created API key {} for user {}. Store it now, it cannot be shown again:", name, user_id);
+            println!("{}", api_key);
+        },
+        ApiKeyAction::Revoke { name } => {
+            let revoked = db.revoke_api_keys(&name).await?;
+            if revoked == 0 {
+                return Err(format!("No API key named {}", name).into());
+            }
+            println!("Revoked {} API key(s) named {}", revoked, name);
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use crate::solana_connection::{websocket_url, SolanaConnection, SolanaError};
+
+// Cluster indexed when none is configured, and of connections built without one
+pub const DEFAULT_CLUSTER: &str = "mainnet";
+
+// RPC and pubsub endpoints of the well-known clusters
+const KNOWN_CLUSTERS: [(&str, &str, &str); 4] = [
+    ("mainnet", "https://api.mainnet-beta.solana.com", "wss://api.mainnet-beta.solana.com"),
+    ("devnet", "https://api.devnet.solana.com", "wss://api.devnet.solana.com"),
+    ("testnet", "https://api.testnet.solana.com", "wss://api.testnet.solana.com"),
+    ("localnet", "http://127.0.0.1:8899", "ws://127.0.0.1:8900"),
+];
+
+// A Solana cluster the directory indexes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cluster {
+    pub name: String,
+    pub rpc_url: String,
+    pub ws_url: Option<String>,
+}
+
+impl Cluster {
+    pub fn new(name: &str, rpc_url: &str) -> Self {
+        Cluster {
+            name: name.to_string(),
+            rpc_url: rpc_url.to_string(),
+            ws_url: websocket_url(rpc_url),
+        }
+    }
+
+    // Parse a well-known cluster name (`mainnet`, `devnet`, `testnet`, `localnet`)
+    // or a custom cluster as `name=rpc_url`
+    pub fn parse(spec: &str) -> Result<Self, SolanaError> {
+        let spec = spec.trim();
+        match spec.split_once('=') {
+            Some((name, rpc_url)) => Self::custom(name, rpc_url),
+            None => Self::known(spec),
+        }
+    }
+
+    // A cluster with its own RPC endpoint
+    pub fn custom(name: &str, rpc_url: &str) -> Result<Self, SolanaError> {
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) || name.contains('=') {
+            return Err(SolanaError::ConfigError(format!("Invalid cluster name: {:?}", name)));
+        }
+        Ok(Self::new(name, rpc_url.trim()))
+    }
+
+    // One of the well-known clusters, with its public endpoints
+    pub fn known(name: &str) -> Result<Self, SolanaError> {
+        let name = match name.trim() {
+            "mainnet-beta" => "mainnet",
+            name => name,
+        };
+        KNOWN_CLUSTERS.iter()
+            .find(|(known, _, _)| *known == name)
+            .map(|(name, rpc_url, ws_url)| Cluster {
+                name: name.to_string(),
+                rpc_url: rpc_url.to_string(),
+                ws_url: Some(ws_url.to_string()),
+            })
+            .ok_or_else(|| SolanaError::ConfigError(format!("Unknown cluster {:?}; use name=rpc_url for a custom one", name)))
+    }
+
+    // Reject an empty list of clusters or a cluster configured twice
+    pub fn check_list(clusters: Vec<Self>) -> Result<Vec<Self>, SolanaError> {
+        if clusters.is_empty() {
+            return Err(SolanaError::ConfigError("No clusters configured".to_string()));
+        }
+        for (i, cluster) in clusters.iter().enumerate() {
+            if clusters[..i].iter().any(|other| other.name == cluster.name) {
+                return Err(SolanaError::ConfigError(format!("Cluster {} is configured twice", cluster.name)));
+            }
+        }
+        Ok(clusters)
+    }
+
+    pub fn connect(&self) -> SolanaConnection {
+        SolanaConnection::new(&self.rpc_url)
+            .with_ws_url(self.ws_url.clone())
+            .with_cluster(&self.name)
+    }
+}
+
+// Connections to every configured cluster. The first one answers requests without `?cluster=`.
+#[derive(Clone)]
+pub struct Clusters {
+    connections: Arc<Vec<SolanaConnection>>,
+}
+
+impl Clusters {
+    pub fn new(connections: Vec<SolanaConnection>) -> Self {
+        assert!(!connections.is_empty(), "at least one cluster must be configured");
+        Clusters { connections: Arc::new(connections) }
+    }
+
+    pub fn default_cluster(&self) -> &SolanaConnection {
+        &self.connections[0]
+    }
+
+    // The connection to a named cluster, or to the default one without a name
+    pub fn get(&self, name: Option<&str>) -> Option<&SolanaConnection> {
+        match name {
+            Some(name) => {
+                let name = if name == "mainnet-beta" { "mainnet" } else { name };
+                self.connections.iter().find(|connection| connection.cluster() == name)
+            },
+            None => Some(self.default_cluster()),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SolanaConnection> {
+        self.connections.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_and_custom_clusters() {
+        let clusters = ["mainnet-beta", " devnet", "local=http://localhost:8899"].map(|spec| Cluster::parse(spec).unwrap());
+
+        assert_eq!(clusters[0].name, "mainnet");
+        assert_eq!(clusters[0].ws_url.as_deref(), Some("wss://api.mainnet-beta.solana.com"));
+        assert_eq!(clusters[1].rpc_url, "https://api.devnet.solana.com");
+        assert_eq!(clusters[2], Cluster::new("local", "http://localhost:8899"));
+
+        assert_eq!(Cluster::parse("localnet").unwrap().ws_url.as_deref(), Some("ws://127.0.0.1:8900"));
+        assert!(Cluster::parse("solana").is_err());
+        assert!(Cluster::custom("my local", "http://localhost:8899").is_err());
+        assert!(Cluster::check_list(clusters.to_vec()).is_ok());
+        assert!(Cluster::check_list(vec![clusters[1].clone(), clusters[1].clone()]).is_err());
+        assert!(Cluster::check_list(vec![]).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use serde_json::{json, Map, Value};
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+use crate::idl::{ArgsLayout, IdlFields, IdlType, IdlTypeDef, IdlTypeDefTy};
+
+// Guards against self-referencing type definitions
+const MAX_DEPTH: usize = 64;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DecodeError {
+    #[error("Unexpected end of data at byte {offset}: needed {needed} bytes, {available} available")]
+    UnexpectedEnd { offset: usize, needed: usize, available: usize },
+
+    #[error("{remaining} trailing bytes after decoding at byte {offset}")]
+    TrailingBytes { offset: usize, remaining: usize },
+
+    #[error("Invalid {what} tag {tag} at byte {offset}")]
+    InvalidTag { what: String, tag: u32, offset: usize },
+
+    #[error("Invalid UTF-8 string at byte {offset}")]
+    InvalidUtf8 { offset: usize },
+
+    #[error("Unknown type: {0}")]
+    UnknownType(String),
+
+    #[error("Unsupported type: {0}")]
+    UnsupportedType(String),
+
+    #[error("Type nesting deeper than {0} levels")]
+    TooDeep(usize),
+}
+
+// Decode Borsh-encoded bytes (without the discriminator prefix) into named JSON fields.
+// u64/i64 are emitted as JSON numbers, u128/i128 as decimal strings,
+// byte blobs as hex and public keys as base58.
+pub fn decode_args(layout: &ArgsLayout, data: &[u8]) -> Result<Value, DecodeError> {
+    let (fields, offset) = decode_layout(layout, data)?;
+
+    if offset != data.len() {
+        return Err(DecodeError::TrailingBytes { offset, remaining: data.len() - offset });
+    }
+
+    Ok(fields)
+}
+
+// Same as `decode_args`, but accounts are often allocated larger than their
+// layout, so bytes left over after the last field are ignored
+pub fn decode_account_data(layout: &ArgsLayout, data: &[u8]) -> Result<Value, DecodeError> {
+    decode_layout(layout, data).map(|(fields, _)| fields)
+}
+
+fn decode_layout(layout: &ArgsLayout, data: &[u8]) -> Result<(Value, usize), DecodeError> {
+    let mut decoder = Decoder { data, offset: 0, types: &layout.types };
+
+    let mut fields = Map::new();
+    for field in &layout.fields {
+        let value = decoder.decode(&field.ty, 0)?;
+        fields.insert(field.name.clone(), value);
+    }
+
+    Ok((Value::Object(fields), decoder.offset))
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    offset: usize,
+    types: &'a [IdlTypeDef],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, needed: usize) -> Result<&'a [u8], DecodeError> {
+        let available = self.data.len() - self.offset;
+        if needed > available {
+            return Err(DecodeError::UnexpectedEnd { offset: self.offset, needed, available });
+        }
+
+        let bytes = &self.data[self.offset..self.offset + needed];
+        self.offset += needed;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    // Length prefix of a string, byte blob or vector
+    fn read_len(&mut self) -> Result<usize, DecodeError> {
+        let offset = self.offset;
+        let len = self.read_u32()? as usize;
+
+        // Every element takes at least one byte, so a longer length cannot be valid
+        let available = self.data.len() - self.offset;
+        if len > available {
+            return Err(DecodeError::UnexpectedEnd { offset, needed: len, available });
+        }
+        Ok(len)
+    }
+
+    fn read_tag(&mut self, what: &str) -> Result<bool, DecodeError> {
+        let offset = self.offset;
+        match self.take(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::InvalidTag { what: what.to_string(), tag: tag as u32, offset }),
+        }
+    }
+
+    fn decode(&mut self, ty: &IdlType, depth: usize) -> Result<Value, DecodeError> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::TooDeep(MAX_DEPTH));
+        }
+
+        match ty {
+            IdlType::Primitive(name) => self.decode_primitive(name),
+            IdlType::Option { option } => {
+                if self.read_tag("option")? {
+                    self.decode(option, depth + 1)
+                } else {
+                    Ok(Value::Null)
+                }
+            },
+            IdlType::COption { coption } => {
+                let offset = self.offset;
+                match self.read_u32()? {
+                    0 => Ok(Value::Null),
+                    1 => self.decode(coption, depth + 1),
+                    tag => Err(DecodeError::InvalidTag { what: "coption".to_string(), tag, offset }),
+                }
+            },
+            IdlType::Vec { vec } => {
+                let len = self.read_len()?;
+                let items = (0..len)
+                    .map(|_| self.decode(vec, depth + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Array(items))
+            },
+            IdlType::Array { array: (inner, len) } => {
+                let items = (0..*len)
+                    .map(|_| self.decode(inner, depth + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Array(items))
+            },
+            IdlType::Defined { defined } => {
+                let types = self.types;
+                let def = types.iter()
+                    .find(|def| def.name == defined.name())
+                    .ok_or_else(|| DecodeError::UnknownType(defined.name().to_string()))?;
+                self.decode_defined(def, depth + 1)
+            },
+            IdlType::Other(value) => Err(DecodeError::UnsupportedType(value.to_string())),
+        }
+    }
+
+    fn decode_primitive(&mut self, name: &str) -> Result<Value, DecodeError> {
+        let value = match name {
+            "bool" => Value::Bool(self.read_tag("bool")?),
+            "u8" => json!(self.take(1)?[0]),
+            "i8" => json!(self.take(1)?[0] as i8),
+            "u16" => json!(u16::from_le_bytes(self.take_array()?)),
+            "i16" => json!(i16::from_le_bytes(self.take_array()?)),
+            "u32" => json!(self.read_u32()?),
+            "i32" => json!(i32::from_le_bytes(self.take_array()?)),
+            "u64" => json!(u64::from_le_bytes(self.take_array()?)),
+            "i64" => json!(i64::from_le_bytes(self.take_array()?)),
+            "u128" => json!(u128::from_le_bytes(self.take_array()?).to_string()),
+            "i128" => json!(i128::from_le_bytes(self.take_array()?).to_string()),
+            "f32" => json!(f32::from_le_bytes(self.take_array()?)),
+            "f64" => json!(f64::from_le_bytes(self.take_array()?)),
+            "string" => {
+                let len = self.read_len()?;
+                let offset = self.offset;
+                let bytes = self.take(len)?;
+                let string = std::str::from_utf8(bytes)
+                    .map_err(|_| DecodeError::InvalidUtf8 { offset })?;
+                Value::String(string.to_string())
+            },
+            "bytes" => {
+                let len = self.read_len()?;
+                Value::String(hex::encode(self.take(len)?))
+            },
+            "publicKey" | "pubkey" => {
+                let bytes: [u8; 32] = self.take_array()?;
+                Value::String(Pubkey::new_from_array(bytes).to_string())
+            },
+            other => return Err(DecodeError::UnknownType(other.to_string())),
+        };
+
+        Ok(value)
+    }
+
+    fn decode_defined(&mut self, def: &IdlTypeDef, depth: usize) -> Result<Value, DecodeError> {
+        match &def.ty {
+            IdlTypeDefTy::Struct { fields } => self.decode_fields(fields.as_ref(), depth),
+            IdlTypeDefTy::Enum { variants } => {
+                let offset = self.offset;
+                let tag = self.take(1)?[0];
+                let variant = variants.get(tag as usize).ok_or_else(|| DecodeError::InvalidTag {
+                    what: format!("{} variant", def.name),
+                    tag: tag as u32,
+                    offset,
+                })?;
+
+                match &variant.fields {
+                    None => Ok(Value::String(variant.name.clone())),
+                    Some(fields) => {
+                        let mut object = Map::new();
+                        object.insert(variant.name.clone(), self.decode_fields(Some(fields), depth)?);
+                        Ok(Value::Object(object))
+                    }
+                }
+            },
+            IdlTypeDefTy::Alias { value } => self.decode(value, depth),
+            IdlTypeDefTy::Unsupported => Err(DecodeError::UnsupportedType(def.name.clone())),
+        }
+    }
+
+    // Named fields become an object, positional fields an array
+    fn decode_fields(&mut self, fields: Option<&IdlFields>, depth: usize) -> Result<Value, DecodeError> {
+        match fields {
+            None => Ok(Value::Object(Map::new())),
+            Some(IdlFields::Named(fields)) => {
+                let mut object = Map::new();
+                for field in fields {
+                    object.insert(field.name.clone(), self.decode(&field.ty, depth)?);
+                }
+                Ok(Value::Object(object))
+            },
+            Some(IdlFields::Tuple(types)) => {
+                let items = types.iter()
+                    .map(|ty| self.decode(ty, depth))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Array(items))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(json: &str) -> ArgsLayout {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn decodes_nested_types() {
+        let layout = layout(r#"{
+            "fields": [
+                { "name": "amount", "type": "u64" },
+                { "name": "owner", "type": "publicKey" },
+                { "name": "memo", "type": { "option": "string" } },
+                { "name": "side", "type": { "defined": "Side" } },
+                { "name": "legs", "type": { "vec": { "defined": { "name": "Leg" } } } }
+            ],
+            "types": [
+                { "name": "Side", "type": { "kind": "enum", "variants": [{ "name": "Bid" }, { "name": "Ask" }] } },
+                { "name": "Leg", "type": { "kind": "struct", "fields": [{ "name": "price", "type": "i32" }] } }
+            ]
+        }"#);
+
+        let owner = Pubkey::new_unique();
+        let mut data = 42u64.to_le_bytes().to_vec();
+        data.extend_from_slice(owner.as_ref());
+        data.extend_from_slice(&[1, 2, 0, 0, 0, b'h', b'i']);
+        data.push(1);
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&(-7i32).to_le_bytes());
+
+        assert_eq!(decode_args(&layout, &data).unwrap(), json!({
+            "amount": 42,
+            "owner": owner.to_string(),
+            "memo": "hi",
+            "side": "Ask",
+            "legs": [{ "price": -7 }],
+        }));
+    }
+
+    #[test]
+    fn reports_mismatched_bytes() {
+        let layout = layout(r#"{ "fields": [{ "name": "amount", "type": "u64" }] }"#);
+
+        assert_eq!(
+            decode_args(&layout, &[1, 2, 3]),
+            Err(DecodeError::UnexpectedEnd { offset: 0, needed: 8, available: 3 })
+        );
+        assert_eq!(
+            decode_args(&layout, &[0; 10]),
+            Err(DecodeError::TrailingBytes { offset: 8, remaining: 2 })
+        );
+    }
+
+    #[test]
+    fn ignores_unused_account_space() {
+        let layout = layout(r#"{ "fields": [{ "name": "count", "type": "u64" }] }"#);
+        let mut data = 42u64.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0; 16]);
+
+        // Accounts are allocated with room to grow, instruction data is not
+        assert_eq!(decode_account_data(&layout, &data).unwrap(), json!({ "count": 42 }));
+        assert_eq!(decode_args(&layout, &data), Err(DecodeError::TrailingBytes { offset: 8, remaining: 16 }));
+        assert_eq!(
+            decode_account_data(&layout, &[1, 2, 3]),
+            Err(DecodeError::UnexpectedEnd { offset: 0, needed: 8, available: 3 })
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::debug;
+
+// Tag Anchor prefixes to the self-CPI instructions emitted by `emit_cpi!`
+pub const EVENT_IX_TAG: [u8; 8] = [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d];
+
+const LOG_DATA_PREFIX: &str = "Program data: ";
+
+// An Anchor event found in a transaction, split into discriminator and payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmittedEvent {
+    pub program_id: String,
+    pub discriminator_data: Vec<u8>,
+    pub event_data: Vec<u8>,
+}
+
+impl EmittedEvent {
+    fn from_bytes(program_id: &str, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None;
+        }
+
+        Some(EmittedEvent {
+            program_id: program_id.to_string(),
+            discriminator_data: bytes[0..8].to_vec(),
+            event_data: bytes[8..].to_vec(),
+        })
+    }
+}
+
+// Extract `emit!` events from transaction logs. `Program data:` lines are attributed
+// to the program at the top of the invocation stack rebuilt from invoke/success/failed lines.
+pub fn events_from_logs(logs: &[String]) -> Vec<EmittedEvent> {
+    let mut stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        if let Some(encoded) = line.strip_prefix(LOG_DATA_PREFIX) {
+            let Some(program_id) = stack.last() else {
+                debug!("Program data outside of any invocation: {}", line);
+                continue;
+            };
+
+            // Anchor logs the whole event as a single base64 chunk
+            let chunk = encoded.split_whitespace().next().unwrap_or_default();
+            match STANDARD.decode(chunk) {
+                Ok(bytes) => events.extend(EmittedEvent::from_bytes(program_id, &bytes)),
+                Err(e) => debug!("Skipping undecodable program data from {}: {}", program_id, e),
+            }
+            continue;
+        }
+
+        let Some(rest) = line.strip_prefix("Program ") else {
+            continue;
+        };
+        let mut parts = rest.split_whitespace();
+        let (Some(program_id), Some(action)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+
+        // "Program log:", "Program return:" and the like carry no invocation
+        if program_id.ends_with(':') {
+            continue;
+        }
+
+        if action == "invoke" {
+            stack.push(program_id);
+        } else if action == "success" || action.starts_with("failed") {
+            stack.pop();
+        }
+    }
+
+    events
+}
+
+// Extract an `emit_cpi!` event from the data of an instruction the program invoked on itself
+pub fn event_from_cpi_data(program_id: &str, data: &[u8]) -> Option<EmittedEvent> {
+    let payload = data.strip_prefix(&EVENT_IX_TAG[..])?;
+    EmittedEvent::from_bytes(program_id, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_logged_events_to_the_invoking_program() {
+        let mut payload = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        payload.extend_from_slice(&[42, 0]);
+
+        let logs: Vec<String> = [
+            "Program Outer111 invoke [1]".to_string(),
+            "Program log: Instruction: Swap".to_string(),
+            "Program Inner222 invoke [2]".to_string(),
+            format!("Program data: {}", STANDARD.encode(&payload)),
+            "Program Inner222 success".to_string(),
+            format!("Program data: {}", STANDARD.encode(&payload)),
+            "Program Outer111 consumed 5000 of 200000 compute units".to_string(),
+            "Program Outer111 success".to_string(),
+        ].into();
+
+        let events = events_from_logs(&logs);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].program_id, "Inner222");
+        assert_eq!(events[1].program_id, "Outer111");
+        assert_eq!(events[1].discriminator_data, payload[..8].to_vec());
+        assert_eq!(events[1].event_data, vec![42, 0]);
+    }
+
+    #[test]
+    fn extracts_self_cpi_events() {
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend_from_slice(&[9; 8]);
+        data.push(1);
+
+        let event = event_from_cpi_data("Program333", &data).unwrap();
+        assert_eq!(event.discriminator_data, vec![9; 8]);
+        assert_eq!(event.event_data, vec![1]);
+        assert_eq!(event_from_cpi_data("Program333", &[9; 16]), None);
+    }
+}
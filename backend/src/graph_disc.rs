@@ -1,19 +1,25 @@
 use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
-use log::{info, error, debug};
+use log::{info, debug, warn};
 use thiserror::Error;
 use sha2::{Digest, Sha256};
-use sqlx::{PgPool, postgres::PgPoolOptions, Row};
+use sqlx::{Connection, Executor, PgPool, postgres::{PgConnection, PgPoolOptions, PgRow}, Row, types::Json};
+use sqlx::migrate::{Migration, Migrator};
 
 use async_trait::async_trait;
 
-// Structs for representing data in PostgreSQL
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Program {
-    pub id: String,
-}
+use crate::idl::ArgsLayout;
+use crate::scheme::DiscriminatorScheme;
+use crate::migrations::{apply_pending_migrations, AppliedMigration, SchemaMigrations, SchemaStatus, POSTGRES_MIGRATIONS};
+use crate::status::{DatabaseHealth, PoolStatus};
 
+// Key of the advisory lock held while migrating the schema ("dd-schem" in ASCII)
+const MIGRATION_LOCK_KEY: i64 = 0x6464_2d73_6368_656d;
+
+// Structs for representing data in PostgreSQL
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Discriminator {
     pub id: String,
@@ -21,7 +27,78 @@ pub struct Discriminator {
     pub discriminator_data: Vec<u8>,
     pub instruction: Instruction,
     pub user_id: String,
+    pub cluster: String,
     pub program_id: String,
+    pub name: Option<String>,
+    pub kind: DiscriminatorKind,
+    pub source: Option<String>,
+    // Uploaded by the program's verified upgrade authority; listed before community labels
+    pub authoritative: bool,
+    pub args_layout: Option<ArgsLayout>,
+    pub invocation: Option<Invocation>,
+}
+
+// Where in a transaction an instruction discriminator was last observed:
+// as a top-level instruction or through CPI, and at which stack height (1 = top level)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Invocation {
+    pub cpi: bool,
+    pub stack_height: Option<u32>,
+}
+
+// What the discriminator prefixes: instruction data, account data or an event
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscriminatorKind {
+    Instruction,
+    Account,
+    Event,
+    #[default]
+    Unknown,
+}
+
+impl DiscriminatorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscriminatorKind::Instruction => "instruction",
+            DiscriminatorKind::Account => "account",
+            DiscriminatorKind::Event => "event",
+            DiscriminatorKind::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for DiscriminatorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DiscriminatorKind {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "instruction" => Ok(DiscriminatorKind::Instruction),
+            "account" => Ok(DiscriminatorKind::Account),
+            "event" => Ok(DiscriminatorKind::Event),
+            "unknown" => Ok(DiscriminatorKind::Unknown),
+            other => Err(DatabaseError::DataParsingError(format!("Unknown discriminator kind: {}", other))),
+        }
+    }
+}
+
+// Optional descriptive data stored alongside a discriminator
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DiscriminatorMetadata {
+    pub name: Option<String>,
+    pub kind: DiscriminatorKind,
+    pub source: Option<String>,
+    // Set for uploads from the program's verified upgrade authority
+    #[serde(default)]
+    pub authoritative: bool,
+    pub args_layout: Option<ArgsLayout>,
+    pub invocation: Option<Invocation>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -31,9 +108,76 @@ pub struct Instruction {
     pub instruction_data: Vec<u8>,
 }
 
+// Stored instruction data together with what is known about its discriminator
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct User {
-    pub id: String,
+pub struct InstructionRecord {
+    pub program_id: String,
+    pub discriminator_id: String,
+    pub name: Option<String>,
+    pub instruction_data: Vec<u8>,
+    pub args_layout: Option<ArgsLayout>,
+}
+
+// Newest signature the listener has processed for a program
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ProgramCursor {
+    pub cluster: String,
+    pub program_id: String,
+    pub signature: String,
+    pub slot: u64,
+}
+
+// Progress of a historical backfill, which walks a program's signatures from newest to oldest
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackfillJob {
+    pub cluster: String,
+    pub program_id: String,
+    pub status: BackfillStatus,
+    // Stop once signatures older than this slot are reached; None walks back to genesis
+    pub stop_slot: Option<u64>,
+    // Oldest signature processed so far, where the walk resumes
+    pub before_signature: Option<String>,
+    pub last_slot: Option<u64>,
+    pub processed: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackfillStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+impl BackfillStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackfillStatus::Running => "running",
+            BackfillStatus::Completed => "completed",
+            BackfillStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for BackfillStatus {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(BackfillStatus::Running),
+            "completed" => Ok(BackfillStatus::Completed),
+            "failed" => Ok(BackfillStatus::Failed),
+            other => Err(DatabaseError::DataParsingError(format!("Unknown backfill status: {}", other))),
+        }
+    }
+}
+
+// An API key as looked up by its hash. Admin keys may also use the /sync_idls and /admin routes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub user_id: String,
+    pub admin: bool,
 }
 
 // Custom error type to handle database-related errors
@@ -53,25 +197,109 @@ pub enum DatabaseError {
     
     #[error("Transaction error: {0}")]
     TransactionError(String),
+    
+    #[error("Not found: {0}")]
+    NotFound(String),
+    
+    #[error("Migration error: {0}")]
+    MigrationError(String),
+
+    #[error("Database not available")]
+    Unavailable,
 }
 
-// Database interface trait for testing
+// Database interface trait for testing.
+// Programs are catalogued per cluster, so every lookup names the cluster it is about.
+#[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait DatabaseInterface: Send + Sync {
     async fn upload_discriminator(
         &self,
+        cluster: &str,
         program_id: &str,
         discriminator_data: Vec<u8>,
         instruction_data: Vec<u8>,
         user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError>;
+
+    // Name, classify or lay out a discriminator without touching the instruction data seen
+    // with it. A discriminator not seen yet is stored with empty instruction data.
+    async fn set_discriminator_metadata(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
     ) -> Result<(), DatabaseError>;
     
-    async fn query_discriminators_and_instructions(&self, program_id: &str) -> Result<Vec<Discriminator>, DatabaseError>;
+    // `kind` restricts the results to one kind of discriminator when set
+    async fn query_discriminators_and_instructions(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<Discriminator>, DatabaseError>;
     
-    async fn get_all_program_ids(&self) -> Result<Vec<String>, DatabaseError>;
+    async fn get_all_program_ids(&self, cluster: &str) -> Result<Vec<String>, DatabaseError>;
 
     // Add new method for querying instructions by discriminator ID
-    async fn query_instructions_by_discriminator(&self, discriminator_id: &str) -> Result<Vec<String>, DatabaseError>;
+    async fn query_instructions_by_discriminator(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<String>, DatabaseError>;
+
+    async fn get_discriminator(&self, cluster: &str, program_id: &str, discriminator_id: &str) -> Result<Option<Discriminator>, DatabaseError>;
+
+    // Instruction data with the name and layout of its discriminator, for decoding
+    async fn query_instruction_records(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<InstructionRecord>, DatabaseError>;
+
+    async fn get_program_cursor(&self, cluster: &str, program_id: &str) -> Result<Option<ProgramCursor>, DatabaseError>;
+
+    async fn update_program_cursor(&self, cluster: &str, program_id: &str, signature: &str, slot: u64) -> Result<(), DatabaseError>;
+
+    async fn get_backfill_job(&self, cluster: &str, program_id: &str) -> Result<Option<BackfillJob>, DatabaseError>;
+
+    // Jobs that were running when the process last stopped, on every cluster
+    async fn get_running_backfill_jobs(&self) -> Result<Vec<BackfillJob>, DatabaseError>;
+
+    async fn save_backfill_job(&self, job: &BackfillJob) -> Result<(), DatabaseError>;
+
+    // Attach a user-submitted argument layout to an existing discriminator
+    async fn set_args_layout(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_id: &str,
+        args_layout: ArgsLayout,
+    ) -> Result<(), DatabaseError>;
+
+    // How the program's discriminators are encoded, which uploads are validated against
+    async fn get_discriminator_scheme(&self, cluster: &str, program_id: &str) -> Result<DiscriminatorScheme, DatabaseError>;
+
+    async fn set_discriminator_scheme(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        scheme: DiscriminatorScheme,
+    ) -> Result<(), DatabaseError>;
+
+    // Register the SHA-256 of an API key, attributing its uploads to `user_id`
+    async fn create_api_key(&self, key_hash: &str, name: &str, user_id: &str, admin: bool) -> Result<(), DatabaseError>;
+
+    // The user an API key belongs to, and its scope
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, DatabaseError>;
+
+    // Delete every key with this name; returns how many there were
+    async fn revoke_api_keys(&self, name: &str) -> Result<u64, DatabaseError>;
 }
 
 // Struct for interacting with the PostgreSQL database
@@ -92,138 +320,67 @@ impl Clone for GraphDatabase {
 impl DatabaseInterface for GraphDatabase {
     async fn upload_discriminator(
         &self,
+        cluster: &str,
         program_id: &str,
         discriminator_data: Vec<u8>,
         instruction_data: Vec<u8>,
         user_id: &str,
+        metadata: DiscriminatorMetadata,
     ) -> Result<(), DatabaseError> {
-        debug!("Uploading discriminator for program {}", program_id);
-        
-        if discriminator_data.len() != 8 {
-            return Err(DatabaseError::DataParsingError(format!(
-                "Invalid discriminator data length: {}, expected 8 bytes", 
-                discriminator_data.len()
-            )));
-        }
+        debug!("Uploading discriminator for program {} on {}", program_id, cluster);
+        self.upsert_discriminator(cluster, program_id, discriminator_data, Some(instruction_data), user_id, metadata).await
+    }
 
-        let discriminator_id = hex::encode(&discriminator_data);
-        let instruction_id = hex::encode(&instruction_data);
-        
-        // Create a transaction so we can rollback if any part fails
-        let mut tx = self.pool.begin().await
-            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
-        
-        // Insert or update program
-        sqlx::query(r#"
-            INSERT INTO programs (id) 
-            VALUES ($1)
-            ON CONFLICT (id) DO NOTHING
-        "#)
-        .bind(program_id)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
-        
-        // Insert or update user
-        sqlx::query(r#"
-            INSERT INTO users (id) 
-            VALUES ($1)
-            ON CONFLICT (id) DO NOTHING
-        "#)
-        .bind(user_id)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
-        
-        // Generate unique IDs for discriminator and instruction
-        let discriminator_unique_id = format!("{}_{}", program_id, discriminator_id);
-        let instruction_unique_id = format!("{}_{}", program_id, Self::hash_key(&instruction_id));
-        
-        // Insert or update instruction
-        sqlx::query(r#"
-            INSERT INTO instructions (id, instruction_id, instruction_data) 
-            VALUES ($1, $2, $3)
-            ON CONFLICT (id) DO UPDATE 
-            SET instruction_id = EXCLUDED.instruction_id,
-                instruction_data = EXCLUDED.instruction_data
-        "#)
-        .bind(&instruction_unique_id)
-        .bind(&instruction_id)
-        .bind(&instruction_data)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
-        
-        // Insert or update discriminator with relationships
-        sqlx::query(r#"
-            INSERT INTO discriminators (id, discriminator_id, discriminator_data, instruction_id, user_id, program_id) 
-            VALUES ($1, $2, $3, $4, $5, $6)
-            ON CONFLICT (id) DO UPDATE 
-            SET discriminator_id = EXCLUDED.discriminator_id,
-                discriminator_data = EXCLUDED.discriminator_data,
-                instruction_id = EXCLUDED.instruction_id,
-                user_id = EXCLUDED.user_id
-        "#)
-        .bind(&discriminator_unique_id)
-        .bind(&discriminator_id)
-        .bind(&discriminator_data)
-        .bind(&instruction_unique_id)
-        .bind(user_id)
-        .bind(program_id)
-        .execute(&mut *tx)
-        .await
-        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
-        
-        // Commit the transaction
-        tx.commit().await
-            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
-        
-        info!("Successfully uploaded discriminator {} for program {}", discriminator_id, program_id);
-        Ok(())
+    async fn set_discriminator_metadata(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        debug!("Setting metadata of discriminator for program {} on {}", program_id, cluster);
+        self.upsert_discriminator(cluster, program_id, discriminator_data, None, user_id, metadata).await
     }
     
-    async fn query_discriminators_and_instructions(&self, program_id: &str) -> Result<Vec<Discriminator>, DatabaseError> {
+    async fn query_discriminators_and_instructions(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<Discriminator>, DatabaseError> {
         debug!("Querying discriminators for program {}", program_id);
         
         let rows = sqlx::query(r#"
-            SELECT d.id, d.discriminator_id, d.discriminator_data, d.program_id, d.user_id,
+            SELECT d.id, d.discriminator_id, d.discriminator_data, d.cluster, d.program_id, d.user_id,
+                   d.name, d.kind, d.source, d.args_layout, d.via_cpi, d.stack_height, d.authoritative,
                    i.id as instruction_id, i.instruction_id as instruction_id_value, i.instruction_data
             FROM discriminators d
             JOIN instructions i ON d.instruction_id = i.id
-            WHERE d.program_id = $1
+            WHERE d.cluster = $1 AND d.program_id = $2
+              AND ($3::TEXT IS NULL OR d.kind = $3)
+            ORDER BY d.authoritative DESC, d.discriminator_id
         "#)
+        .bind(cluster)
         .bind(program_id)
+        .bind(kind.map(|kind| kind.as_str()))
         .fetch_all(&self.pool)
         .await
         .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
         
-        let mut discriminators = Vec::new();
-        
-        for row in rows {
-            let discriminator = Discriminator {
-                id: row.get("id"),
-                discriminator_id: row.get("discriminator_id"),
-                discriminator_data: row.get("discriminator_data"),
-                program_id: row.get("program_id"),
-                user_id: row.get("user_id"),
-                instruction: Instruction {
-                    id: row.get("instruction_id"),
-                    instruction_id: row.get("instruction_id_value"),
-                    instruction_data: row.get("instruction_data"),
-                },
-            };
-            
-            discriminators.push(discriminator);
-        }
+        let discriminators = rows.iter()
+            .map(Self::discriminator_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
         
         info!("Found {} discriminators for program {}", discriminators.len(), program_id);
         Ok(discriminators)
     }
     
-    async fn get_all_program_ids(&self) -> Result<Vec<String>, DatabaseError> {
-        debug!("Fetching all program IDs");
+    async fn get_all_program_ids(&self, cluster: &str) -> Result<Vec<String>, DatabaseError> {
+        debug!("Fetching all program IDs on {}", cluster);
         
-        let rows = sqlx::query("SELECT id FROM programs")
+        let rows = sqlx::query("SELECT id FROM programs WHERE cluster = $1")
+            .bind(cluster)
             .fetch_all(&self.pool)
             .await
             .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
@@ -236,16 +393,24 @@ impl DatabaseInterface for GraphDatabase {
         Ok(program_ids)
     }
 
-    async fn query_instructions_by_discriminator(&self, discriminator_id: &str) -> Result<Vec<String>, DatabaseError> {
+    async fn query_instructions_by_discriminator(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<String>, DatabaseError> {
         debug!("Querying instructions for discriminator {}", discriminator_id);
         
         let rows = sqlx::query(r#"
             SELECT i.instruction_data 
             FROM instructions i
             JOIN discriminators d ON i.id = d.instruction_id
-            WHERE d.discriminator_id = $1
+            WHERE d.cluster = $1 AND d.discriminator_id = $2
+              AND ($3::TEXT IS NULL OR d.kind = $3)
         "#)
+        .bind(cluster)
         .bind(discriminator_id)
+        .bind(kind.map(|kind| kind.as_str()))
         .fetch_all(&self.pool)
         .await
         .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
@@ -260,86 +425,589 @@ impl DatabaseInterface for GraphDatabase {
         info!("Found {} instructions for discriminator {}", instructions.len(), discriminator_id);
         Ok(instructions)
     }
+
+    async fn get_discriminator(&self, cluster: &str, program_id: &str, discriminator_id: &str) -> Result<Option<Discriminator>, DatabaseError> {
+        debug!("Fetching discriminator {} for program {}", discriminator_id, program_id);
+        
+        let row = sqlx::query(r#"
+            SELECT d.id, d.discriminator_id, d.discriminator_data, d.cluster, d.program_id, d.user_id,
+                   d.name, d.kind, d.source, d.args_layout, d.via_cpi, d.stack_height, d.authoritative,
+                   i.id as instruction_id, i.instruction_id as instruction_id_value, i.instruction_data
+            FROM discriminators d
+            JOIN instructions i ON d.instruction_id = i.id
+            WHERE d.cluster = $1 AND d.program_id = $2 AND d.discriminator_id = $3
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .bind(discriminator_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        
+        row.as_ref().map(Self::discriminator_from_row).transpose()
+    }
+
+    async fn query_instruction_records(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<InstructionRecord>, DatabaseError> {
+        debug!("Querying instruction records for discriminator {}", discriminator_id);
+        
+        let rows = sqlx::query(r#"
+            SELECT d.program_id, d.discriminator_id, d.name, d.args_layout, i.instruction_data
+            FROM instructions i
+            JOIN discriminators d ON i.id = d.instruction_id
+            WHERE d.cluster = $1 AND d.discriminator_id = $2
+              AND ($3::TEXT IS NULL OR d.kind = $3)
+        "#)
+        .bind(cluster)
+        .bind(discriminator_id)
+        .bind(kind.map(|kind| kind.as_str()))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        
+        let records: Vec<InstructionRecord> = rows.iter()
+            .map(|row| {
+                let args_layout: Option<Json<ArgsLayout>> = row.get("args_layout");
+                InstructionRecord {
+                    program_id: row.get("program_id"),
+                    discriminator_id: row.get("discriminator_id"),
+                    name: row.get("name"),
+                    instruction_data: row.get("instruction_data"),
+                    args_layout: args_layout.map(|layout| layout.0),
+                }
+            })
+            .collect();
+            
+        info!("Found {} instruction records for discriminator {}", records.len(), discriminator_id);
+        Ok(records)
+    }
+
+    async fn get_program_cursor(&self, cluster: &str, program_id: &str) -> Result<Option<ProgramCursor>, DatabaseError> {
+        debug!("Fetching signature cursor for program {} on {}", program_id, cluster);
+        
+        let row = sqlx::query("SELECT signature, slot FROM program_cursors WHERE cluster = $1 AND program_id = $2")
+            .bind(cluster)
+            .bind(program_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        
+        Ok(row.map(|row| {
+            let slot: i64 = row.get("slot");
+            ProgramCursor {
+                cluster: cluster.to_string(),
+                program_id: program_id.to_string(),
+                signature: row.get("signature"),
+                slot: slot as u64,
+            }
+        }))
+    }
+
+    async fn update_program_cursor(&self, cluster: &str, program_id: &str, signature: &str, slot: u64) -> Result<(), DatabaseError> {
+        debug!("Advancing signature cursor for program {} on {} to {}", program_id, cluster, signature);
+        
+        sqlx::query(r#"
+            INSERT INTO program_cursors (cluster, program_id, signature, slot, updated_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            ON CONFLICT (cluster, program_id) DO UPDATE
+            SET signature = EXCLUDED.signature,
+                slot = EXCLUDED.slot,
+                updated_at = EXCLUDED.updated_at
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .bind(signature)
+        .bind(slot as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+        
+        Ok(())
+    }
+
+    async fn get_backfill_job(&self, cluster: &str, program_id: &str) -> Result<Option<BackfillJob>, DatabaseError> {
+        debug!("Fetching backfill job for program {} on {}", program_id, cluster);
+        
+        let row = sqlx::query(r#"
+            SELECT cluster, program_id, status, stop_slot, before_signature, last_slot, processed, last_error
+            FROM backfill_jobs
+            WHERE cluster = $1 AND program_id = $2
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        
+        row.as_ref().map(Self::backfill_job_from_row).transpose()
+    }
+
+    async fn get_running_backfill_jobs(&self) -> Result<Vec<BackfillJob>, DatabaseError> {
+        debug!("Fetching running backfill jobs");
+        
+        let rows = sqlx::query(r#"
+            SELECT cluster, program_id, status, stop_slot, before_signature, last_slot, processed, last_error
+            FROM backfill_jobs
+            WHERE status = 'running'
+        "#)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        
+        rows.iter().map(Self::backfill_job_from_row).collect()
+    }
+
+    async fn save_backfill_job(&self, job: &BackfillJob) -> Result<(), DatabaseError> {
+        debug!("Saving backfill job for program {} ({} processed)", job.program_id, job.processed);
+        
+        sqlx::query(r#"
+            INSERT INTO backfill_jobs (program_id, status, stop_slot, before_signature, last_slot, processed, last_error, cluster, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+            ON CONFLICT (cluster, program_id) DO UPDATE
+            SET status = EXCLUDED.status,
+                stop_slot = EXCLUDED.stop_slot,
+                before_signature = EXCLUDED.before_signature,
+                last_slot = EXCLUDED.last_slot,
+                processed = EXCLUDED.processed,
+                last_error = EXCLUDED.last_error,
+                updated_at = EXCLUDED.updated_at
+        "#)
+        .bind(&job.program_id)
+        .bind(job.status.as_str())
+        .bind(job.stop_slot.map(|slot| slot as i64))
+        .bind(&job.before_signature)
+        .bind(job.last_slot.map(|slot| slot as i64))
+        .bind(job.processed as i64)
+        .bind(&job.last_error)
+        .bind(&job.cluster)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+        
+        Ok(())
+    }
+
+    async fn set_args_layout(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_id: &str,
+        args_layout: ArgsLayout,
+    ) -> Result<(), DatabaseError> {
+        debug!("Setting args layout for discriminator {} of program {}", discriminator_id, program_id);
+        
+        let result = sqlx::query(r#"
+            UPDATE discriminators
+            SET args_layout = $1
+            WHERE cluster = $2 AND program_id = $3 AND discriminator_id = $4
+        "#)
+        .bind(Json(args_layout))
+        .bind(cluster)
+        .bind(program_id)
+        .bind(discriminator_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+        
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound(format!(
+                "discriminator {} for program {} on {}", discriminator_id, program_id, cluster
+            )));
+        }
+        
+        info!("Stored args layout for discriminator {} of program {}", discriminator_id, program_id);
+        Ok(())
+    }
+    
+    async fn get_discriminator_scheme(&self, cluster: &str, program_id: &str) -> Result<DiscriminatorScheme, DatabaseError> {
+        let stored: Option<Option<String>> = sqlx::query_scalar(
+            "SELECT discriminator_scheme FROM programs WHERE cluster = $1 AND id = $2"
+        )
+        .bind(cluster)
+        .bind(program_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        
+        let stored = stored.flatten().map(|scheme| scheme.parse()).transpose()?;
+        Ok(DiscriminatorScheme::for_program(program_id, stored))
+    }
+    
+    async fn set_discriminator_scheme(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        scheme: DiscriminatorScheme,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(r#"
+            INSERT INTO programs (cluster, id, discriminator_scheme)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (cluster, id) DO UPDATE
+            SET discriminator_scheme = EXCLUDED.discriminator_scheme
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .bind(scheme.as_str())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+        
+        info!("Set discriminator scheme of program {} on {} to {}", program_id, cluster, scheme);
+        Ok(())
+    }
+    
+    async fn create_api_key(&self, key_hash: &str, name: &str, user_id: &str, admin: bool) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+        
+        sqlx::query("INSERT INTO users (id) VALUES ($1) ON CONFLICT (id) DO NOTHING")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+        
+        sqlx::query("INSERT INTO api_keys (key_hash, name, user_id, admin) VALUES ($1, $2, $3, $4)")
+            .bind(key_hash)
+            .bind(name)
+            .bind(user_id)
+            .bind(admin)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+        
+        tx.commit().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+        
+        info!("Created {} API key {} for user {}", if admin { "admin" } else { "upload" }, name, user_id);
+        Ok(())
+    }
+    
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, DatabaseError> {
+        let row = sqlx::query("SELECT user_id, admin FROM api_keys WHERE key_hash = $1")
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        Ok(row.map(|row| ApiKey {
+            user_id: row.get("user_id"),
+            admin: row.get("admin"),
+        }))
+    }
+    
+    async fn revoke_api_keys(&self, name: &str) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        
+        info!("Revoked {} API keys named {}", result.rows_affected(), name);
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl SchemaMigrations for GraphDatabase {
+    fn migrator(&self) -> &'static Migrator {
+        &POSTGRES_MIGRATIONS
+    }
+    
+    async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, DatabaseError> {
+        sqlx::query(r#"
+            CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY,
+                description TEXT NOT NULL,
+                checksum BYTEA NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )
+        "#)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+        
+        let rows = sqlx::query("SELECT version, checksum FROM schema_migrations ORDER BY version")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+        
+        Ok(rows.iter()
+            .map(|row| AppliedMigration {
+                version: row.get("version"),
+                checksum: row.get("checksum"),
+            })
+            .collect())
+    }
+    
+    async fn apply_migration(&self, migration: &Migration) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+        
+        // Migration files hold several statements, so they run as a simple query
+        tx.execute(&*migration.sql).await
+            .map_err(|e| DatabaseError::MigrationError(format!("migration {} failed: {}", migration.version, e)))?;
+        
+        sqlx::query("INSERT INTO schema_migrations (version, description, checksum) VALUES ($1, $2, $3)")
+            .bind(migration.version)
+            .bind(&*migration.description)
+            .bind(&*migration.checksum)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+        
+        tx.commit().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))
+    }
+    
+    // Replicas starting together would plan the same migrations, and all but one would fail
+    // to record them. An advisory lock makes the others wait, then re-read `schema_migrations`
+    // and find nothing left to apply. It is held on a connection of its own, outside the pool,
+    // so a pool of one connection can still run the migrations.
+    async fn run_migrations(&self) -> Result<Vec<i64>, DatabaseError> {
+        let mut lock = PgConnection::connect_with(&self.pool.connect_options()).await
+            .map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+        sqlx::query("SELECT pg_advisory_lock($1)")
+            .bind(MIGRATION_LOCK_KEY)
+            .execute(&mut lock)
+            .await
+            .map_err(|e| DatabaseError::MigrationError(format!("failed to lock the schema: {}", e)))?;
+        debug!("Locked the schema for migration");
+        
+        let applied = apply_pending_migrations(self).await;
+        
+        // Closing the session releases the lock
+        if let Err(e) = lock.close().await {
+            warn!("Failed to close the schema lock connection: {}", e);
+        }
+        applied
+    }
+}
+
+#[async_trait]
+impl DatabaseHealth for GraphDatabase {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+    
+    fn pool_status(&self) -> Option<PoolStatus> {
+        Some(PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+            max_connections: self.pool.options().get_max_connections(),
+        })
+    }
+    
+    async fn ping(&self) -> Result<(), DatabaseError> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        Ok(())
+    }
+    
+    async fn schema_status(&self) -> Result<Option<SchemaStatus>, DatabaseError> {
+        SchemaMigrations::schema_status(self).await.map(Some)
+    }
 }
 
 impl GraphDatabase {
     // Function to initialize a new GraphDatabase instance
-    pub async fn new(database_url: &str) -> Result<Self, Box<dyn Error>> {
+    pub async fn new(database_url: &str, max_connections: u32) -> Result<Self, Box<dyn Error>> {
+        let db = Self::connect(database_url, max_connections).await?;
+        
+        // Bring the schema up to date, refusing to run against one this build does not know
+        let applied = db.run_migrations().await?;
+        if !applied.is_empty() {
+            info!("Applied schema migrations {:?}", applied);
+        }
+        
+        info!("Successfully initialized database connection");
+        
+        Ok(db)
+    }
+    
+    // Connect without touching the schema
+    pub async fn connect(database_url: &str, max_connections: u32) -> Result<Self, Box<dyn Error>> {
         info!("Connecting to PostgreSQL at {}", database_url);
         
         // Create a connection pool
         let pool = PgPoolOptions::new()
-            .max_connections(5)
+            .max_connections(max_connections)
             .connect(database_url)
             .await?;
         
-        // Initialize database schema if needed
-        Self::initialize_schema(&pool).await?;
-        
-        info!("Successfully initialized database connection");
-        
         Ok(GraphDatabase { pool })
     }
     
-    // Create database schema if it doesn't exist
-    async fn initialize_schema(pool: &PgPool) -> Result<(), Box<dyn Error>> {
-        // Create programs table
-        sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS programs (
-                id TEXT PRIMARY KEY
-            )
-        "#)
-        .execute(pool)
-        .await?;
+    // Insert or update a discriminator and the instruction data seen with it, if any
+    async fn upsert_discriminator(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        instruction_data: Option<Vec<u8>>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        self.get_discriminator_scheme(cluster, program_id).await?
+            .for_kind(metadata.kind)
+            .validate(&discriminator_data)?;
+
+        // Without instruction data, the discriminator keeps the instruction it was seen with
+        let observed = instruction_data.is_some();
+        let instruction_data = instruction_data.unwrap_or_default();
+        let discriminator_id = hex::encode(&discriminator_data);
+        let instruction_id = hex::encode(&instruction_data);
+        
+        // Create a transaction so we can rollback if any part fails
+        let mut tx = self.pool.begin().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
         
-        // Create users table
+        // Insert or update program
         sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS users (
-                id TEXT PRIMARY KEY
-            )
+            INSERT INTO programs (cluster, id) 
+            VALUES ($1, $2)
+            ON CONFLICT (cluster, id) DO NOTHING
         "#)
-        .execute(pool)
-        .await?;
+        .bind(cluster)
+        .bind(program_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
         
-        // Create instructions table
+        // Insert or update user
         sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS instructions (
-                id TEXT PRIMARY KEY,
-                instruction_id TEXT NOT NULL,
-                instruction_data BYTEA NOT NULL
-            )
+            INSERT INTO users (id) 
+            VALUES ($1)
+            ON CONFLICT (id) DO NOTHING
         "#)
-        .execute(pool)
-        .await?;
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+        
+        // Generate unique IDs for discriminator and instruction
+        let discriminator_unique_id = format!("{}_{}_{}", cluster, program_id, discriminator_id);
+        let instruction_unique_id = format!("{}_{}", program_id, hash_key(&instruction_id));
         
-        // Create discriminators table
+        // Insert or update instruction
         sqlx::query(r#"
-            CREATE TABLE IF NOT EXISTS discriminators (
-                id TEXT PRIMARY KEY,
-                discriminator_id TEXT NOT NULL,
-                discriminator_data BYTEA NOT NULL,
-                instruction_id TEXT NOT NULL REFERENCES instructions(id),
-                user_id TEXT NOT NULL REFERENCES users(id),
-                program_id TEXT NOT NULL REFERENCES programs(id)
-            )
+            INSERT INTO instructions (id, instruction_id, instruction_data) 
+            VALUES ($1, $2, $3)
+            ON CONFLICT (id) DO UPDATE 
+            SET instruction_id = EXCLUDED.instruction_id,
+                instruction_data = EXCLUDED.instruction_data
         "#)
-        .execute(pool)
-        .await?;
+        .bind(&instruction_unique_id)
+        .bind(&instruction_id)
+        .bind(&instruction_data)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
         
-        // Create index on discriminators
+        // Insert or update discriminator with relationships.
+        // A known name, kind or source is never overwritten by an anonymous upload, and
+        // labels from a verified upgrade authority are never overwritten by anyone else.
         sqlx::query(r#"
-            CREATE INDEX IF NOT EXISTS idx_discriminators_program_id ON discriminators(program_id)
+            INSERT INTO discriminators (id, discriminator_id, discriminator_data, instruction_id, user_id, program_id, name, kind, source, args_layout, via_cpi, stack_height, cluster, authoritative)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            ON CONFLICT (id) DO UPDATE 
+            SET discriminator_id = EXCLUDED.discriminator_id,
+                discriminator_data = EXCLUDED.discriminator_data,
+                instruction_id = CASE WHEN $15 THEN EXCLUDED.instruction_id ELSE discriminators.instruction_id END,
+                user_id = CASE WHEN discriminators.authoritative AND NOT EXCLUDED.authoritative THEN discriminators.user_id ELSE EXCLUDED.user_id END,
+                name = CASE WHEN discriminators.authoritative AND NOT EXCLUDED.authoritative THEN discriminators.name ELSE COALESCE(EXCLUDED.name, discriminators.name) END,
+                kind = CASE WHEN (discriminators.authoritative AND NOT EXCLUDED.authoritative) OR EXCLUDED.kind = 'unknown' THEN discriminators.kind ELSE EXCLUDED.kind END,
+                source = CASE WHEN discriminators.authoritative AND NOT EXCLUDED.authoritative THEN discriminators.source ELSE COALESCE(EXCLUDED.source, discriminators.source) END,
+                args_layout = CASE WHEN discriminators.authoritative AND NOT EXCLUDED.authoritative THEN discriminators.args_layout ELSE COALESCE(EXCLUDED.args_layout, discriminators.args_layout) END,
+                via_cpi = COALESCE(EXCLUDED.via_cpi, discriminators.via_cpi),
+                stack_height = CASE WHEN EXCLUDED.via_cpi IS NULL THEN discriminators.stack_height ELSE EXCLUDED.stack_height END,
+                authoritative = discriminators.authoritative OR EXCLUDED.authoritative
         "#)
-        .execute(pool)
-        .await?;
+        .bind(&discriminator_unique_id)
+        .bind(&discriminator_id)
+        .bind(&discriminator_data)
+        .bind(&instruction_unique_id)
+        .bind(user_id)
+        .bind(program_id)
+        .bind(&metadata.name)
+        .bind(metadata.kind.as_str())
+        .bind(&metadata.source)
+        .bind(metadata.args_layout.map(Json))
+        .bind(metadata.invocation.map(|invocation| invocation.cpi))
+        .bind(metadata.invocation.and_then(|invocation| invocation.stack_height).map(|height| height as i32))
+        .bind(cluster)
+        .bind(metadata.authoritative)
+        .bind(observed)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+        
+        // Commit the transaction
+        tx.commit().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
         
+        info!("Successfully uploaded discriminator {} for program {} on {}", discriminator_id, program_id, cluster);
         Ok(())
     }
     
-    // Function to hash keys
-    fn hash_key(input: &str) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(input);
-        hex::encode(hasher.finalize())
+    // Map a discriminators row joined with its instruction
+    fn discriminator_from_row(row: &PgRow) -> Result<Discriminator, DatabaseError> {
+        let kind: String = row.get("kind");
+        let args_layout: Option<Json<ArgsLayout>> = row.get("args_layout");
+        let via_cpi: Option<bool> = row.get("via_cpi");
+        let stack_height: Option<i32> = row.get("stack_height");
+        
+        Ok(Discriminator {
+            id: row.get("id"),
+            discriminator_id: row.get("discriminator_id"),
+            discriminator_data: row.get("discriminator_data"),
+            cluster: row.get("cluster"),
+            program_id: row.get("program_id"),
+            user_id: row.get("user_id"),
+            name: row.get("name"),
+            kind: kind.parse()?,
+            source: row.get("source"),
+            authoritative: row.get("authoritative"),
+            args_layout: args_layout.map(|layout| layout.0),
+            invocation: via_cpi.map(|cpi| Invocation {
+                cpi,
+                stack_height: stack_height.map(|height| height as u32),
+            }),
+            instruction: Instruction {
+                id: row.get("instruction_id"),
+                instruction_id: row.get("instruction_id_value"),
+                instruction_data: row.get("instruction_data"),
+            },
+        })
+    }
+    
+    fn backfill_job_from_row(row: &PgRow) -> Result<BackfillJob, DatabaseError> {
+        let status: String = row.get("status");
+        let stop_slot: Option<i64> = row.get("stop_slot");
+        let last_slot: Option<i64> = row.get("last_slot");
+        let processed: i64 = row.get("processed");
+        
+        Ok(BackfillJob {
+            cluster: row.get("cluster"),
+            program_id: row.get("program_id"),
+            status: status.parse()?,
+            stop_slot: stop_slot.map(|slot| slot as u64),
+            before_signature: row.get("before_signature"),
+            last_slot: last_slot.map(|slot| slot as u64),
+            processed: processed as u64,
+            last_error: row.get("last_error"),
+        })
     }
 }
+
+// Function to hash keys
+pub fn hash_key(input: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(input);
+    hex::encode(hasher.finalize())
+}
@@ -0,0 +1,413 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use log::{info, debug};
+
+use crate::graph_disc::{DatabaseError, DatabaseInterface, DiscriminatorKind, DiscriminatorMetadata};
+
+// Subset of the Anchor IDL that is needed to derive discriminators.
+// Both the legacy (< 0.30) and the current IDL format are accepted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Idl {
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<IdlMetadata>,
+    #[serde(default)]
+    pub instructions: Vec<IdlInstruction>,
+    #[serde(default)]
+    pub accounts: Vec<IdlAccount>,
+    #[serde(default)]
+    pub events: Vec<IdlEvent>,
+    #[serde(default)]
+    pub types: Vec<IdlTypeDef>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdlMetadata {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdlInstruction {
+    pub name: String,
+    #[serde(default)]
+    pub discriminator: Option<Vec<u8>>,
+    #[serde(default)]
+    pub args: Vec<IdlField>,
+}
+
+// Legacy IDLs define the account layout inline, the current format refers to `types`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdlAccount {
+    pub name: String,
+    #[serde(default)]
+    pub discriminator: Option<Vec<u8>>,
+    #[serde(default, rename = "type")]
+    pub ty: Option<IdlTypeDefTy>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdlEvent {
+    pub name: String,
+    #[serde(default)]
+    pub discriminator: Option<Vec<u8>>,
+    #[serde(default)]
+    pub fields: Option<Vec<IdlField>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdlField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlType,
+}
+
+// Type of a field, in either IDL format ("publicKey" / "pubkey", plain or nested `defined`)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum IdlType {
+    Primitive(String),
+    Option { option: Box<IdlType> },
+    COption { coption: Box<IdlType> },
+    Vec { vec: Box<IdlType> },
+    Array { array: (Box<IdlType>, usize) },
+    Defined { defined: IdlDefined },
+    // Anything else (generics, new IDL features) is kept so the IDL still parses
+    Other(serde_json::Value),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum IdlDefined {
+    Name(String),
+    Named { name: String },
+}
+
+impl IdlDefined {
+    pub fn name(&self) -> &str {
+        match self {
+            IdlDefined::Name(name) => name,
+            IdlDefined::Named { name } => name,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdlTypeDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlTypeDefTy,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum IdlTypeDefTy {
+    Struct {
+        #[serde(default)]
+        fields: Option<IdlFields>,
+    },
+    Enum {
+        variants: Vec<IdlEnumVariant>,
+    },
+    Alias {
+        value: IdlType,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+// Struct and variant fields are either named or positional
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum IdlFields {
+    Named(Vec<IdlField>),
+    Tuple(Vec<IdlType>),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdlEnumVariant {
+    pub name: String,
+    #[serde(default)]
+    pub fields: Option<IdlFields>,
+}
+
+// Borsh layout of the bytes following a discriminator,
+// together with the type definitions it refers to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArgsLayout {
+    pub fields: Vec<IdlField>,
+    #[serde(default)]
+    pub types: Vec<IdlTypeDef>,
+}
+
+impl ArgsLayout {
+    // Build a layout for `fields`, keeping only the type definitions they reach
+    pub fn new(fields: Vec<IdlField>, types: &[IdlTypeDef]) -> Self {
+        let mut reachable = HashSet::new();
+        let mut pending: Vec<&IdlType> = fields.iter().map(|field| &field.ty).collect();
+
+        while let Some(ty) = pending.pop() {
+            match ty {
+                IdlType::Option { option: inner }
+                | IdlType::COption { coption: inner }
+                | IdlType::Vec { vec: inner } => pending.push(inner),
+                IdlType::Array { array: (inner, _) } => pending.push(inner),
+                IdlType::Defined { defined } => {
+                    let name = defined.name();
+                    if !reachable.insert(name.to_string()) {
+                        continue;
+                    }
+                    if let Some(def) = types.iter().find(|def| def.name == name) {
+                        pending.extend(def.ty.referenced_types());
+                    }
+                },
+                IdlType::Primitive(_) | IdlType::Other(_) => {},
+            }
+        }
+
+        ArgsLayout {
+            fields,
+            types: types.iter().filter(|def| reachable.contains(&def.name)).cloned().collect(),
+        }
+    }
+}
+
+impl IdlTypeDefTy {
+    fn referenced_types(&self) -> Vec<&IdlType> {
+        match self {
+            IdlTypeDefTy::Struct { fields } => fields.iter().flat_map(IdlFields::types).collect(),
+            IdlTypeDefTy::Enum { variants } => variants.iter()
+                .flat_map(|variant| variant.fields.iter().flat_map(IdlFields::types))
+                .collect(),
+            IdlTypeDefTy::Alias { value } => vec![value],
+            IdlTypeDefTy::Unsupported => Vec::new(),
+        }
+    }
+}
+
+impl IdlFields {
+    fn types(&self) -> Vec<&IdlType> {
+        match self {
+            IdlFields::Named(fields) => fields.iter().map(|field| &field.ty).collect(),
+            IdlFields::Tuple(types) => types.iter().collect(),
+        }
+    }
+}
+
+// A discriminator derived from an IDL, ready to be stored
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdlDiscriminator {
+    pub name: String,
+    pub kind: DiscriminatorKind,
+    pub discriminator_data: Vec<u8>,
+    pub args_layout: Option<ArgsLayout>,
+}
+
+impl Idl {
+    // Program name, taken from the metadata in the current format
+    pub fn program_name(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.name.as_deref())
+            .or(self.name.as_deref())
+    }
+
+    // Program address, top-level in the current format and in the metadata in the legacy one
+    pub fn program_address(&self) -> Option<&str> {
+        self.address
+            .as_deref()
+            .or_else(|| self.metadata.as_ref().and_then(|metadata| metadata.address.as_deref()))
+    }
+
+    // Derive the discriminator of every instruction, account and event in the IDL.
+    // Explicit discriminators (current format) take precedence over derived ones.
+    pub fn discriminators(&self) -> Vec<IdlDiscriminator> {
+        let mut discriminators = Vec::new();
+
+        for instruction in &self.instructions {
+            let discriminator_data = instruction.discriminator.clone()
+                .unwrap_or_else(|| sighash("global", &to_snake_case(&instruction.name)).to_vec());
+            discriminators.push(IdlDiscriminator {
+                name: instruction.name.clone(),
+                kind: DiscriminatorKind::Instruction,
+                discriminator_data,
+                args_layout: Some(ArgsLayout::new(instruction.args.clone(), &self.types)),
+            });
+        }
+
+        for account in &self.accounts {
+            let discriminator_data = account.discriminator.clone()
+                .unwrap_or_else(|| sighash("account", &account.name).to_vec());
+            discriminators.push(IdlDiscriminator {
+                name: account.name.clone(),
+                kind: DiscriminatorKind::Account,
+                discriminator_data,
+                args_layout: self.struct_layout(&account.name, account.ty.as_ref()),
+            });
+        }
+
+        for event in &self.events {
+            let discriminator_data = event.discriminator.clone()
+                .unwrap_or_else(|| sighash("event", &event.name).to_vec());
+            let args_layout = match &event.fields {
+                Some(fields) => Some(ArgsLayout::new(fields.clone(), &self.types)),
+                None => self.struct_layout(&event.name, None),
+            };
+            discriminators.push(IdlDiscriminator {
+                name: event.name.clone(),
+                kind: DiscriminatorKind::Event,
+                discriminator_data,
+                args_layout,
+            });
+        }
+
+        discriminators
+    }
+
+    // Layout of a named struct, defined inline or in `types`
+    fn struct_layout(&self, name: &str, inline: Option<&IdlTypeDefTy>) -> Option<ArgsLayout> {
+        let ty = inline.or_else(|| {
+            self.types.iter().find(|def| def.name == name).map(|def| &def.ty)
+        })?;
+
+        match ty {
+            IdlTypeDefTy::Struct { fields: Some(IdlFields::Named(fields)) } => {
+                Some(ArgsLayout::new(fields.clone(), &self.types))
+            },
+            IdlTypeDefTy::Struct { fields: None } => Some(ArgsLayout::new(Vec::new(), &self.types)),
+            _ => None,
+        }
+    }
+}
+
+// Anchor discriminator: first 8 bytes of sha256("<namespace>:<name>")
+pub fn sighash(namespace: &str, name: &str) -> [u8; 8] {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}:{}", namespace, name));
+    let hash = hasher.finalize();
+
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+// Legacy IDLs use camelCase instruction names while Anchor hashes the snake_case name
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            // Start a new word unless this continues an acronym ("createATA" -> "create_ata")
+            let after_lower = i > 0 && (chars[i - 1].is_lowercase() || chars[i - 1].is_ascii_digit());
+            let acronym_end = i > 0 && chars[i - 1].is_uppercase()
+                && chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if after_lower || acronym_end {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+// Derive every discriminator in the IDL and store it with its name attached.
+// `authoritative` marks an IDL uploaded by the program's verified upgrade authority.
+pub async fn ingest_idl<T>(
+    db: &T,
+    cluster: &str,
+    program_id: &str,
+    idl: &Idl,
+    user_id: &str,
+    source: &str,
+    authoritative: bool,
+) -> Result<usize, DatabaseError>
+where
+    T: DatabaseInterface + ?Sized,
+{
+    let discriminators = idl.discriminators();
+    debug!("Ingesting {} discriminators from IDL for program {}", discriminators.len(), program_id);
+
+    for discriminator in &discriminators {
+        let metadata = DiscriminatorMetadata {
+            name: Some(discriminator.name.clone()),
+            kind: discriminator.kind,
+            source: Some(source.to_string()),
+            authoritative,
+            args_layout: discriminator.args_layout.clone(),
+            invocation: None,
+        };
+
+        db.set_discriminator_metadata(
+            cluster,
+            program_id,
+            discriminator.discriminator_data.clone(),
+            user_id,
+            metadata,
+        ).await?;
+    }
+
+    info!("Stored {} named discriminators from IDL for program {}", discriminators.len(), program_id);
+    Ok(discriminators.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_namespaced_names() {
+        assert_eq!(hex::encode(sighash("global", "initialize")), "afaf6d1f0d989bed");
+        assert_ne!(sighash("global", "initialize"), sighash("account", "initialize"));
+    }
+
+    #[test]
+    fn converts_legacy_names_to_snake_case() {
+        for (name, expected) in [
+            ("initialize", "initialize"),
+            ("Initialize", "initialize"),
+            ("increment_by", "increment_by"),
+            ("incrementBy", "increment_by"),
+            ("createATA", "create_ata"),
+            ("ATAProgram", "ata_program"),
+            ("initializeV2", "initialize_v2"),
+            ("setV2Config", "set_v2_config"),
+            ("", ""),
+        ] {
+            assert_eq!(to_snake_case(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn derives_discriminators_by_item_kind() {
+        let idl: Idl = serde_json::from_value(serde_json::json!({
+            "version": "0.1.0",
+            "name": "counter",
+            "instructions": [
+                { "name": "incrementBy" },
+                { "name": "reset", "discriminator": [1, 2, 3, 4, 5, 6, 7, 8] },
+            ],
+            "accounts": [{ "name": "Counter" }],
+            "events": [{ "name": "Incremented" }],
+        })).unwrap();
+
+        let derived: Vec<_> = idl.discriminators().into_iter()
+            .map(|d| (d.name, d.kind, d.discriminator_data))
+            .collect();
+        // Explicit discriminators are kept; legacy camelCase names are hashed in snake_case
+        assert_eq!(derived, vec![
+            ("incrementBy".to_string(), DiscriminatorKind::Instruction, sighash("global", "increment_by").to_vec()),
+            ("reset".to_string(), DiscriminatorKind::Instruction, vec![1, 2, 3, 4, 5, 6, 7, 8]),
+            ("Counter".to_string(), DiscriminatorKind::Account, sighash("account", "Counter").to_vec()),
+            ("Incremented".to_string(), DiscriminatorKind::Event, sighash("event", "Incremented").to_vec()),
+        ]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use log::error;
+use serde::Serialize;
+
+use crate::graph_disc::DatabaseInterface;
+use crate::solana_connection::{SolanaConnection, SolanaError};
+
+// Where the transactions of tracked programs come from. Every source hands them to
+// `SolanaConnection::process_transaction`, so discriminators are stored the same way.
+#[async_trait]
+pub trait IngestionSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Ingest the transactions of the given programs until the source stops
+    async fn run(&self, db: Arc<dyn DatabaseInterface>, program_ids: Vec<String>) -> Result<(), SolanaError>;
+}
+
+// What is known about the listener of one program, as reported by `/status`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ListenerStatus {
+    pub program_id: String,
+    pub source: &'static str,
+    pub running: bool,
+    pub last_signature: Option<String>,
+    pub last_slot: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+// Listener states of one cluster, shared by its connection and every clone of it
+#[derive(Debug, Default)]
+pub struct ListenerRegistry {
+    listeners: Mutex<BTreeMap<String, ListenerStatus>>,
+}
+
+impl ListenerRegistry {
+    pub fn started(&self, program_id: &str, source: &'static str) {
+        let mut listeners = self.listeners.lock().unwrap();
+        let listener = listeners.entry(program_id.to_string()).or_insert_with(|| ListenerStatus {
+            program_id: program_id.to_string(),
+            source,
+            running: true,
+            last_signature: None,
+            last_slot: None,
+            last_error: None,
+        });
+        listener.source = source;
+        listener.running = true;
+    }
+
+    pub fn stopped(&self, program_id: &str, error: Option<String>) {
+        self.update(program_id, |listener| {
+            listener.running = false;
+            if error.is_some() {
+                listener.last_error = error;
+            }
+        });
+    }
+
+    pub fn advanced(&self, program_id: &str, signature: &str, slot: u64) {
+        self.update(program_id, |listener| {
+            listener.last_signature = Some(signature.to_string());
+            listener.last_slot = Some(slot);
+        });
+    }
+
+    pub fn failed(&self, program_id: &str, error: String) {
+        self.update(program_id, |listener| listener.last_error = Some(error));
+    }
+
+    pub fn snapshot(&self) -> Vec<ListenerStatus> {
+        self.listeners.lock().unwrap().values().cloned().collect()
+    }
+
+    // Programs that are not listened to are not reported
+    fn update(&self, program_id: &str, update: impl FnOnce(&mut ListenerStatus)) {
+        if let Some(listener) = self.listeners.lock().unwrap().get_mut(program_id) {
+            update(listener);
+        }
+    }
+}
+
+// The RPC node itself: one listener per program, streaming over WebSocket or polling
+#[async_trait]
+impl IngestionSource for SolanaConnection {
+    fn name(&self) -> &'static str {
+        "rpc"
+    }
+
+    async fn run(&self, db: Arc<dyn DatabaseInterface>, program_ids: Vec<String>) -> Result<(), SolanaError> {
+        let listeners = program_ids.iter().map(|program_id| {
+            let solana_client = self.clone();
+            let db = db.clone();
+            let program_id = program_id.clone();
+            solana_client.listeners().started(&program_id, self.name());
+
+            tokio::spawn(async move {
+                let error = solana_client.real_time_listener(db, program_id.clone()).await.err();
+                if let Some(e) = &error {
+                    error!("Error in real time listener: {}", e);
+                }
+                solana_client.listeners().stopped(&program_id, error.map(|e| e.to_string()));
+            })
+        });
+
+        // A listener that panicked never got to report itself stopped
+        for (program_id, result) in program_ids.iter().zip(join_all(listeners).await) {
+            if let Err(e) = result {
+                self.listeners().stopped(program_id, Some(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_listener_progress_and_failures() {
+        let registry = ListenerRegistry::default();
+        registry.advanced("unknown", "signature", 1);
+        assert!(registry.snapshot().is_empty());
+
+        registry.started("program", "rpc");
+        registry.advanced("program", "signature", 42);
+        registry.failed("program", "socket closed".to_string());
+        registry.stopped("program", None);
+
+        assert_eq!(registry.snapshot(), vec![ListenerStatus {
+            program_id: "program".to_string(),
+            source: "rpc",
+            running: false,
+            last_signature: Some("signature".to_string()),
+            last_slot: Some(42),
+            last_error: Some("socket closed".to_string()),
+        }]);
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use log::{info, debug};
+
+use crate::graph_disc::{
+    hash_key, ApiKey, BackfillJob, BackfillStatus, DatabaseError, DatabaseInterface, Discriminator,
+    DiscriminatorKind, DiscriminatorMetadata, Instruction, InstructionRecord, ProgramCursor,
+};
+use crate::idl::ArgsLayout;
+use crate::migrations::SchemaStatus;
+use crate::scheme::DiscriminatorScheme;
+use crate::status::{DatabaseHealth, PoolStatus};
+
+// (cluster, program ID)
+type ProgramKey = (String, String);
+
+// Rows of the tables `GraphDatabase` keeps in PostgreSQL, keyed by primary key
+#[derive(Default)]
+struct Tables {
+    // Programs with the discriminator scheme stored for them, if any
+    programs: BTreeMap<ProgramKey, Option<DiscriminatorScheme>>,
+    users: BTreeSet<String>,
+    instructions: BTreeMap<String, Instruction>,
+    // Discriminator rows, each referencing its instruction by `instruction.id`
+    discriminators: BTreeMap<String, Discriminator>,
+    program_cursors: BTreeMap<ProgramKey, ProgramCursor>,
+    backfill_jobs: BTreeMap<ProgramKey, BackfillJob>,
+    // API key hash -> (name, key)
+    api_keys: BTreeMap<String, (String, ApiKey)>,
+}
+
+fn program_key(cluster: &str, program_id: &str) -> ProgramKey {
+    (cluster.to_string(), program_id.to_string())
+}
+
+// Database held in process memory, with the same semantics as `GraphDatabase`.
+// Used by tests and to run the service without PostgreSQL; nothing survives a restart.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tables(&self) -> Result<MutexGuard<'_, Tables>, DatabaseError> {
+        self.tables.lock()
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))
+    }
+
+    // Insert or update a discriminator and the instruction data seen with it, if any
+    fn upsert_discriminator(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        instruction_data: Option<Vec<u8>>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        let discriminator_id = hex::encode(&discriminator_data);
+        let discriminator_unique_id = format!("{}_{}_{}", cluster, program_id, discriminator_id);
+
+        // The lock is held for the whole upload, like the transaction in `GraphDatabase`
+        let mut tables = self.tables()?;
+        let stored = tables.programs.get(&program_key(cluster, program_id)).copied().flatten();
+        DiscriminatorScheme::for_program(program_id, stored)
+            .for_kind(metadata.kind)
+            .validate(&discriminator_data)?;
+        tables.programs.entry(program_key(cluster, program_id)).or_default();
+        tables.users.insert(user_id.to_string());
+
+        // Without instruction data, the discriminator keeps the instruction it was seen with
+        let existing = tables.discriminators.remove(&discriminator_unique_id);
+        let instruction = match (instruction_data, &existing) {
+            (None, Some(existing)) => existing.instruction.clone(),
+            (instruction_data, _) => {
+                let instruction_data = instruction_data.unwrap_or_default();
+                let instruction_id = hex::encode(&instruction_data);
+                let instruction = Instruction {
+                    id: format!("{}_{}", program_id, hash_key(&instruction_id)),
+                    instruction_id,
+                    instruction_data,
+                };
+                tables.instructions.insert(instruction.id.clone(), instruction.clone());
+                instruction
+            },
+        };
+
+        // A known name, kind or source is never overwritten by an anonymous upload, and
+        // labels from a verified upgrade authority are never overwritten by anyone else
+        let protected = existing.as_ref().filter(|d| d.authoritative && !metadata.authoritative);
+        let discriminator = Discriminator {
+            id: discriminator_unique_id,
+            discriminator_id: discriminator_id.clone(),
+            discriminator_data,
+            instruction,
+            user_id: protected.map_or_else(|| user_id.to_string(), |d| d.user_id.clone()),
+            cluster: cluster.to_string(),
+            program_id: program_id.to_string(),
+            name: match protected {
+                Some(protected) => protected.name.clone(),
+                None => metadata.name.or_else(|| existing.as_ref().and_then(|d| d.name.clone())),
+            },
+            kind: match (metadata.kind, &existing) {
+                (_, Some(existing)) if protected.is_some() => existing.kind,
+                (DiscriminatorKind::Unknown, Some(existing)) => existing.kind,
+                (kind, _) => kind,
+            },
+            source: match protected {
+                Some(protected) => protected.source.clone(),
+                None => metadata.source.or_else(|| existing.as_ref().and_then(|d| d.source.clone())),
+            },
+            authoritative: metadata.authoritative || existing.as_ref().is_some_and(|d| d.authoritative),
+            args_layout: match protected {
+                Some(protected) => protected.args_layout.clone(),
+                None => metadata.args_layout.or_else(|| existing.as_ref().and_then(|d| d.args_layout.clone())),
+            },
+            invocation: metadata.invocation.or_else(|| existing.as_ref().and_then(|d| d.invocation)),
+        };
+        tables.discriminators.insert(discriminator.id.clone(), discriminator);
+
+        info!("Successfully uploaded discriminator {} for program {} on {}", discriminator_id, program_id, cluster);
+        Ok(())
+    }
+}
+
+impl Tables {
+    // Join a discriminator row with its instruction
+    fn joined(&self, discriminator: &Discriminator) -> Result<Discriminator, DatabaseError> {
+        let instruction = self.instructions.get(&discriminator.instruction.id)
+            .ok_or_else(|| DatabaseError::QueryError(format!("Missing instruction {}", discriminator.instruction.id)))?;
+
+        Ok(Discriminator {
+            instruction: instruction.clone(),
+            ..discriminator.clone()
+        })
+    }
+
+    fn discriminators_with_id<'a>(
+        &'a self,
+        cluster: &'a str,
+        discriminator_id: &'a str,
+        kind: Option<DiscriminatorKind>,
+    ) -> impl Iterator<Item = &'a Discriminator> {
+        self.discriminators.values()
+            .filter(move |d| d.cluster == cluster && d.discriminator_id == discriminator_id)
+            .filter(move |d| kind.is_none_or(|kind| d.kind == kind))
+    }
+}
+
+#[async_trait]
+impl DatabaseInterface for MemoryDatabase {
+    async fn upload_discriminator(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        instruction_data: Vec<u8>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        debug!("Uploading discriminator for program {} on {}", program_id, cluster);
+        self.upsert_discriminator(cluster, program_id, discriminator_data, Some(instruction_data), user_id, metadata)
+    }
+
+    async fn set_discriminator_metadata(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        debug!("Setting metadata of discriminator for program {} on {}", program_id, cluster);
+        self.upsert_discriminator(cluster, program_id, discriminator_data, None, user_id, metadata)
+    }
+
+    async fn query_discriminators_and_instructions(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<Discriminator>, DatabaseError> {
+        debug!("Querying discriminators for program {}", program_id);
+
+        let tables = self.tables()?;
+        let mut discriminators = tables.discriminators.values()
+            .filter(|d| d.cluster == cluster && d.program_id == program_id)
+            .filter(|d| kind.is_none_or(|kind| d.kind == kind))
+            .map(|d| tables.joined(d))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Authoritative labels first, each group in discriminator order
+        discriminators.sort_by_key(|d| !d.authoritative);
+
+        info!("Found {} discriminators for program {}", discriminators.len(), program_id);
+        Ok(discriminators)
+    }
+
+    async fn get_all_program_ids(&self, cluster: &str) -> Result<Vec<String>, DatabaseError> {
+        debug!("Fetching all program IDs on {}", cluster);
+
+        let program_ids: Vec<String> = self.tables()?.programs.keys()
+            .filter(|(program_cluster, _)| program_cluster == cluster)
+            .map(|(_, program_id)| program_id.clone())
+            .collect();
+
+        info!("Retrieved {} program IDs", program_ids.len());
+        Ok(program_ids)
+    }
+
+    async fn query_instructions_by_discriminator(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<String>, DatabaseError> {
+        debug!("Querying instructions for discriminator {}", discriminator_id);
+
+        let tables = self.tables()?;
+        let instructions = tables.discriminators_with_id(cluster, discriminator_id, kind)
+            .map(|d| tables.joined(d).map(|d| hex::encode(d.instruction.instruction_data)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        info!("Found {} instructions for discriminator {}", instructions.len(), discriminator_id);
+        Ok(instructions)
+    }
+
+    async fn get_discriminator(&self, cluster: &str, program_id: &str, discriminator_id: &str) -> Result<Option<Discriminator>, DatabaseError> {
+        debug!("Fetching discriminator {} for program {}", discriminator_id, program_id);
+
+        let tables = self.tables()?;
+        tables.discriminators.get(&format!("{}_{}_{}", cluster, program_id, discriminator_id))
+            .map(|d| tables.joined(d))
+            .transpose()
+    }
+
+    async fn query_instruction_records(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<InstructionRecord>, DatabaseError> {
+        debug!("Querying instruction records for discriminator {}", discriminator_id);
+
+        let tables = self.tables()?;
+        let records = tables.discriminators_with_id(cluster, discriminator_id, kind)
+            .map(|d| tables.joined(d).map(|d| InstructionRecord {
+                program_id: d.program_id,
+                discriminator_id: d.discriminator_id,
+                name: d.name,
+                instruction_data: d.instruction.instruction_data,
+                args_layout: d.args_layout,
+            }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        info!("Found {} instruction records for discriminator {}", records.len(), discriminator_id);
+        Ok(records)
+    }
+
+    async fn get_program_cursor(&self, cluster: &str, program_id: &str) -> Result<Option<ProgramCursor>, DatabaseError> {
+        debug!("Fetching signature cursor for program {} on {}", program_id, cluster);
+
+        Ok(self.tables()?.program_cursors.get(&program_key(cluster, program_id)).cloned())
+    }
+
+    async fn update_program_cursor(&self, cluster: &str, program_id: &str, signature: &str, slot: u64) -> Result<(), DatabaseError> {
+        debug!("Advancing signature cursor for program {} on {} to {}", program_id, cluster, signature);
+
+        let mut tables = self.tables()?;
+        let key = program_key(cluster, program_id);
+        // Cursors reference the programs table
+        if !tables.programs.contains_key(&key) {
+            return Err(DatabaseError::InsertionError(format!("Unknown program {} on {}", program_id, cluster)));
+        }
+
+        tables.program_cursors.insert(key, ProgramCursor {
+            cluster: cluster.to_string(),
+            program_id: program_id.to_string(),
+            signature: signature.to_string(),
+            slot,
+        });
+        Ok(())
+    }
+
+    async fn get_backfill_job(&self, cluster: &str, program_id: &str) -> Result<Option<BackfillJob>, DatabaseError> {
+        debug!("Fetching backfill job for program {} on {}", program_id, cluster);
+
+        Ok(self.tables()?.backfill_jobs.get(&program_key(cluster, program_id)).cloned())
+    }
+
+    async fn get_running_backfill_jobs(&self) -> Result<Vec<BackfillJob>, DatabaseError> {
+        debug!("Fetching running backfill jobs");
+
+        Ok(self.tables()?.backfill_jobs.values()
+            .filter(|job| job.status == BackfillStatus::Running)
+            .cloned()
+            .collect())
+    }
+
+    async fn save_backfill_job(&self, job: &BackfillJob) -> Result<(), DatabaseError> {
+        debug!("Saving backfill job for program {} ({} processed)", job.program_id, job.processed);
+
+        self.tables()?.backfill_jobs.insert(program_key(&job.cluster, &job.program_id), job.clone());
+        Ok(())
+    }
+
+    async fn set_args_layout(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_id: &str,
+        args_layout: ArgsLayout,
+    ) -> Result<(), DatabaseError> {
+        debug!("Setting args layout for discriminator {} of program {}", discriminator_id, program_id);
+
+        let mut tables = self.tables()?;
+        let discriminator = tables.discriminators.get_mut(&format!("{}_{}_{}", cluster, program_id, discriminator_id))
+            .ok_or_else(|| DatabaseError::NotFound(format!(
+                "discriminator {} for program {} on {}", discriminator_id, program_id, cluster
+            )))?;
+        discriminator.args_layout = Some(args_layout);
+
+        info!("Stored args layout for discriminator {} of program {}", discriminator_id, program_id);
+        Ok(())
+    }
+
+    async fn get_discriminator_scheme(&self, cluster: &str, program_id: &str) -> Result<DiscriminatorScheme, DatabaseError> {
+        let stored = self.tables()?.programs.get(&program_key(cluster, program_id)).copied().flatten();
+        Ok(DiscriminatorScheme::for_program(program_id, stored))
+    }
+
+    async fn set_discriminator_scheme(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        scheme: DiscriminatorScheme,
+    ) -> Result<(), DatabaseError> {
+        self.tables()?.programs.insert(program_key(cluster, program_id), Some(scheme));
+
+        info!("Set discriminator scheme of program {} on {} to {}", program_id, cluster, scheme);
+        Ok(())
+    }
+
+    async fn create_api_key(&self, key_hash: &str, name: &str, user_id: &str, admin: bool) -> Result<(), DatabaseError> {
+        let mut tables = self.tables()?;
+        if tables.api_keys.contains_key(key_hash) {
+            return Err(DatabaseError::InsertionError(format!("API key {} already exists", name)));
+        }
+        tables.users.insert(user_id.to_string());
+        tables.api_keys.insert(key_hash.to_string(), (name.to_string(), ApiKey { user_id: user_id.to_string(), admin }));
+
+        info!("Created {} API key {} for user {}", if admin { "admin" } else { "upload" }, name, user_id);
+        Ok(())
+    }
+
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, DatabaseError> {
+        Ok(self.tables()?.api_keys.get(key_hash).map(|(_, key)| key.clone()))
+    }
+
+    async fn revoke_api_keys(&self, name: &str) -> Result<u64, DatabaseError> {
+        let mut tables = self.tables()?;
+        let before = tables.api_keys.len();
+        tables.api_keys.retain(|_, (key_name, _)| key_name != name);
+        let revoked = (before - tables.api_keys.len()) as u64;
+
+        info!("Revoked {} API keys named {}", revoked, name);
+        Ok(revoked)
+    }
+}
+
+// Always reachable, with neither a pool nor a versioned schema
+#[async_trait]
+impl DatabaseHealth for MemoryDatabase {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        None
+    }
+
+    async fn ping(&self) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    async fn schema_status(&self) -> Result<Option<SchemaStatus>, DatabaseError> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph_disc::Invocation;
+
+    fn named(name: &str, kind: DiscriminatorKind) -> DiscriminatorMetadata {
+        DiscriminatorMetadata {
+            name: Some(name.to_string()),
+            kind,
+            source: Some("idl".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_discriminators_that_are_not_eight_bytes() {
+        let db = MemoryDatabase::new();
+
+        let result = db.upload_discriminator("devnet", "program", vec![1; 7], vec![], "user", Default::default()).await;
+        assert!(matches!(result, Err(DatabaseError::DataParsingError(_))));
+        assert!(db.get_all_program_ids("devnet").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn validates_discriminators_against_the_program_scheme() {
+        let db = MemoryDatabase::new();
+        let token_program = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+        // SPL Token comes preconfigured with 1-byte tags
+        db.upload_discriminator("devnet", token_program, vec![3], vec![], "user", Default::default()).await.unwrap();
+        assert!(db.upload_discriminator("devnet", token_program, vec![3; 8], vec![], "user", Default::default()).await.is_err());
+        // Only instruction tags follow the scheme; accounts and events keep 8-byte discriminators
+        db.upload_discriminator("devnet", token_program, vec![3; 8], vec![], "user", named("Transfer", DiscriminatorKind::Event)).await.unwrap();
+        assert!(db.upload_discriminator("devnet", token_program, vec![3], vec![], "user", named("Mint", DiscriminatorKind::Account)).await.is_err());
+
+        assert!(db.upload_discriminator("devnet", "program", vec![2, 0, 0, 0], vec![], "user", Default::default()).await.is_err());
+        db.set_discriminator_scheme("devnet", "program", DiscriminatorScheme::U32Le).await.unwrap();
+        db.upload_discriminator("devnet", "program", vec![2, 0, 0, 0], vec![], "user", Default::default()).await.unwrap();
+        assert_eq!(db.get_discriminator_scheme("devnet", "program").await.unwrap(), DiscriminatorScheme::U32Le);
+        assert_eq!(db.get_discriminator_scheme("mainnet", "program").await.unwrap(), DiscriminatorScheme::Anchor);
+    }
+
+    #[tokio::test]
+    async fn anonymous_uploads_keep_known_metadata() {
+        let db = MemoryDatabase::new();
+        let discriminator = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let discriminator_id = hex::encode(&discriminator);
+
+        db.upload_discriminator("devnet", "program", discriminator.clone(), vec![9], "idl-user", named("initialize", DiscriminatorKind::Instruction)).await.unwrap();
+        db.upload_discriminator("devnet", "program", discriminator.clone(), vec![10], "signer", DiscriminatorMetadata {
+            invocation: Some(Invocation { cpi: true, stack_height: Some(2) }),
+            ..Default::default()
+        }).await.unwrap();
+        db.upload_discriminator("devnet", "program", discriminator, vec![11], "signer", Default::default()).await.unwrap();
+
+        let stored = db.get_discriminator("devnet", "program", &discriminator_id).await.unwrap().unwrap();
+        assert_eq!(stored.name.as_deref(), Some("initialize"));
+        assert_eq!(stored.kind, DiscriminatorKind::Instruction);
+        assert_eq!(stored.source.as_deref(), Some("idl"));
+        assert_eq!(stored.invocation, Some(Invocation { cpi: true, stack_height: Some(2) }));
+        // The latest upload's instruction and user replace the earlier ones
+        assert_eq!(stored.user_id, "signer");
+        assert_eq!(stored.instruction.instruction_data, vec![11]);
+        assert_eq!(db.query_instructions_by_discriminator("devnet", &discriminator_id, None).await.unwrap(), vec!["0b"]);
+
+        assert_eq!(db.query_discriminators_and_instructions("devnet", "program", Some(DiscriminatorKind::Instruction)).await.unwrap().len(), 1);
+        assert!(db.query_discriminators_and_instructions("devnet", "program", Some(DiscriminatorKind::Account)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn naming_a_discriminator_keeps_its_observed_instruction() {
+        let db = MemoryDatabase::new();
+        let discriminator = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let discriminator_id = hex::encode(&discriminator);
+
+        db.upload_discriminator("devnet", "program", discriminator.clone(), vec![1, 2, 3, 4, 5, 6, 7, 8, 42], "signer", Default::default()).await.unwrap();
+        db.set_discriminator_metadata("devnet", "program", discriminator, "idl-user", named("initialize", DiscriminatorKind::Instruction)).await.unwrap();
+        db.set_discriminator_metadata("devnet", "program", vec![9; 8], "idl-user", named("close", DiscriminatorKind::Instruction)).await.unwrap();
+
+        let stored = db.get_discriminator("devnet", "program", &discriminator_id).await.unwrap().unwrap();
+        assert_eq!(stored.name.as_deref(), Some("initialize"));
+        assert_eq!(stored.instruction.instruction_data, vec![1, 2, 3, 4, 5, 6, 7, 8, 42]);
+        // A discriminator not seen on chain yet is stored with empty instruction data
+        let unseen = db.get_discriminator("devnet", "program", "0909090909090909").await.unwrap().unwrap();
+        assert_eq!(unseen.name.as_deref(), Some("close"));
+        assert!(unseen.instruction.instruction_data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn layouts_and_cursors_require_existing_rows() {
+        let db = MemoryDatabase::new();
+
+        let result = db.set_args_layout("devnet", "program", "0102030405060708", ArgsLayout::new(vec![], &[])).await;
+        assert!(matches!(result, Err(DatabaseError::NotFound(_))));
+        assert!(db.update_program_cursor("devnet", "program", "signature", 1).await.is_err());
+
+        db.upload_discriminator("devnet", "program", vec![1, 2, 3, 4, 5, 6, 7, 8], vec![], "user", Default::default()).await.unwrap();
+        db.set_args_layout("devnet", "program", "0102030405060708", ArgsLayout::new(vec![], &[])).await.unwrap();
+        db.update_program_cursor("devnet", "program", "signature", 1).await.unwrap();
+
+        let records = db.query_instruction_records("devnet", "0102030405060708", None).await.unwrap();
+        assert!(records[0].args_layout.is_some());
+        assert_eq!(db.get_program_cursor("devnet", "program").await.unwrap().unwrap().slot, 1);
+    }
+
+    #[tokio::test]
+    async fn keeps_clusters_apart() {
+        let db = MemoryDatabase::new();
+
+        db.upload_discriminator("mainnet", "program", vec![1; 8], vec![], "user", named("swap", DiscriminatorKind::Instruction)).await.unwrap();
+        db.upload_discriminator("devnet", "program", vec![1; 8], vec![], "user", Default::default()).await.unwrap();
+
+        let mainnet = db.get_discriminator("mainnet", "program", "0101010101010101").await.unwrap().unwrap();
+        let devnet = db.get_discriminator("devnet", "program", "0101010101010101").await.unwrap().unwrap();
+        assert_eq!(mainnet.name.as_deref(), Some("swap"));
+        assert_eq!(devnet.name, None);
+        assert_eq!(devnet.cluster, "devnet");
+        assert!(db.get_all_program_ids("testnet").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use log::info;
+use serde::Serialize;
+use sqlx::migrate::{Migration, Migrator};
+
+use crate::graph_disc::DatabaseError;
+
+// Schema versions, embedded from `backend/migrations/<backend>/<version>_<description>.sql`.
+// Applied versions are recorded in the `schema_migrations` table.
+pub static POSTGRES_MIGRATIONS: Migrator = sqlx::migrate!("migrations/postgres");
+
+#[cfg(feature = "sqlite")]
+pub static SQLITE_MIGRATIONS: Migrator = sqlx::migrate!("migrations/sqlite");
+
+// A row of `schema_migrations`
+#[derive(Debug, Clone)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub checksum: Vec<u8>,
+}
+
+// A migration known to this build and whether the database has applied it
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+// The newest schema version applied to a database and the newest this build ships
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaStatus {
+    pub version: Option<i64>,
+    pub latest: Option<i64>,
+}
+
+// Storage backends with a versioned schema
+#[async_trait]
+pub trait SchemaMigrations: Send + Sync {
+    // The migrations compiled into this build for the backend
+    fn migrator(&self) -> &'static Migrator;
+
+    // Create `schema_migrations` if needed and read it, oldest version first
+    async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, DatabaseError>;
+
+    // Run one migration and record it, atomically
+    async fn apply_migration(&self, migration: &Migration) -> Result<(), DatabaseError>;
+
+    async fn migration_status(&self) -> Result<Vec<MigrationStatus>, DatabaseError> {
+        let applied = self.applied_migrations().await?;
+        pending_migrations(self.migrator(), &applied)?;
+
+        Ok(self.migrator().iter()
+            .map(|migration| MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: applied.iter().any(|applied| applied.version == migration.version),
+            })
+            .collect())
+    }
+
+    async fn schema_status(&self) -> Result<SchemaStatus, DatabaseError> {
+        let applied = self.applied_migrations().await?;
+        Ok(SchemaStatus {
+            version: applied.iter().map(|migration| migration.version).max(),
+            latest: self.migrator().iter().map(|migration| migration.version).max(),
+        })
+    }
+
+    // Apply every pending migration, refusing to touch a schema this build does not know.
+    // Returns the versions that were applied. Backends shared by several processes override
+    // this to hold a lock around `apply_pending_migrations`.
+    async fn run_migrations(&self) -> Result<Vec<i64>, DatabaseError> {
+        apply_pending_migrations(self).await
+    }
+}
+
+// Plan against `schema_migrations` as it is now and apply what is missing
+pub async fn apply_pending_migrations<M>(db: &M) -> Result<Vec<i64>, DatabaseError>
+where
+    M: SchemaMigrations + ?Sized,
+{
+    let applied = db.applied_migrations().await?;
+    let pending = pending_migrations(db.migrator(), &applied)?;
+
+    let mut versions = Vec::new();
+    for migration in pending {
+        info!("Applying schema migration {} ({})", migration.version, migration.description);
+        db.apply_migration(migration).await?;
+        versions.push(migration.version);
+    }
+    Ok(versions)
+}
+
+// The migrations still to apply, in version order. Fails when the database holds a version
+// this build does not ship, or one whose SQL has changed since it was applied.
+pub fn pending_migrations<'a>(
+    migrator: &'a Migrator,
+    applied: &[AppliedMigration],
+) -> Result<Vec<&'a Migration>, DatabaseError> {
+    for applied in applied {
+        match migrator.iter().find(|migration| migration.version == applied.version) {
+            None => return Err(DatabaseError::MigrationError(format!(
+                "database schema version {} is unknown to this build; upgrade the binary", applied.version
+            ))),
+            Some(migration) if *migration.checksum != *applied.checksum => {
+                return Err(DatabaseError::MigrationError(format!(
+                    "migration {} ({}) changed after it was applied", migration.version, migration.description
+                )));
+            },
+            Some(_) => {},
+        }
+    }
+
+    Ok(migrator.iter()
+        .filter(|migration| !applied.iter().any(|applied| applied.version == migration.version))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use sqlx::migrate::MigrationType;
+
+    fn migrator() -> Migrator {
+        Migrator {
+            migrations: Cow::Owned(vec![
+                Migration::new(1, "initial schema".into(), MigrationType::Simple, "SELECT 1".into()),
+                Migration::new(2, "add cluster".into(), MigrationType::Simple, "SELECT 2".into()),
+            ]),
+            ..Migrator::DEFAULT
+        }
+    }
+
+    fn applied(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            checksum: migration.checksum.to_vec(),
+        }
+    }
+
+    #[test]
+    fn plans_pending_migrations_in_order() {
+        let migrator = migrator();
+        let versions = |applied: &[AppliedMigration]| {
+            pending_migrations(&migrator, applied).unwrap().iter().map(|migration| migration.version).collect::<Vec<_>>()
+        };
+
+        assert_eq!(versions(&[]), vec![1, 2]);
+        assert_eq!(versions(&[applied(&migrator.migrations[0])]), vec![2]);
+    }
+
+    #[test]
+    fn refuses_unknown_or_modified_schema_versions() {
+        let migrator = migrator();
+
+        let unknown = AppliedMigration { version: 3, checksum: vec![] };
+        assert!(matches!(pending_migrations(&migrator, &[unknown]), Err(DatabaseError::MigrationError(_))));
+
+        let modified = AppliedMigration { version: 1, checksum: vec![0; 48] };
+        assert!(matches!(pending_migrations(&migrator, &[modified]), Err(DatabaseError::MigrationError(_))));
+    }
+}
@@ -1,22 +1,67 @@
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
 use serde_json::json;
-use crate::graph_disc::{GraphDatabase, DatabaseInterface};
-use crate::solana_connection::SolanaConnection;
+use crate::auth::{Admin, Contributor};
+use crate::backfill::{next_backfill_job, spawn_backfill};
+use crate::decode::{decode_account_data, decode_args};
+use crate::graph_disc::{
+    DatabaseError, DatabaseInterface, DiscriminatorKind, DiscriminatorMetadata,
+};
+use crate::idl::{self, ArgsLayout, Idl};
+use crate::cluster::Clusters;
+use crate::scheme::{DiscriminatorScheme, ACCOUNT_SCHEME};
+use crate::upload::DiscriminatorUpload;
+use crate::solana_connection::{ChainSource, SolanaConnection};
 use log::{error, info};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
 
-pub async fn query_discriminators_endpoint(
-    db: web::Data<GraphDatabase>,
-    solana_client: web::Data<SolanaConnection>,
+// Optional `?cluster=` parameter accepted by every endpoint; the default cluster when absent
+#[derive(Debug, Deserialize)]
+pub struct ClusterQuery {
+    pub cluster: Option<String>,
+}
+
+// Optional `?kind=` filter shared by the query endpoints
+#[derive(Debug, Deserialize)]
+pub struct KindQuery {
+    pub kind: Option<DiscriminatorKind>,
+    pub cluster: Option<String>,
+}
+
+// The connection to the cluster a request names, or the 400 response for an unknown one
+pub fn resolve_cluster<'a>(clusters: &'a Clusters, name: Option<&str>) -> Result<&'a SolanaConnection, HttpResponse> {
+    clusters.get(name).ok_or_else(|| {
+        HttpResponse::BadRequest().json(json!({"error": format!("Unknown cluster: {}", name.unwrap_or_default())}))
+    })
+}
+
+pub async fn query_discriminators_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
     program_id: web::Path<String>,
-) -> impl Responder {
+    query: web::Query<KindQuery>,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
     let program_id = program_id.into_inner();
+    let kind = query.kind;
+    let solana_client = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client,
+        Err(response) => return response,
+    };
+    let cluster = solana_client.cluster();
 
     // Check if discriminators are in the database
-    let discriminators = db.query_discriminators_and_instructions(&program_id).await;
+    let discriminators = db.query_discriminators_and_instructions(cluster, &program_id, kind).await;
+
+    // The Solana fallback can only discover account discriminators
+    let can_fall_back = matches!(kind, None | Some(DiscriminatorKind::Account));
 
     match discriminators {
         Ok(discriminators) => {
-            if !discriminators.is_empty() {
+            if !discriminators.is_empty() || !can_fall_back {
                 // Return discriminators if found in the database
                 HttpResponse::Ok().json(discriminators)
             } else {
@@ -27,21 +72,21 @@ pub async fn query_discriminators_endpoint(
                     Ok(accounts) => {
                         let mut uploaded_any = false;
                         for (pub_key, account) in accounts {
-                            // Skip accounts with insufficient data
-                            if account.data.len() < 8 {
+                            // Extract the discriminator from account data, skipping accounts with insufficient data
+                            let Some((discriminator_data, instruction_data)) = ACCOUNT_SCHEME.split(&account.data) else {
                                 continue;
-                            }
-
-                            let data = &account.data;
-                            // Extract the discriminator from account data
-                            let discriminator_data = data[0..8].to_vec();
-                            let instruction_data = data[8..].to_vec();
+                            };
 
                             if let Err(e) = db.upload_discriminator(
+                                cluster,
                                 &program_id,
-                                discriminator_data,
-                                instruction_data,
+                                discriminator_data.to_vec(),
+                                instruction_data.to_vec(),
                                 &pub_key.to_string(),
+                                DiscriminatorMetadata {
+                                    kind: DiscriminatorKind::Account,
+                                    ..Default::default()
+                                },
                             ).await {
                                 error!("Error uploading discriminator: {}", e);
                                 return HttpResponse::InternalServerError().body(e.to_string());
@@ -51,7 +96,7 @@ pub async fn query_discriminators_endpoint(
                         }
 
                         if uploaded_any {
-                            let disc = db.query_discriminators_and_instructions(&program_id).await;
+                            let disc = db.query_discriminators_and_instructions(cluster, &program_id, kind).await;
                             match disc {
                                 Ok(disc) => HttpResponse::Ok().json(disc),
                                 Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
@@ -69,27 +114,49 @@ pub async fn query_discriminators_endpoint(
 }
 
 
-pub async fn upload_discriminator_endpoint(
-    db: web::Data<GraphDatabase>,
+pub async fn upload_discriminator_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
     program_id: web::Path<String>,
-    discriminator_info: web::Json<(String, String, String)>,
-    req: HttpRequest,
-) -> impl Responder {
+    query: web::Query<ClusterQuery>,
+    body: web::Json<serde_json::Value>,
+    contributor: Contributor,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
     let program_id = program_id.into_inner();
-    let (discriminator, instruction, _) = discriminator_info.into_inner();
+    let solana_client = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client,
+        Err(response) => return response,
+    };
+    let cluster = solana_client.cluster();
     info!("Uploading discriminator for program_id: {}", program_id);
 
-    // Extract user_id from the headers
-    let user_id = match req.headers().get("user_id") {
-        Some(value) => match value.to_str() {
-            Ok(v) => v.to_string(),
-            Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid user_id header value"})),
-        },
-        None => return HttpResponse::BadRequest().json(json!({"error": "Missing user_id header"})),
+    let upload: DiscriminatorUpload = match serde_json::from_value(body.into_inner()) {
+        Ok(upload) => upload,
+        Err(e) => return HttpResponse::BadRequest().json(json!({"error": format!("Invalid request body: {}", e)})),
+    };
+
+    let scheme = match db.get_discriminator_scheme(cluster, &program_id).await {
+        Ok(scheme) => scheme,
+        Err(e) => {
+            error!("Error looking up discriminator scheme: {}", e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to upload discriminator to DB"}));
+        }
+    };
+    let mut upload = match upload.validate(scheme) {
+        Ok(upload) => upload,
+        Err(fields) => return HttpResponse::BadRequest().json(json!({"error": "Invalid discriminator upload", "fields": fields})),
     };
+    upload.metadata.authoritative = contributor.is_authority(solana_client, &program_id).await;
 
-    match db.upload_discriminator(&program_id, discriminator.into_bytes(), instruction.into_bytes(), &user_id).await {
+    match db.upload_discriminator(cluster, &program_id, upload.discriminator_data, upload.instruction_data, &contributor.user_id, upload.metadata).await {
         Ok(_) => HttpResponse::Ok().json(json!({"status": "Discriminator uploaded successfully"})),
+        // The program's scheme changed since it was checked
+        Err(DatabaseError::DataParsingError(reason)) => {
+            HttpResponse::BadRequest().json(json!({"error": "Invalid discriminator upload", "fields": {"discriminator": reason}}))
+        },
         Err(e) => {
             error!("Error uploading discriminator to DB: {}", e);
             HttpResponse::InternalServerError().json(json!({"error": "Failed to upload discriminator to DB"}))
@@ -97,16 +164,220 @@ pub async fn upload_discriminator_endpoint(
     }
 }
 
+// Derive named discriminators from an Anchor IDL and store them
+pub async fn upload_idl_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
+    program_id: web::Path<String>,
+    query: web::Query<ClusterQuery>,
+    idl: web::Json<Idl>,
+    contributor: Contributor,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    let program_id = program_id.into_inner();
+    let solana_client = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client,
+        Err(response) => return response,
+    };
+    let cluster = solana_client.cluster();
+    let idl = idl.into_inner();
+    info!("Uploading IDL for program_id: {}", program_id);
+
+    let authoritative = contributor.is_authority(solana_client, &program_id).await;
+    match idl::ingest_idl(db.get_ref(), cluster, &program_id, &idl, &contributor.user_id, "idl", authoritative).await {
+        Ok(count) => HttpResponse::Ok().json(json!({
+            "status": "IDL uploaded successfully",
+            "program_name": idl.program_name(),
+            "discriminators": count,
+        })),
+        Err(e) => {
+            error!("Error uploading IDL discriminators to DB: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to upload IDL discriminators to DB"}))
+        }
+    }
+}
+
+// Re-fetch the on-chain Anchor IDL of every tracked program
+pub async fn sync_idls_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
+    query: web::Query<ClusterQuery>,
+    Admin(admin): Admin,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    let solana_client = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client,
+        Err(response) => return response,
+    };
+    info!("Syncing on-chain IDLs on {} on demand of {}", solana_client.cluster(), admin.user_id);
+
+    match solana_client.sync_program_idls(db.get_ref()).await {
+        Ok(count) => HttpResponse::Ok().json(json!({
+            "status": "IDL sync completed",
+            "programs_with_idl": count,
+        })),
+        Err(e) => {
+            error!("Error syncing on-chain IDLs: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to sync on-chain IDLs"}))
+        }
+    }
+}
+
+// Attach a user-submitted Borsh layout to a discriminator so its instructions can be decoded
+pub async fn upload_layout_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
+    path: web::Path<(String, String)>,
+    query: web::Query<ClusterQuery>,
+    args_layout: web::Json<ArgsLayout>,
+    contributor: Contributor,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    let (program_id, discriminator_id) = path.into_inner();
+    let solana_client = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client,
+        Err(response) => return response,
+    };
+    let cluster = solana_client.cluster();
+    info!("Uploading layout for discriminator {} of program {}", discriminator_id, program_id);
+
+    // Only the upgrade authority may change the layout of its own labels
+    match db.get_discriminator(cluster, &program_id, &discriminator_id).await {
+        Ok(Some(discriminator)) if discriminator.authoritative => {
+            if !contributor.is_authority(solana_client, &program_id).await {
+                return HttpResponse::Forbidden().json(json!({"error": "The discriminator is labelled by the program's upgrade authority"}));
+            }
+        },
+        Ok(_) => {},
+        Err(e) => {
+            error!("Error looking up discriminator: {}", e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to upload layout to DB"}));
+        }
+    }
+
+    match db.set_args_layout(cluster, &program_id, &discriminator_id, args_layout.into_inner()).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"status": "Layout uploaded successfully"})),
+        Err(DatabaseError::NotFound(what)) => {
+            HttpResponse::NotFound().json(json!({"error": format!("Unknown {}", what)}))
+        },
+        Err(e) => {
+            error!("Error uploading layout to DB: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to upload layout to DB"}))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SchemeUpload {
+    pub scheme: DiscriminatorScheme,
+}
+
+// Declare how a program encodes its discriminators. The scheme decides which uploads are
+// accepted, so only the program's upgrade authority or an admin may set it.
+pub async fn upload_scheme_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
+    program_id: web::Path<String>,
+    query: web::Query<ClusterQuery>,
+    upload: web::Json<SchemeUpload>,
+    contributor: Contributor,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    let program_id = program_id.into_inner();
+    let solana_client = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client,
+        Err(response) => return response,
+    };
+    let cluster = solana_client.cluster();
+
+    if let Some(known) = DiscriminatorScheme::known(&program_id) {
+        return HttpResponse::Conflict().json(json!({
+            "error": format!("Program {} is preconfigured with the {} scheme", program_id, known)
+        }));
+    }
+    if !contributor.is_admin() && !contributor.is_authority(solana_client, &program_id).await {
+        return HttpResponse::Forbidden().json(json!({
+            "error": "Only the program's upgrade authority or an admin can set its scheme"
+        }));
+    }
+    info!("{} is setting the discriminator scheme of program {} to {}", contributor.user_id, program_id, upload.scheme);
+
+    match db.set_discriminator_scheme(cluster, &program_id, upload.scheme).await {
+        Ok(_) => HttpResponse::Ok().json(json!({"status": "Scheme uploaded successfully"})),
+        Err(e) => {
+            error!("Error uploading scheme to DB: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to upload scheme to DB"}))
+        }
+    }
+}
+
+pub async fn query_scheme_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
+    program_id: web::Path<String>,
+    query: web::Query<ClusterQuery>,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    let program_id = program_id.into_inner();
+    let cluster = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client.cluster(),
+        Err(response) => return response,
+    };
+
+    match db.get_discriminator_scheme(cluster, &program_id).await {
+        Ok(scheme) => HttpResponse::Ok().json(json!({
+            "cluster": cluster,
+            "program_id": program_id,
+            "scheme": scheme,
+        })),
+        Err(e) => {
+            error!("Error querying scheme: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to query scheme"}))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InstructionsQuery {
+    #[serde(default)]
+    pub decode: bool,
+    pub kind: Option<DiscriminatorKind>,
+    pub cluster: Option<String>,
+}
+
 // New endpoint to query instructions by discriminator ID
-pub async fn query_instructions_endpoint(
-    db: web::Data<GraphDatabase>,
+pub async fn query_instructions_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
     discriminator_id: web::Path<String>,
-) -> impl Responder {
+    query: web::Query<InstructionsQuery>,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
     let discriminator_id = discriminator_id.into_inner();
     info!("Querying instructions for discriminator_id: {}", discriminator_id);
+    let cluster = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client.cluster(),
+        Err(response) => return response,
+    };
+
+    if query.decode {
+        return decode_instructions(db.get_ref(), cluster, &discriminator_id, query.kind).await;
+    }
     
     // Query the database for instructions with this discriminator ID
-    match db.query_instructions_by_discriminator(&discriminator_id).await {
+    match db.query_instructions_by_discriminator(cluster, &discriminator_id, query.kind).await {
         Ok(instructions) => {
             HttpResponse::Ok().json(instructions)
         },
@@ -116,3 +387,492 @@ pub async fn query_instructions_endpoint(
         }
     }
 }
+
+// Decode the stored instruction data of a discriminator with its known layout
+async fn decode_instructions<T>(db: &T, cluster: &str, discriminator_id: &str, kind: Option<DiscriminatorKind>) -> HttpResponse
+where
+    T: DatabaseInterface + ?Sized,
+{
+    let records = match db.query_instruction_records(cluster, discriminator_id, kind).await {
+        Ok(records) => records,
+        Err(e) => {
+            error!("Error querying instruction records: {}", e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to query instructions"}));
+        }
+    };
+
+    let decoded: Vec<_> = records.iter()
+        .map(|record| {
+            let mut entry = json!({
+                "program_id": record.program_id,
+                "discriminator_id": record.discriminator_id,
+                "name": record.name,
+                "instruction_data": hex::encode(&record.instruction_data),
+            });
+
+            match &record.args_layout {
+                Some(layout) => match decode_args(layout, &record.instruction_data) {
+                    Ok(args) => entry["args"] = args,
+                    Err(e) => entry["error"] = json!(format!("Instruction data does not match layout: {}", e)),
+                },
+                None => entry["error"] = json!("No layout known for this discriminator"),
+            }
+
+            entry
+        })
+        .collect();
+
+    HttpResponse::Ok().json(decoded)
+}
+
+// Fetch an account and decode it with the account discriminator known for its owner program
+pub async fn decode_account_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
+    pubkey: web::Path<String>,
+    query: web::Query<ClusterQuery>,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    let pubkey = pubkey.into_inner();
+    info!("Decoding account {}", pubkey);
+    let solana_client = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client,
+        Err(response) => return response,
+    };
+
+    let address = match Pubkey::from_str(&pubkey) {
+        Ok(address) => address,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid account pubkey"})),
+    };
+
+    let account = match solana_client.get_account(&address).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return HttpResponse::NotFound().json(json!({"error": "Account not found"})),
+        Err(e) => {
+            error!("Error fetching account {}: {}", pubkey, e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to fetch account"}));
+        }
+    };
+
+    let owner = account.owner.to_string();
+    let Some((discriminator_data, account_data)) = ACCOUNT_SCHEME.split(&account.data) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Account data is too short to carry a discriminator",
+            "owner": owner,
+        }));
+    };
+
+    let discriminator_id = hex::encode(discriminator_data);
+    // Only account discriminators (or ones of unknown kind) can describe account data
+    let discriminator = match db.get_discriminator(solana_client.cluster(), &owner, &discriminator_id).await {
+        Ok(Some(discriminator)) if matches!(
+            discriminator.kind,
+            DiscriminatorKind::Account | DiscriminatorKind::Unknown
+        ) => discriminator,
+        Ok(_) => return HttpResponse::NotFound().json(json!({
+            "error": "No known account discriminator for this account",
+            "owner": owner,
+            "discriminator_id": discriminator_id,
+        })),
+        Err(e) => {
+            error!("Error querying discriminator {}: {}", discriminator_id, e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to query discriminator"}));
+        }
+    };
+
+    let mut response = json!({
+        "pubkey": pubkey,
+        "owner": owner,
+        "discriminator_id": discriminator_id,
+        "name": discriminator.name,
+        "kind": discriminator.kind,
+        "data": null,
+    });
+
+    // The type name is still useful when the layout is unknown
+    match &discriminator.args_layout {
+        Some(layout) => match decode_account_data(layout, account_data) {
+            Ok(data) => response["data"] = data,
+            Err(e) => response["error"] = json!(format!("Account data does not match layout: {}", e)),
+        },
+        None => response["error"] = json!("No layout known for this account type"),
+    }
+
+    HttpResponse::Ok().json(response)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillQuery {
+    pub stop_slot: Option<u64>,
+    pub cluster: Option<String>,
+}
+
+// Start, or resume, a historical backfill of a program's signatures
+pub async fn start_backfill_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
+    program_id: web::Path<String>,
+    query: web::Query<BackfillQuery>,
+    Admin(admin): Admin,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    let program_id = program_id.into_inner();
+    let solana_client = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client,
+        Err(response) => return response,
+    };
+    let cluster = solana_client.cluster();
+    info!("Starting backfill for program {} on {} on demand of {}", program_id, cluster, admin.user_id);
+
+    if Pubkey::from_str(&program_id).is_err() {
+        return HttpResponse::BadRequest().json(json!({"error": "Invalid program ID"}));
+    }
+
+    let job = match next_backfill_job(db.get_ref(), cluster, &program_id, query.stop_slot).await {
+        Ok(job) => job,
+        Err(e) => {
+            error!("Error loading backfill job: {}", e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to load backfill job"}));
+        }
+    };
+
+    if !spawn_backfill(solana_client, db.into_inner(), job.clone()) {
+        return HttpResponse::Conflict().json(json!({"error": "A backfill is already running for this program"}));
+    }
+
+    HttpResponse::Accepted().json(job)
+}
+
+// Report the progress of a program's backfill
+pub async fn backfill_status_endpoint<T>(
+    db: web::Data<T>,
+    clusters: web::Data<Clusters>,
+    program_id: web::Path<String>,
+    query: web::Query<ClusterQuery>,
+    _admin: Admin,
+) -> impl Responder
+where
+    T: DatabaseInterface + ?Sized + 'static,
+{
+    let program_id = program_id.into_inner();
+    let cluster = match resolve_cluster(&clusters, query.cluster.as_deref()) {
+        Ok(solana_client) => solana_client.cluster(),
+        Err(response) => return response,
+    };
+
+    match db.get_backfill_job(cluster, &program_id).await {
+        Ok(Some(job)) => HttpResponse::Ok().json(job),
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": "No backfill job for this program"})),
+        Err(e) => {
+            error!("Error loading backfill job: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to load backfill job"}))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use actix_web::{test, App};
+    use crate::auth::{generate_api_key, hash_api_key, API_KEY_HEADER};
+    use crate::chain_fixture::{ChainFixture, FixtureChainSource};
+    use crate::graph_disc::Discriminator;
+    use crate::memory_db::MemoryDatabase;
+
+    #[actix_web::test]
+    async fn stores_account_discriminators_found_on_chain() {
+        let db: Arc<dyn DatabaseInterface> = Arc::new(MemoryDatabase::new());
+        let source = FixtureChainSource::load(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/chain.json")).unwrap();
+        let solana_client = SolanaConnection::with_source(Arc::new(source));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![solana_client])))
+                .route("/query_discriminators/{program_id}", web::get().to(query_discriminators_endpoint::<dyn DatabaseInterface>))
+        ).await;
+
+        let program_id = Pubkey::new_from_array([7; 32]).to_string();
+        let request = test::TestRequest::get()
+            .uri(&format!("/query_discriminators/{}", program_id))
+            .to_request();
+        let discriminators: Vec<Discriminator> = test::call_and_read_body_json(&app, request).await;
+
+        assert_eq!(discriminators.len(), 1);
+        assert_eq!(discriminators[0].discriminator_id, "0102030405060708");
+        assert_eq!(discriminators[0].kind, DiscriminatorKind::Account);
+        assert_eq!(discriminators[0].instruction.instruction_data, vec![9]);
+        assert_eq!(db.get_all_program_ids("mainnet").await.unwrap(), vec![program_id.clone()]);
+        assert!(db.get_all_program_ids("devnet").await.unwrap().is_empty());
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/query_discriminators/{}?cluster=devnet", program_id))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn uploads_decoded_discriminators_and_reports_field_errors() {
+        let db: Arc<dyn DatabaseInterface> = Arc::new(MemoryDatabase::new());
+        let api_key = generate_api_key();
+        db.create_api_key(&hash_api_key(&api_key), "bot", "bot", false).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![SolanaConnection::new("http://127.0.0.1:8899")])))
+                .route("/upload_discriminator/{program_id}", web::post().to(upload_discriminator_endpoint::<dyn DatabaseInterface>))
+        ).await;
+        let upload = |program_id: &str, body: serde_json::Value| test::TestRequest::post()
+            .uri(&format!("/upload_discriminator/{}", program_id))
+            .insert_header((API_KEY_HEADER, api_key.clone()))
+            .set_json(body)
+            .to_request();
+
+        let response = test::call_service(&app, upload("program", json!({
+            "discriminator": "af af 6d 1f 0d 98 9b ed",
+            "name": "swap",
+            "kind": "instruction",
+        }))).await;
+        assert!(response.status().is_success());
+        let stored = db.get_discriminator("mainnet", "program", "afaf6d1f0d989bed").await.unwrap().unwrap();
+        assert_eq!(stored.name.as_deref(), Some("swap"));
+
+        // SPL Token tags are a single byte
+        let token_program = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+        let response: serde_json::Value = test::call_and_read_body_json(&app, upload(token_program, json!({
+            "discriminator": "AQIDBAUGBwg=",
+            "encoding": "base64",
+            "kind": "method",
+        }))).await;
+        assert!(response["fields"]["discriminator"].as_str().unwrap().contains("u8 scheme"));
+        assert!(response["fields"]["kind"].is_string());
+
+        let response = test::call_service(&app, upload("program", json!(["abcdefgh", "data", ""]))).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        // Unauthenticated uploads are refused
+        let request = test::TestRequest::post()
+            .uri("/upload_discriminator/program")
+            .insert_header(("user_id", "user"))
+            .set_json(json!({"discriminator": "0102030405060708"}))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn only_the_authority_or_an_admin_sets_a_scheme() {
+        let db: Arc<dyn DatabaseInterface> = Arc::new(MemoryDatabase::new());
+        let upload_key = generate_api_key();
+        let admin_key = generate_api_key();
+        db.create_api_key(&hash_api_key(&upload_key), "bot", "bot", false).await.unwrap();
+        db.create_api_key(&hash_api_key(&admin_key), "ops", "ops", true).await.unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![SolanaConnection::new("http://127.0.0.1:8899")])))
+                .route("/upload_scheme/{program_id}", web::post().to(upload_scheme_endpoint::<dyn DatabaseInterface>))
+        ).await;
+        let upload = |program_id: &str, api_key: &str| test::TestRequest::post()
+            .uri(&format!("/upload_scheme/{}", program_id))
+            .insert_header((API_KEY_HEADER, api_key.to_string()))
+            .set_json(json!({"scheme": "u8"}))
+            .to_request();
+
+        let response = test::call_service(&app, upload("program", &upload_key)).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::FORBIDDEN);
+        assert_eq!(db.get_discriminator_scheme("mainnet", "program").await.unwrap(), DiscriminatorScheme::Anchor);
+
+        let response = test::call_service(&app, upload("program", &admin_key)).await;
+        assert!(response.status().is_success());
+        assert_eq!(db.get_discriminator_scheme("mainnet", "program").await.unwrap(), DiscriminatorScheme::U8);
+
+        // Not even admins override the scheme of a native program
+        let response = test::call_service(&app, upload("11111111111111111111111111111111", &admin_key)).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn filters_discriminators_by_kind() {
+        let db: Arc<dyn DatabaseInterface> = Arc::new(MemoryDatabase::new());
+        let solana_client = SolanaConnection::with_source(Arc::new(FixtureChainSource::new(Default::default())));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![solana_client])))
+                .route("/query_discriminators/{program_id}", web::get().to(query_discriminators_endpoint::<dyn DatabaseInterface>))
+                .route("/query_instructions/{discriminator_id}", web::get().to(query_instructions_endpoint::<dyn DatabaseInterface>))
+        ).await;
+
+        let program_id = Pubkey::new_unique().to_string();
+        let initialize = idl::sighash("global", "initialize").to_vec();
+        let upload = |discriminator_data: Vec<u8>, kind: DiscriminatorKind| {
+            let db = db.clone();
+            let program_id = program_id.clone();
+            async move {
+                let metadata = DiscriminatorMetadata { kind, ..Default::default() };
+                db.upload_discriminator("mainnet", &program_id, discriminator_data, vec![], "user", metadata).await.unwrap();
+            }
+        };
+        // Stored before kinds were tracked, then named by an IDL
+        upload(initialize.clone(), DiscriminatorKind::Unknown).await;
+        upload(vec![1; 8], DiscriminatorKind::Unknown).await;
+        upload(vec![2; 8], DiscriminatorKind::Instruction).await;
+        upload(vec![3; 8], DiscriminatorKind::Account).await;
+        upload(vec![4; 8], DiscriminatorKind::Event).await;
+        let idl: Idl = serde_json::from_value(json!({
+            "version": "0.1.0",
+            "name": "counter",
+            "instructions": [{ "name": "initialize" }],
+        })).unwrap();
+        idl::ingest_idl(db.as_ref(), "mainnet", &program_id, &idl, "user", "idl", false).await.unwrap();
+
+        let query = |kind: &str| test::TestRequest::get()
+            .uri(&format!("/query_discriminators/{}?kind={}", program_id, kind))
+            .to_request();
+        let ids = |discriminators: Vec<Discriminator>| {
+            discriminators.into_iter().map(|d| d.discriminator_id).collect::<Vec<_>>()
+        };
+        let instructions: Vec<Discriminator> = test::call_and_read_body_json(&app, query("instruction")).await;
+        assert_eq!(ids(instructions), vec!["0202020202020202".to_string(), hex::encode(&initialize)]);
+        let accounts: Vec<Discriminator> = test::call_and_read_body_json(&app, query("account")).await;
+        assert_eq!(ids(accounts), vec!["0303030303030303"]);
+        let events: Vec<Discriminator> = test::call_and_read_body_json(&app, query("event")).await;
+        assert_eq!(ids(events), vec!["0404040404040404"]);
+        let unknown: Vec<Discriminator> = test::call_and_read_body_json(&app, query("unknown")).await;
+        assert_eq!(ids(unknown), vec!["0101010101010101"]);
+        let response = test::call_service(&app, query("program")).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let instructions = |kind: &str| test::TestRequest::get()
+            .uri(&format!("/query_instructions/0303030303030303?kind={}", kind))
+            .to_request();
+        let found: Vec<serde_json::Value> = test::call_and_read_body_json(&app, instructions("account")).await;
+        assert_eq!(found.len(), 1);
+        let found: Vec<serde_json::Value> = test::call_and_read_body_json(&app, instructions("instruction")).await;
+        assert!(found.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn decodes_instructions_observed_before_their_idl() {
+        let db: Arc<dyn DatabaseInterface> = Arc::new(MemoryDatabase::new());
+        let solana_client = SolanaConnection::with_source(Arc::new(FixtureChainSource::new(Default::default())));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![solana_client])))
+                .route("/query_instructions/{discriminator_id}", web::get().to(query_instructions_endpoint::<dyn DatabaseInterface>))
+        ).await;
+
+        // The listener stores the instruction before anyone uploads the program's IDL
+        let program_id = Pubkey::new_unique().to_string();
+        let discriminator = idl::sighash("global", "increment_by").to_vec();
+        let instruction_data = 5u64.to_le_bytes().to_vec();
+        let metadata = DiscriminatorMetadata { kind: DiscriminatorKind::Instruction, ..Default::default() };
+        db.upload_discriminator("mainnet", &program_id, discriminator.clone(), instruction_data.clone(), "signature", metadata).await.unwrap();
+        let idl: Idl = serde_json::from_value(json!({
+            "version": "0.1.0",
+            "name": "counter",
+            "instructions": [{ "name": "incrementBy", "accounts": [], "args": [{ "name": "amount", "type": "u64" }] }],
+        })).unwrap();
+        idl::ingest_idl(db.as_ref(), "mainnet", &program_id, &idl, "user", "idl", false).await.unwrap();
+
+        let request = test::TestRequest::get()
+            .uri(&format!("/query_instructions/{}?decode=true", hex::encode(&discriminator)))
+            .to_request();
+        let decoded: Vec<serde_json::Value> = test::call_and_read_body_json(&app, request).await;
+
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0]["name"], "incrementBy");
+        assert_eq!(decoded[0]["instruction_data"], hex::encode(&instruction_data));
+        assert_eq!(decoded[0]["args"], json!({ "amount": 5 }));
+    }
+
+    #[actix_web::test]
+    async fn decodes_accounts_by_their_owner_discriminator() {
+        let program_id = Pubkey::new_unique();
+        let keyed = |address: &Pubkey, data: Vec<u8>| serde_json::from_value(json!({
+            "pubkey": address.to_string(),
+            "account": {
+                "data": [base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &data), "base64"],
+                "executable": false,
+                "lamports": 1_000_000,
+                "owner": program_id.to_string(),
+                "rentEpoch": 0,
+                "space": data.len(),
+            },
+        })).unwrap();
+        let counter = Pubkey::new_unique();
+        let untyped = Pubkey::new_unique();
+        let short = Pubkey::new_unique();
+        let mistaken = Pubkey::new_unique();
+        let fixture = ChainFixture {
+            accounts: vec![
+                keyed(&counter, [vec![3; 8], 42u64.to_le_bytes().to_vec()].concat()),
+                keyed(&untyped, vec![5; 9]),
+                keyed(&short, vec![3; 4]),
+                keyed(&mistaken, vec![2; 8]),
+            ],
+            ..Default::default()
+        };
+        let solana_client = SolanaConnection::with_source(Arc::new(FixtureChainSource::new(fixture)));
+
+        let db: Arc<dyn DatabaseInterface> = Arc::new(MemoryDatabase::new());
+        let layout: ArgsLayout = serde_json::from_value(json!({"fields": [{"name": "count", "type": "u64"}]})).unwrap();
+        for (discriminator_data, name, kind, args_layout) in [
+            (vec![3; 8], "Counter", DiscriminatorKind::Account, Some(layout)),
+            (vec![5; 8], "Untyped", DiscriminatorKind::Account, None),
+            (vec![2; 8], "increment", DiscriminatorKind::Instruction, None),
+        ] {
+            let metadata = DiscriminatorMetadata { name: Some(name.to_string()), kind, args_layout, ..Default::default() };
+            db.upload_discriminator("mainnet", &program_id.to_string(), discriminator_data, vec![], "user", metadata).await.unwrap();
+        }
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(db.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![solana_client])))
+                .route("/decode_account/{pubkey}", web::get().to(decode_account_endpoint::<dyn DatabaseInterface>))
+        ).await;
+        let decode = |address: &Pubkey| {
+            let request = test::TestRequest::get().uri(&format!("/decode_account/{}", address)).to_request();
+            let app = &app;
+            async move {
+                let response = test::call_service(app, request).await;
+                let status = response.status();
+                (status, test::read_body_json::<serde_json::Value, _>(response).await)
+            }
+        };
+
+        let (status, body) = decode(&counter).await;
+        assert_eq!(status, actix_web::http::StatusCode::OK);
+        assert_eq!(body["owner"], program_id.to_string());
+        assert_eq!(body["name"], "Counter");
+        assert_eq!(body["data"]["count"], 42);
+
+        // The type is named even when its layout is unknown
+        let (status, body) = decode(&untyped).await;
+        assert_eq!(status, actix_web::http::StatusCode::OK);
+        assert_eq!(body["name"], "Untyped");
+        assert!(body["data"].is_null());
+        assert!(body["error"].is_string());
+
+        let (status, body) = decode(&short).await;
+        assert_eq!(status, actix_web::http::StatusCode::NOT_FOUND);
+        assert_eq!(body["owner"], program_id.to_string());
+        assert!(body.get("discriminator_id").is_none());
+
+        // An instruction tag does not describe account data
+        let (status, body) = decode(&mistaken).await;
+        assert_eq!(status, actix_web::http::StatusCode::NOT_FOUND);
+        assert_eq!(body["discriminator_id"], "0202020202020202");
+
+        let (status, _) = decode(&Pubkey::new_unique()).await;
+        assert_eq!(status, actix_web::http::StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,241 @@
+use std::sync::{Arc, OnceLock, RwLock};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::graph_disc::{
+    ApiKey, BackfillJob, DatabaseError, DatabaseInterface, Discriminator, DiscriminatorKind, DiscriminatorMetadata,
+    InstructionRecord, ProgramCursor,
+};
+use crate::idl::ArgsLayout;
+use crate::scheme::DiscriminatorScheme;
+use crate::status::DatabaseHealth;
+
+// Where the service is in bringing up its database
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ReadinessState {
+    // Still connecting; `attempts` have failed so far
+    Connecting { attempts: u32, last_error: Option<String> },
+    Ready,
+    // The database cannot be used by this build, e.g. its schema is newer
+    Failed { error: String },
+}
+
+// Database shared by the HTTP API and the task that connects to it in the background.
+// Until the database is set every call fails with `DatabaseError::Unavailable`, and
+// `require_database` answers requests with 503 instead of reaching the handlers.
+pub struct Readiness {
+    state: RwLock<ReadinessState>,
+    database: OnceLock<Arc<dyn DatabaseInterface>>,
+    health: OnceLock<Arc<dyn DatabaseHealth>>,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Readiness {
+            state: RwLock::new(ReadinessState::Connecting { attempts: 0, last_error: None }),
+            database: OnceLock::new(),
+            health: OnceLock::new(),
+        }
+    }
+
+    pub fn state(&self) -> ReadinessState {
+        self.state.read().unwrap().clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.database.get().is_some()
+    }
+
+    pub fn record_failure(&self, error: String) {
+        let mut state = self.state.write().unwrap();
+        if let ReadinessState::Connecting { attempts, .. } = *state {
+            *state = ReadinessState::Connecting { attempts: attempts + 1, last_error: Some(error) };
+        }
+    }
+
+    pub fn fail(&self, error: String) {
+        *self.state.write().unwrap() = ReadinessState::Failed { error };
+    }
+
+    // Health checks of the connected database, once there is one
+    pub fn health(&self) -> Option<&dyn DatabaseHealth> {
+        self.health.get().map(|health| health.as_ref())
+    }
+
+    pub fn set_ready(&self, database: Arc<dyn DatabaseInterface>, health: Arc<dyn DatabaseHealth>) {
+        if self.database.set(database).is_ok() {
+            let _ = self.health.set(health);
+            *self.state.write().unwrap() = ReadinessState::Ready;
+        }
+    }
+
+    fn get(&self) -> Result<&dyn DatabaseInterface, DatabaseError> {
+        self.database.get().map(|database| database.as_ref()).ok_or(DatabaseError::Unavailable)
+    }
+}
+
+// Answer 503 until the database is connected
+pub async fn require_database<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let ready = req.app_data::<web::Data<Readiness>>().is_some_and(|readiness| readiness.is_ready());
+    if ready {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let response = HttpResponse::ServiceUnavailable().json(json!({"error": "Database not available"}));
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+#[async_trait]
+impl DatabaseInterface for Readiness {
+    async fn upload_discriminator(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        instruction_data: Vec<u8>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        self.get()?.upload_discriminator(cluster, program_id, discriminator_data, instruction_data, user_id, metadata).await
+    }
+
+    async fn set_discriminator_metadata(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        self.get()?.set_discriminator_metadata(cluster, program_id, discriminator_data, user_id, metadata).await
+    }
+
+    async fn query_discriminators_and_instructions(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<Discriminator>, DatabaseError> {
+        self.get()?.query_discriminators_and_instructions(cluster, program_id, kind).await
+    }
+
+    async fn get_all_program_ids(&self, cluster: &str) -> Result<Vec<String>, DatabaseError> {
+        self.get()?.get_all_program_ids(cluster).await
+    }
+
+    async fn query_instructions_by_discriminator(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<String>, DatabaseError> {
+        self.get()?.query_instructions_by_discriminator(cluster, discriminator_id, kind).await
+    }
+
+    async fn get_discriminator(&self, cluster: &str, program_id: &str, discriminator_id: &str) -> Result<Option<Discriminator>, DatabaseError> {
+        self.get()?.get_discriminator(cluster, program_id, discriminator_id).await
+    }
+
+    async fn query_instruction_records(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<InstructionRecord>, DatabaseError> {
+        self.get()?.query_instruction_records(cluster, discriminator_id, kind).await
+    }
+
+    async fn get_program_cursor(&self, cluster: &str, program_id: &str) -> Result<Option<ProgramCursor>, DatabaseError> {
+        self.get()?.get_program_cursor(cluster, program_id).await
+    }
+
+    async fn update_program_cursor(&self, cluster: &str, program_id: &str, signature: &str, slot: u64) -> Result<(), DatabaseError> {
+        self.get()?.update_program_cursor(cluster, program_id, signature, slot).await
+    }
+
+    async fn get_backfill_job(&self, cluster: &str, program_id: &str) -> Result<Option<BackfillJob>, DatabaseError> {
+        self.get()?.get_backfill_job(cluster, program_id).await
+    }
+
+    async fn get_running_backfill_jobs(&self) -> Result<Vec<BackfillJob>, DatabaseError> {
+        self.get()?.get_running_backfill_jobs().await
+    }
+
+    async fn save_backfill_job(&self, job: &BackfillJob) -> Result<(), DatabaseError> {
+        self.get()?.save_backfill_job(job).await
+    }
+
+    async fn set_args_layout(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_id: &str,
+        args_layout: ArgsLayout,
+    ) -> Result<(), DatabaseError> {
+        self.get()?.set_args_layout(cluster, program_id, discriminator_id, args_layout).await
+    }
+
+    async fn get_discriminator_scheme(&self, cluster: &str, program_id: &str) -> Result<DiscriminatorScheme, DatabaseError> {
+        self.get()?.get_discriminator_scheme(cluster, program_id).await
+    }
+
+    async fn set_discriminator_scheme(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        scheme: DiscriminatorScheme,
+    ) -> Result<(), DatabaseError> {
+        self.get()?.set_discriminator_scheme(cluster, program_id, scheme).await
+    }
+
+    async fn create_api_key(&self, key_hash: &str, name: &str, user_id: &str, admin: bool) -> Result<(), DatabaseError> {
+        self.get()?.create_api_key(key_hash, name, user_id, admin).await
+    }
+
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, DatabaseError> {
+        self.get()?.get_api_key(key_hash).await
+    }
+
+    async fn revoke_api_keys(&self, name: &str) -> Result<u64, DatabaseError> {
+        self.get()?.revoke_api_keys(name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_db::MemoryDatabase;
+
+    #[tokio::test]
+    async fn fails_calls_until_the_database_is_connected() {
+        let readiness = Readiness::new();
+        assert!(matches!(readiness.get_all_program_ids("devnet").await, Err(DatabaseError::Unavailable)));
+
+        readiness.record_failure("connection refused".to_string());
+        assert_eq!(readiness.state(), ReadinessState::Connecting {
+            attempts: 1,
+            last_error: Some("connection refused".to_string()),
+        });
+
+        let database = Arc::new(MemoryDatabase::new());
+        readiness.set_ready(database.clone(), database);
+        assert_eq!(readiness.state(), ReadinessState::Ready);
+        assert!(readiness.get_all_program_ids("devnet").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph_disc::{DatabaseError, DiscriminatorKind};
+
+// How a program's instruction data starts: the length and encoding of its discriminator
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscriminatorScheme {
+    // 8-byte sighash prefix written by Anchor
+    #[default]
+    Anchor,
+    // 1-byte tag: SPL Token, Compute Budget, Shank and Steel programs
+    U8,
+    // 4-byte little-endian tag: bincode-encoded native programs such as System and Stake
+    U32Le,
+    // Variable-length unsigned LEB128 tag of 1 to 5 bytes
+    Uleb128,
+    // No discriminator: the whole instruction data is the argument, as with Memo
+    Untagged,
+}
+
+// Longest LEB128 encoding of a u32 tag
+const MAX_ULEB128_LEN: usize = 5;
+
+// How account and event data starts, whatever the program's instruction scheme: with the
+// 8-byte prefix Anchor writes
+pub const ACCOUNT_SCHEME: DiscriminatorScheme = DiscriminatorScheme::Anchor;
+
+// Native and SPL programs that do not use Anchor discriminators
+const KNOWN_SCHEMES: &[(&str, DiscriminatorScheme)] = &[
+    ("11111111111111111111111111111111", DiscriminatorScheme::U32Le),
+    ("Stake11111111111111111111111111111111111111", DiscriminatorScheme::U32Le),
+    ("Vote111111111111111111111111111111111111111", DiscriminatorScheme::U32Le),
+    ("BPFLoader2111111111111111111111111111111111", DiscriminatorScheme::U32Le),
+    ("BPFLoaderUpgradeab1e11111111111111111111111", DiscriminatorScheme::U32Le),
+    ("LoaderV411111111111111111111111111111111111", DiscriminatorScheme::U32Le),
+    ("AddressLookupTab1e1111111111111111111111111", DiscriminatorScheme::U32Le),
+    ("ComputeBudget111111111111111111111111111111", DiscriminatorScheme::U8),
+    ("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", DiscriminatorScheme::U8),
+    ("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb", DiscriminatorScheme::U8),
+    ("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL", DiscriminatorScheme::U8),
+    ("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr", DiscriminatorScheme::Untagged),
+    ("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo", DiscriminatorScheme::Untagged),
+];
+
+impl DiscriminatorScheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscriminatorScheme::Anchor => "anchor",
+            DiscriminatorScheme::U8 => "u8",
+            DiscriminatorScheme::U32Le => "u32_le",
+            DiscriminatorScheme::Uleb128 => "uleb128",
+            DiscriminatorScheme::Untagged => "untagged",
+        }
+    }
+
+    // The preconfigured scheme of a native or SPL program
+    pub fn known(program_id: &str) -> Option<Self> {
+        KNOWN_SCHEMES.iter()
+            .find(|(known, _)| *known == program_id)
+            .map(|(_, scheme)| *scheme)
+    }
+
+    // A program's scheme: the one stored for it, else its preconfigured one, else Anchor
+    pub fn for_program(program_id: &str, stored: Option<Self>) -> Self {
+        stored.or_else(|| Self::known(program_id)).unwrap_or_default()
+    }
+
+    // The scheme of a program's discriminators of `kind`, `self` being its instruction scheme.
+    // Only instruction tags vary between programs; discriminators of unknown kind are most
+    // often tags.
+    pub fn for_kind(self, kind: DiscriminatorKind) -> Self {
+        match kind {
+            DiscriminatorKind::Account | DiscriminatorKind::Event => ACCOUNT_SCHEME,
+            DiscriminatorKind::Instruction | DiscriminatorKind::Unknown => self,
+        }
+    }
+
+    // Length of the discriminator at the start of `data`, if `data` starts with a whole one
+    fn prefix_len(&self, data: &[u8]) -> Option<usize> {
+        let len = match self {
+            DiscriminatorScheme::Anchor => 8,
+            DiscriminatorScheme::U8 => 1,
+            DiscriminatorScheme::U32Le => 4,
+            // Every byte but the last has its continuation bit set
+            DiscriminatorScheme::Uleb128 => data.iter()
+                .take(MAX_ULEB128_LEN)
+                .position(|byte| byte & 0x80 == 0)?
+                + 1,
+            DiscriminatorScheme::Untagged => 0,
+        };
+        (data.len() >= len).then_some(len)
+    }
+
+    // Discriminator of the instruction with numeric tag `tag`; Anchor discriminators are not numbered
+    pub fn encode_tag(&self, tag: u32) -> Option<Vec<u8>> {
+        match self {
+            DiscriminatorScheme::Anchor => None,
+            DiscriminatorScheme::U8 => u8::try_from(tag).ok().map(|tag| vec![tag]),
+            DiscriminatorScheme::U32Le => Some(tag.to_le_bytes().to_vec()),
+            DiscriminatorScheme::Uleb128 => {
+                let mut encoded = Vec::new();
+                let mut rest = tag;
+                loop {
+                    let byte = (rest & 0x7f) as u8;
+                    rest >>= 7;
+                    if rest == 0 {
+                        encoded.push(byte);
+                        return Some(encoded);
+                    }
+                    encoded.push(byte | 0x80);
+                }
+            },
+            DiscriminatorScheme::Untagged => (tag == 0).then(Vec::new),
+        }
+    }
+
+    // Split instruction or account data into its discriminator and the bytes that follow
+    pub fn split<'a>(&self, data: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+        self.prefix_len(data).map(|len| data.split_at(len))
+    }
+
+    // Check that `discriminator_data` is exactly one discriminator of this scheme
+    pub fn validate(&self, discriminator_data: &[u8]) -> Result<(), DatabaseError> {
+        if self.prefix_len(discriminator_data) == Some(discriminator_data.len()) {
+            return Ok(());
+        }
+
+        let expected = match self {
+            DiscriminatorScheme::Anchor => "8 bytes",
+            DiscriminatorScheme::U8 => "1 byte",
+            DiscriminatorScheme::U32Le => "4 bytes",
+            DiscriminatorScheme::Uleb128 => "a LEB128 tag of 1 to 5 bytes",
+            DiscriminatorScheme::Untagged => "no bytes",
+        };
+        Err(DatabaseError::DataParsingError(format!(
+            "Invalid discriminator data length: {}, expected {} for the {} scheme",
+            discriminator_data.len(), expected, self
+        )))
+    }
+}
+
+impl fmt::Display for DiscriminatorScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DiscriminatorScheme {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anchor" => Ok(DiscriminatorScheme::Anchor),
+            "u8" => Ok(DiscriminatorScheme::U8),
+            "u32_le" => Ok(DiscriminatorScheme::U32Le),
+            "uleb128" => Ok(DiscriminatorScheme::Uleb128),
+            "untagged" => Ok(DiscriminatorScheme::Untagged),
+            other => Err(DatabaseError::DataParsingError(format!("Unknown discriminator scheme: {}", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_data_by_scheme() {
+        let data = [0x83, 0x01, 7, 7, 7, 7, 7, 7, 7];
+        assert_eq!(DiscriminatorScheme::Anchor.split(&data), Some((&data[..8], &data[8..])));
+        assert_eq!(DiscriminatorScheme::U8.split(&data), Some((&data[..1], &data[1..])));
+        assert_eq!(DiscriminatorScheme::U32Le.split(&data), Some((&data[..4], &data[4..])));
+        assert_eq!(DiscriminatorScheme::Uleb128.split(&data), Some((&data[..2], &data[2..])));
+
+        assert_eq!(DiscriminatorScheme::Anchor.split(&data[..7]), None);
+        assert_eq!(DiscriminatorScheme::U8.split(&[]), None);
+        assert_eq!(DiscriminatorScheme::Uleb128.split(&[0x80, 0x80]), None);
+        assert_eq!(DiscriminatorScheme::Uleb128.split(&[0x80; 6]), None);
+        assert_eq!(DiscriminatorScheme::Untagged.split(b"memo"), Some((&b""[..], &b"memo"[..])));
+    }
+
+    #[test]
+    fn encodes_numeric_tags() {
+        assert_eq!(DiscriminatorScheme::U8.encode_tag(3), Some(vec![3]));
+        assert_eq!(DiscriminatorScheme::U8.encode_tag(256), None);
+        assert_eq!(DiscriminatorScheme::U32Le.encode_tag(2), Some(vec![2, 0, 0, 0]));
+        assert_eq!(DiscriminatorScheme::Uleb128.encode_tag(131), Some(vec![0x83, 0x01]));
+        assert_eq!(DiscriminatorScheme::Untagged.encode_tag(0), Some(vec![]));
+        assert_eq!(DiscriminatorScheme::Anchor.encode_tag(0), None);
+    }
+
+    #[test]
+    fn validates_discriminator_lengths() {
+        assert!(DiscriminatorScheme::Anchor.validate(&[1; 8]).is_ok());
+        assert!(DiscriminatorScheme::Anchor.validate(&[1; 4]).is_err());
+        assert!(DiscriminatorScheme::U8.validate(&[3]).is_ok());
+        assert!(DiscriminatorScheme::U8.validate(&[3, 0]).is_err());
+        assert!(DiscriminatorScheme::U32Le.validate(&[2, 0, 0, 0]).is_ok());
+        assert!(DiscriminatorScheme::Uleb128.validate(&[0x83, 0x01]).is_ok());
+        assert!(DiscriminatorScheme::Uleb128.validate(&[0x83, 0x01, 0x00]).is_err());
+        assert!(DiscriminatorScheme::U8.for_kind(DiscriminatorKind::Event).validate(&[1; 8]).is_ok());
+        assert!(DiscriminatorScheme::U8.for_kind(DiscriminatorKind::Instruction).validate(&[1; 8]).is_err());
+
+        assert_eq!(DiscriminatorScheme::known("11111111111111111111111111111111"), Some(DiscriminatorScheme::U32Le));
+        assert_eq!(DiscriminatorScheme::known("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"), Some(DiscriminatorScheme::U8));
+        assert_eq!(DiscriminatorScheme::known("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4"), None);
+    }
+}
@@ -1,18 +1,59 @@
+use std::collections::HashSet;
 use std::error::Error;
+use std::io::Read;
 use std::str::FromStr;
-use std::sync::Arc;
-use solana_client::{rpc_client::RpcClient, rpc_response::RpcConfirmedTransactionStatusWithSignature};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::{RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+    rpc_response::RpcConfirmedTransactionStatusWithSignature,
+};
 use solana_sdk::account::Account;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::signature::Signature;
-use solana_transaction_status::UiTransactionEncoding;
+use solana_sdk::message::VersionedMessage;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, UiInnerInstructions, UiInstruction, UiLoadedAddresses,
+    UiTransactionEncoding, UiTransactionStatusMeta,
+};
 use solana_sdk::pubkey::Pubkey;
 use tokio::task;
+use async_trait::async_trait;
+use flate2::read::ZlibDecoder;
+use futures_util::StreamExt;
 use log::{info, error, warn, debug};
 use thiserror::Error;
 
 
-use crate::graph_disc::DatabaseInterface;
+use crate::graph_disc::{
+    DatabaseError, DatabaseInterface, DiscriminatorKind, DiscriminatorMetadata, Invocation, ProgramCursor,
+};
+use crate::cluster::DEFAULT_CLUSTER;
+use crate::events::{event_from_cpi_data, events_from_logs, EVENT_IX_TAG};
+use crate::idl::{self, Idl};
+use crate::scheme::DiscriminatorScheme;
+use crate::ingest::ListenerRegistry;
+
+// Maximum number of signatures `getSignaturesForAddress` returns per call
+pub const SIGNATURE_PAGE_LIMIT: usize = 1000;
+
+// Pause between polls of a program whose log subscription is unavailable, unless configured
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+// Seed Anchor uses to derive the IDL account from the program's base address
+const IDL_SEED: &str = "anchor:idl";
+
+// IDL account header: 8-byte discriminator, 32-byte authority, 4-byte data length
+const IDL_HEADER_LEN: usize = 8 + 32 + 4;
+
+// Bincode tags of the upgradeable loader's Program and ProgramData account states
+const LOADER_PROGRAM_TAG: u32 = 2;
+const LOADER_PROGRAMDATA_TAG: u32 = 3;
+
+// ProgramData header: 4-byte tag, 8-byte slot, 1-byte option flag, 32-byte upgrade authority
+const PROGRAMDATA_HEADER_LEN: usize = 4 + 8 + 1 + 32;
 
 #[derive(Error, Debug)]
 pub enum SolanaError {
@@ -25,33 +66,177 @@ pub enum SolanaError {
     #[error("Failed to parse signature: {0}")]
     SignatureParseError(String),
     
-    #[error("Transaction decode error")]
-    TransactionDecodeError,
-    
-    #[error("Invalid instruction data: {0}")]
-    InvalidInstructionData(String),
+    #[error("Invalid IDL account: {0}")]
+    InvalidIdl(String),
+
+    #[error("Not an upgradeable program: {0}")]
+    NotUpgradeable(String),
+
+    #[error("Subscription error: {0}")]
+    SubscriptionError(String),
+
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
 }
 
-pub struct SolanaConnection {
+// An instruction as it appears in a transaction, before its discriminator is extracted
+struct ObservedInstruction<'a> {
+    program_id_index: u8,
+    accounts: &'a [u8],
+    data: &'a [u8],
+    invocation: Invocation,
+}
+
+// Account keys an instruction index refers to: the static keys of the message followed,
+// for v0 messages, by the addresses loaded from lookup tables (writable, then readonly)
+pub fn full_account_keys(message: &VersionedMessage, meta: Option<&UiTransactionStatusMeta>) -> Vec<Pubkey> {
+    let mut account_keys = message.static_account_keys().to_vec();
+
+    let loaded_addresses = meta.and_then(|meta| Option::<&UiLoadedAddresses>::from(meta.loaded_addresses.as_ref()));
+    if let Some(loaded) = loaded_addresses {
+        for address in loaded.writable.iter().chain(&loaded.readonly) {
+            match Pubkey::from_str(address) {
+                Ok(pubkey) => account_keys.push(pubkey),
+                Err(e) => {
+                    // Indexes past this point would resolve to the wrong keys
+                    warn!("Invalid loaded address {}: {}", address, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    account_keys
+}
+
+// IDL published on-chain by an Anchor program
+#[derive(Debug, Clone)]
+pub struct OnChainIdl {
+    pub address: Pubkey,
+    pub authority: Pubkey,
+    pub idl: Idl,
+}
+
+// Address of the account Anchor stores a program's IDL in
+pub fn idl_address(program_id: &Pubkey) -> Result<Pubkey, SolanaError> {
+    let (base, _) = Pubkey::find_program_address(&[], program_id);
+    Pubkey::create_with_seed(&base, IDL_SEED, program_id)
+        .map_err(|e| SolanaError::PubkeyParseError(e.to_string()))
+}
+
+// Parse the raw data of an IDL account: header followed by the zlib-compressed IDL JSON
+pub fn parse_idl_account(data: &[u8]) -> Result<(Pubkey, Idl), SolanaError> {
+    if data.len() < IDL_HEADER_LEN {
+        return Err(SolanaError::InvalidIdl(format!("account data too short: {} bytes", data.len())));
+    }
+
+    let authority = Pubkey::try_from(&data[8..40])
+        .map_err(|e| SolanaError::InvalidIdl(e.to_string()))?;
+
+    let mut len_bytes = [0u8; 4];
+    len_bytes.copy_from_slice(&data[40..44]);
+    let data_len = u32::from_le_bytes(len_bytes) as usize;
+
+    let compressed = data.get(IDL_HEADER_LEN..IDL_HEADER_LEN + data_len)
+        .ok_or_else(|| SolanaError::InvalidIdl(format!(
+            "declared IDL length {} exceeds account data", data_len
+        )))?;
+
+    let mut json = Vec::new();
+    ZlibDecoder::new(compressed)
+        .read_to_end(&mut json)
+        .map_err(|e| SolanaError::InvalidIdl(format!("failed to decompress IDL: {}", e)))?;
+
+    let idl: Idl = serde_json::from_slice(&json)
+        .map_err(|e| SolanaError::InvalidIdl(format!("failed to parse IDL JSON: {}", e)))?;
+
+    Ok((authority, idl))
+}
+
+fn loader_state_tag(data: &[u8]) -> Option<u32> {
+    data.get(..4).map(|tag| u32::from_le_bytes([tag[0], tag[1], tag[2], tag[3]]))
+}
+
+// Address of the ProgramData account that a program account of the upgradeable loader points to
+pub fn parse_program_account(data: &[u8]) -> Result<Pubkey, SolanaError> {
+    match (loader_state_tag(data), data.get(4..36)) {
+        (Some(LOADER_PROGRAM_TAG), Some(address)) => Pubkey::try_from(address)
+            .map_err(|e| SolanaError::NotUpgradeable(e.to_string())),
+        _ => Err(SolanaError::NotUpgradeable("not a program account".to_string())),
+    }
+}
+
+// Upgrade authority stored in a ProgramData account; None once the program is immutable
+pub fn parse_programdata_account(data: &[u8]) -> Result<Option<Pubkey>, SolanaError> {
+    if loader_state_tag(data) != Some(LOADER_PROGRAMDATA_TAG) || data.len() < PROGRAMDATA_HEADER_LEN {
+        return Err(SolanaError::NotUpgradeable("not a ProgramData account".to_string()));
+    }
+
+    match data[12] {
+        0 => Ok(None),
+        1 => Pubkey::try_from(&data[13..PROGRAMDATA_HEADER_LEN])
+            .map(Some)
+            .map_err(|e| SolanaError::NotUpgradeable(e.to_string())),
+        flag => Err(SolanaError::NotUpgradeable(format!("invalid upgrade authority flag {}", flag))),
+    }
+}
+
+// Pubsub endpoint served alongside an RPC endpoint (http -> ws, https -> wss)
+pub fn websocket_url(rpc_url: &str) -> Option<String> {
+    if let Some(rest) = rpc_url.strip_prefix("https://") {
+        Some(format!("wss://{}", rest))
+    } else {
+        rpc_url.strip_prefix("http://").map(|rest| format!("ws://{}", rest))
+    }
+}
+
+// Raw chain data the indexer reads. `SolanaConnection` builds everything else on top
+// of it, so an RPC node can be swapped for recorded fixtures in tests.
+#[async_trait]
+pub trait ChainSource: Send + Sync {
+    async fn get_program_accounts(&self, program_id: &str) -> Result<Vec<(Pubkey, Account)>, SolanaError>;
+
+    // Fetch one page of signatures for a program, newest first.
+    // `before` and `until` bound the page, as in `getSignaturesForAddress`.
+    async fn get_signatures_page(
+        &self,
+        program_id: &str,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, SolanaError>;
+
+    // Fetch a single account, returning None if it does not exist
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, SolanaError>;
+
+    // Fetch a transaction in binary form, so it can be decoded, including v0 transactions
+    async fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransactionWithStatusMeta, SolanaError>;
+
+    // The newest slot the source has seen
+    async fn get_slot(&self) -> Result<u64, SolanaError>;
+}
+
+// Chain source backed by a Solana RPC node
+pub struct RpcChainSource {
     client: Arc<RpcClient>,
 }
 
-impl SolanaConnection {
-    pub fn new(url: &str) -> Self {
-        let client = Arc::new(RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()));
-        info!("Connected to Solana node at {}", url);
-        SolanaConnection { client }
+impl RpcChainSource {
+    pub fn new(client: RpcClient) -> Self {
+        RpcChainSource { client: Arc::new(client) }
     }
+}
 
+#[async_trait]
+impl ChainSource for RpcChainSource {
     // Use spawn_blocking for RPC calls
-    pub async fn get_program_accounts(&self, program_id: &str) -> Result<Vec<(Pubkey, Account)>, String> {
+    async fn get_program_accounts(&self, program_id: &str) -> Result<Vec<(Pubkey, Account)>, SolanaError> {
         let program_id_str = program_id.to_string();
         let program_pubkey = match Pubkey::from_str(program_id) {
             Ok(pubkey) => pubkey,
             Err(e) => {
                 let err_msg = format!("Invalid program ID {}: {}", program_id, e);
                 error!("{}", err_msg);
-                return Err(err_msg);
+                return Err(SolanaError::PubkeyParseError(err_msg));
             }
         };
         
@@ -60,7 +245,7 @@ impl SolanaConnection {
 
         // Move the RPC call to a blocking task
         match task::spawn_blocking(move || {
-            client.get_program_accounts(&program_pubkey)
+            client.get_program_accounts(&program_pubkey).map_err(|e| e.to_string())
         }).await {
             Ok(result) => match result {
                 Ok(accounts) => {
@@ -70,177 +255,1214 @@ impl SolanaConnection {
                 Err(e) => {
                     let err_msg = format!("Failed to get program accounts: {}", e);
                     error!("{}", err_msg);
-                    Err(err_msg)
+                    Err(SolanaError::RpcError(err_msg))
                 }
             },
             Err(e) => {
                 let err_msg = format!("Task execution error: {}", e);
                 error!("{}", err_msg);
-                Err(err_msg)
+                Err(SolanaError::RpcError(err_msg))
             }
         }
     }
-    
-    pub async fn get_transactions(
-        &self, 
-        program_id: &str
+
+    async fn get_signatures_page(
+        &self,
+        program_id: &str,
+        before: Option<Signature>,
+        until: Option<Signature>,
     ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, SolanaError> {
         let program_pubkey = match Pubkey::from_str(program_id) {
             Ok(pubkey) => pubkey,
             Err(e) => return Err(SolanaError::PubkeyParseError(e.to_string())),
         };
-    
-        debug!("Fetching signatures for program {}", program_id);
+
         let client = self.client.clone();
-        
+
         // Use spawn_blocking to handle the synchronous part of this call.
-        let signatures = match task::spawn_blocking(move || {
-            client.get_signatures_for_address(&program_pubkey)
-        }).await {
-            Ok(result) => match result {
-                Ok(sigs) => {
-                    info!("Retrieved {} transaction signatures for program {}", sigs.len(), program_id);
-                    sigs
-                },
-                Err(e) => return Err(SolanaError::RpcError(e.to_string())),
-            },
-            Err(e) => return Err(SolanaError::RpcError(e.to_string())),
+        task::spawn_blocking(move || {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                before,
+                until,
+                limit: Some(SIGNATURE_PAGE_LIMIT),
+                commitment: Some(client.commitment()),
+            };
+            client.get_signatures_for_address_with_config(&program_pubkey, config).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| SolanaError::RpcError(e.to_string()))?
+        .map_err(SolanaError::RpcError)
+    }
+
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, SolanaError> {
+        let address = *address;
+        let client = self.client.clone();
+
+        task::spawn_blocking(move || {
+            client.get_account_with_commitment(&address, client.commitment())
+                .map(|response| response.value)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| SolanaError::RpcError(e.to_string()))?
+        .map_err(SolanaError::RpcError)
+    }
+
+    async fn get_transaction(
+        &self,
+        signature: &Signature,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta, SolanaError> {
+        let signature = *signature;
+        let client = self.client.clone();
+
+        // Use spawn_blocking for the RPC call
+        task::spawn_blocking(move || {
+            let config = RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(client.commitment()),
+                max_supported_transaction_version: Some(0),
+            };
+            client.get_transaction_with_config(&signature, config).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| SolanaError::RpcError(e.to_string()))?
+        .map_err(SolanaError::RpcError)
+    }
+
+    async fn get_slot(&self) -> Result<u64, SolanaError> {
+        let client = self.client.clone();
+
+        task::spawn_blocking(move || client.get_slot().map_err(|e| e.to_string()))
+            .await
+            .map_err(|e| SolanaError::RpcError(e.to_string()))?
+            .map_err(SolanaError::RpcError)
+    }
+}
+
+// Chain source that spaces out requests to stay under a provider's rate limit
+pub struct RateLimitedChainSource {
+    inner: Arc<dyn ChainSource>,
+    interval: Duration,
+    // Earliest time the next request may be sent
+    next_request: Mutex<tokio::time::Instant>,
+}
+
+impl RateLimitedChainSource {
+    pub fn new(inner: Arc<dyn ChainSource>, requests_per_second: u32) -> Self {
+        RateLimitedChainSource {
+            inner,
+            interval: Duration::from_secs(1) / requests_per_second.max(1),
+            next_request: Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    // Wait for this request's turn
+    async fn throttle(&self) {
+        let at = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let at = (*next_request).max(tokio::time::Instant::now());
+            *next_request = at + self.interval;
+            at
         };
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+#[async_trait]
+impl ChainSource for RateLimitedChainSource {
+    async fn get_program_accounts(&self, program_id: &str) -> Result<Vec<(Pubkey, Account)>, SolanaError> {
+        self.throttle().await;
+        self.inner.get_program_accounts(program_id).await
+    }
+
+    async fn get_signatures_page(
+        &self,
+        program_id: &str,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, SolanaError> {
+        self.throttle().await;
+        self.inner.get_signatures_page(program_id, before, until).await
+    }
+
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, SolanaError> {
+        self.throttle().await;
+        self.inner.get_account(address).await
+    }
+
+    async fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransactionWithStatusMeta, SolanaError> {
+        self.throttle().await;
+        self.inner.get_transaction(signature).await
+    }
+
+    async fn get_slot(&self) -> Result<u64, SolanaError> {
+        self.throttle().await;
+        self.inner.get_slot().await
+    }
+}
+
+pub struct SolanaConnection {
+    chain: Arc<dyn ChainSource>,
+    // Cluster the chain data belongs to, which scopes everything stored from it
+    cluster: String,
+    // Pubsub endpoint used to stream transactions; listeners poll without one
+    ws_url: Option<String>,
+    poll_interval: Duration,
+    // Programs with a backfill running in this process
+    active_backfills: Arc<Mutex<HashSet<String>>>,
+    listeners: Arc<ListenerRegistry>,
+}
+
+impl SolanaConnection {
+    pub fn new(url: &str) -> Self {
+        let client = RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed());
+        info!("Connected to Solana node at {}", url);
+        Self::with_client(client).with_ws_url(websocket_url(url))
+    }
+
+    pub fn with_client(client: RpcClient) -> Self {
+        Self::with_source(Arc::new(RpcChainSource::new(client)))
+    }
+
+    pub fn with_source(chain: Arc<dyn ChainSource>) -> Self {
+        SolanaConnection {
+            chain,
+            cluster: DEFAULT_CLUSTER.to_string(),
+            ws_url: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            active_backfills: Arc::new(Mutex::new(HashSet::new())),
+            listeners: Arc::new(ListenerRegistry::default()),
+        }
+    }
+
+    pub fn with_ws_url(mut self, ws_url: Option<String>) -> Self {
+        self.ws_url = ws_url;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    // Cap the requests sent to the chain source; no cap without a limit
+    pub fn with_rate_limit(mut self, requests_per_second: Option<u32>) -> Self {
+        if let Some(requests_per_second) = requests_per_second {
+            self.chain = Arc::new(RateLimitedChainSource::new(self.chain, requests_per_second));
+        }
+        self
+    }
+
+    pub fn with_cluster(mut self, cluster: &str) -> Self {
+        self.cluster = cluster.to_string();
+        self
+    }
+
+    pub fn cluster(&self) -> &str {
+        &self.cluster
+    }
+
+    // State of the listeners following this cluster's programs
+    pub fn listeners(&self) -> &ListenerRegistry {
+        &self.listeners
+    }
+
+    // Mark a program as being backfilled; false if a backfill is already running for it
+    pub fn claim_backfill(&self, program_id: &str) -> bool {
+        self.active_backfills.lock().unwrap().insert(program_id.to_string())
+    }
+
+    pub fn release_backfill(&self, program_id: &str) {
+        self.active_backfills.lock().unwrap().remove(program_id);
+    }
+
+    // Fetch every signature for a program newer than `until`, newest first.
+    // Without `until` only the latest page is returned.
+    pub async fn get_transactions(
+        &self, 
+        program_id: &str,
+        until: Option<&str>,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, SolanaError> {
+        let until = until
+            .map(Signature::from_str)
+            .transpose()
+            .map_err(|e| SolanaError::SignatureParseError(e.to_string()))?;
     
+        debug!("Fetching signatures for program {}", program_id);
+
+        let mut signatures = Vec::new();
+        let mut before = None;
+        loop {
+            let page = self.get_signatures_page(program_id, before, until).await?;
+            let page_len = page.len();
+            signatures.extend(page);
+
+            // A short page means `until` (or the start of history) was reached
+            if until.is_none() || page_len < SIGNATURE_PAGE_LIMIT {
+                break;
+            }
+
+            before = match signatures.last().map(|last| Signature::from_str(&last.signature)) {
+                Some(Ok(signature)) => Some(signature),
+                _ => break,
+            };
+        }
+
+        info!("Retrieved {} transaction signatures for program {}", signatures.len(), program_id);
         Ok(signatures)
     }
 
+    // Fetch and decode the Anchor IDL of a program, if it published one
+    pub async fn fetch_idl(&self, program_id: &str) -> Result<Option<OnChainIdl>, SolanaError> {
+        let program_pubkey = Pubkey::from_str(program_id)
+            .map_err(|e| SolanaError::PubkeyParseError(e.to_string()))?;
+        let address = idl_address(&program_pubkey)?;
+
+        debug!("Fetching IDL account {} for program {}", address, program_id);
+        match self.get_account(&address).await? {
+            Some(account) => {
+                let (authority, idl) = parse_idl_account(&account.data)?;
+                info!("Fetched on-chain IDL for program {}", program_id);
+                Ok(Some(OnChainIdl { address, authority, idl }))
+            },
+            None => {
+                debug!("No IDL account found for program {}", program_id);
+                Ok(None)
+            }
+        }
+    }
+
+    // Current upgrade authority of a program deployed with the upgradeable BPF loader,
+    // read from its ProgramData account. None if the program was made immutable.
+    pub async fn get_upgrade_authority(&self, program_id: &str) -> Result<Option<Pubkey>, SolanaError> {
+        let program_pubkey = Pubkey::from_str(program_id)
+            .map_err(|e| SolanaError::PubkeyParseError(e.to_string()))?;
+
+        let program = self.get_account(&program_pubkey).await?
+            .ok_or_else(|| SolanaError::NotUpgradeable(format!("program {} not found", program_id)))?;
+        if program.owner != solana_sdk::bpf_loader_upgradeable::id() {
+            return Err(SolanaError::NotUpgradeable(format!("{} is owned by {}", program_id, program.owner)));
+        }
+
+        let programdata_address = parse_program_account(&program.data)?;
+        debug!("Reading upgrade authority of {} from {}", program_id, programdata_address);
+        let programdata = self.get_account(&programdata_address).await?
+            .ok_or_else(|| SolanaError::NotUpgradeable(format!("ProgramData account {} not found", programdata_address)))?;
+        parse_programdata_account(&programdata.data)
+    }
+
+    // Fetch the on-chain IDL of every tracked program and store its named discriminators.
+    // Returns the number of programs for which an IDL was ingested.
+    pub async fn sync_program_idls<T>(&self, db: &T) -> Result<usize, DatabaseError>
+    where
+        T: DatabaseInterface + ?Sized,
+    {
+        let program_ids = db.get_all_program_ids(&self.cluster).await?;
+        info!("Syncing on-chain IDLs for {} programs on {}", program_ids.len(), self.cluster);
+
+        let mut synced = 0;
+        for program_id in program_ids {
+            match self.fetch_idl(&program_id).await {
+                Ok(Some(on_chain)) => {
+                    let authority = on_chain.authority.to_string();
+                    match idl::ingest_idl(db, &self.cluster, &program_id, &on_chain.idl, &authority, "anchor_idl_account", false).await {
+                        Ok(_) => {
+                            debug!("Ingested IDL account {} for program {}", on_chain.address, program_id);
+                            synced += 1;
+                        },
+                        Err(e) => error!("Failed to store IDL discriminators for program {}: {}", program_id, e),
+                    }
+                },
+                Ok(None) => {},
+                Err(e) => warn!("Failed to fetch IDL for program {}: {}", program_id, e),
+            }
+        }
+
+        info!("Ingested on-chain IDLs for {} programs", synced);
+        Ok(synced)
+    }
+
+    // Extract and store every discriminator found in a fetched transaction
+    pub async fn process_transaction<T>(
+        &self,
+        db: &T,
+        signature: &str,
+        transaction_result: &EncodedConfirmedTransactionWithStatusMeta,
+    )
+    where
+        T: DatabaseInterface + ?Sized,
+    {
+        let Some(transaction) = transaction_result.transaction.transaction.decode() else {
+            warn!("Could not decode transaction {}", signature);
+            return;
+        };
+        let meta = transaction_result.transaction.meta.as_ref();
+
+        let account_keys = full_account_keys(&transaction.message, meta);
+
+        let instructions = transaction.message.instructions();
+        debug!("Processing {} instructions in transaction {}", instructions.len(), signature);
+
+        for (i, instruction) in instructions.iter().enumerate() {
+            let observed = ObservedInstruction {
+                program_id_index: instruction.program_id_index,
+                accounts: &instruction.accounts,
+                data: &instruction.data,
+                invocation: Invocation { cpi: false, stack_height: Some(1) },
+            };
+            if let Err(reason) = self.store_instruction(db, signature, &account_keys, observed).await {
+                debug!("Skipping instruction {} in transaction {}: {}", i, signature, reason);
+            }
+        }
+
+        let Some(meta) = meta else {
+            return;
+        };
+
+        // Anchor events: `emit!` writes them to the logs, `emit_cpi!` to a self-invocation
+        let mut events = Vec::new();
+        if let Some(logs) = Option::<&Vec<String>>::from(meta.log_messages.as_ref()) {
+            events.extend(events_from_logs(logs));
+        }
+
+        // Instructions invoked through CPI, which are all a CPI-only program ever receives
+        if let Some(inner_instructions) = Option::<&Vec<UiInnerInstructions>>::from(meta.inner_instructions.as_ref()) {
+            for inner in inner_instructions {
+                // Program of the invocation at each stack height, from the top-level instruction
+                // (height 1) down, to find the caller of each inner instruction
+                let mut callers: Vec<u8> = instructions.get(inner.index as usize)
+                    .map(|outer| vec![outer.program_id_index])
+                    .unwrap_or_default();
+
+                for (i, instruction) in inner.instructions.iter().enumerate() {
+                    let UiInstruction::Compiled(instruction) = instruction else {
+                        callers.clear();
+                        continue;
+                    };
+
+                    let caller = match instruction.stack_height {
+                        Some(height) if height >= 2 => {
+                            callers.truncate(height as usize - 1);
+                            let caller = if callers.len() + 1 == height as usize { callers.last().copied() } else { None };
+                            callers.push(instruction.program_id_index);
+                            caller
+                        },
+                        // Without stack heights the caller cannot be told
+                        _ => {
+                            callers.clear();
+                            None
+                        },
+                    };
+
+                    let Ok(data) = bs58::decode(&instruction.data).into_vec() else {
+                        debug!("Skipping inner instruction with undecodable data in transaction {}", signature);
+                        continue;
+                    };
+
+                    // Self-CPI events are catalogued as events, not as instructions. Anchor
+                    // programs invoke themselves to emit them; from any other caller the tag is forged.
+                    if data.starts_with(&EVENT_IX_TAG) {
+                        match account_keys.get(instruction.program_id_index as usize) {
+                            Some(program_id) if caller == Some(instruction.program_id_index) => {
+                                events.extend(event_from_cpi_data(&program_id.to_string(), &data));
+                            },
+                            _ => debug!(
+                                "Skipping event instruction {}.{} in transaction {} not invoked by its own program",
+                                inner.index, i, signature
+                            ),
+                        }
+                        continue;
+                    }
+
+                    let observed = ObservedInstruction {
+                        program_id_index: instruction.program_id_index,
+                        accounts: &instruction.accounts,
+                        data: &data,
+                        invocation: Invocation { cpi: true, stack_height: instruction.stack_height },
+                    };
+                    if let Err(reason) = self.store_instruction(db, signature, &account_keys, observed).await {
+                        debug!(
+                            "Skipping inner instruction {}.{} in transaction {}: {}",
+                            inner.index, i, signature, reason
+                        );
+                    }
+                }
+            }
+        }
+
+        // Events have no signer of their own, so they are attributed to the fee payer
+        let user_id = account_keys.first()
+            .map(|key| key.to_string())
+            .unwrap_or_else(|| signature.to_string());
+
+        for event in events {
+            // Only Anchor programs prefix what they log with an event discriminator
+            match db.get_discriminator_scheme(&self.cluster, &event.program_id).await {
+                Ok(DiscriminatorScheme::Anchor) => {},
+                Ok(scheme) => {
+                    debug!("Skipping program data of {} in transaction {}: {} scheme", event.program_id, signature, scheme);
+                    continue;
+                },
+                Err(e) => {
+                    error!("Failed to look up the discriminator scheme of {}: {}", event.program_id, e);
+                    continue;
+                },
+            }
+
+            if let Err(e) = db.upload_discriminator(
+                &self.cluster,
+                &event.program_id,
+                event.discriminator_data,
+                event.event_data,
+                &user_id,
+                DiscriminatorMetadata {
+                    kind: DiscriminatorKind::Event,
+                    ..Default::default()
+                },
+            ).await {
+                error!("Failed to store event from transaction {}: {}", signature, e);
+            } else {
+                debug!("Stored event discriminator for program {} from transaction {}", event.program_id, signature);
+            }
+        }
+    }
+
+    // Store the discriminator of a single top-level or inner instruction.
+    // Returns why the instruction was skipped, if it was.
+    async fn store_instruction<T>(
+        &self,
+        db: &T,
+        signature: &str,
+        account_keys: &[Pubkey],
+        instruction: ObservedInstruction<'_>,
+    ) -> Result<(), String>
+    where
+        T: DatabaseInterface + ?Sized,
+    {
+        // Get the real program ID for this instruction
+        let program_id_index = instruction.program_id_index as usize;
+        let Some(instruction_program_id) = account_keys.get(program_id_index) else {
+            warn!("Invalid program ID index {} in transaction {}", program_id_index, signature);
+            return Err(format!("invalid program ID index {}", program_id_index));
+        };
+
+        // The program's scheme decides how long its discriminators are
+        let program_id = instruction_program_id.to_string();
+        let scheme = db.get_discriminator_scheme(&self.cluster, &program_id).await
+            .map_err(|e| format!("failed to look up discriminator scheme: {}", e))?;
+        let Some((discriminator_data, instruction_data)) = scheme.split(instruction.data) else {
+            return Err(format!("instruction data too short for the {} scheme", scheme));
+        };
+
+        // Use the first account as user ID, or fall back to the transaction signature
+        let user_id = instruction.accounts.first()
+            .and_then(|&index| account_keys.get(index as usize))
+            .map(|key| key.to_string())
+            .unwrap_or_else(|| signature.to_string());
+
+        // Store the extracted data in the database
+        if let Err(e) = db.upload_discriminator(
+            &self.cluster,
+            &program_id,
+            discriminator_data.to_vec(),
+            instruction_data.to_vec(),
+            &user_id,
+            DiscriminatorMetadata {
+                kind: DiscriminatorKind::Instruction,
+                invocation: Some(instruction.invocation),
+                ..Default::default()
+            },
+        ).await {
+            error!("Failed to store transaction data for {}: {}", signature, e);
+        } else {
+            debug!("Successfully stored discriminator from transaction {}", signature);
+        }
+
+        Ok(())
+    }
+
+    // Follow a program's transactions as they land. With a WebSocket endpoint configured,
+    // signatures are streamed via `logsSubscribe`; whenever the socket is unavailable the
+    // listener falls back to polling every poll interval and retries the subscription.
     pub async fn real_time_listener<T>(
         &self, 
         db: Arc<T>,
         program_id: String,
     ) -> Result<(), Box<dyn Error + Send + Sync>> 
     where 
-        T: DatabaseInterface + Send + Sync + 'static
+        T: DatabaseInterface + ?Sized + 'static
     {
-        info!("Starting real-time listener for program {}", program_id);
+        info!("Starting real-time listener for program {} on {}", program_id, self.cluster);
         
-        // Keep track of processed signatures to avoid duplication
-        let mut processed_signatures = std::collections::HashSet::new();
+        // Resume from the newest signature processed before the last restart
+        let mut cursor = match db.get_program_cursor(&self.cluster, &program_id).await {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                error!("Failed to load signature cursor for program {}: {}", program_id, e);
+                None
+            }
+        };
+        if let Some(cursor) = &cursor {
+            info!("Resuming program {} from signature {} at slot {}", program_id, cursor.signature, cursor.slot);
+            self.listeners.advanced(&program_id, &cursor.signature, cursor.slot);
+        }
         
         loop {
-            match self.get_transactions(&program_id).await {
-                Ok(signatures) => {
-                    info!("Fetched {} signatures for program {}", signatures.len(), program_id);
-                    
-                    for signature in signatures {
-                        // Skip already processed signatures
-                        if processed_signatures.contains(&signature.signature) {
-                            continue;
-                        }
-                        
-                        let tx_signature = match Signature::from_str(&signature.signature) {
-                            Ok(sig) => sig,
-                            Err(e) => {
-                                error!("Failed to parse signature {}: {}", signature.signature, e);
-                                continue;
-                            }
-                        };
-                        
-                        let client = self.client.clone();
-                        
-                        // Use spawn_blocking for the RPC call
-                        let transaction_result = match task::spawn_blocking(move || {
-                            client.get_transaction(&tx_signature, UiTransactionEncoding::Json)
-                        }).await {
-                            Ok(result) => match result {
-                                Ok(tx) => tx,
-                                Err(e) => {
-                                    error!("Failed to get transaction {}: {}", signature.signature, e);
-                                    continue;
-                                }
-                            },
-                            Err(e) => {
-                                error!("Task execution error for transaction {}: {}", signature.signature, e);
-                                continue;
-                            }
-                        };
-
-                        // Decode and process the transaction
-                        if let Some(transaction) = transaction_result.transaction.transaction.decode() {
-                            let instructions = transaction.message.instructions();
-                            debug!("Processing {} instructions in transaction {}", instructions.len(), signature.signature);
-
-                            // Get account keys from the appropriate message type
-                            let account_keys = match &transaction.message {
-                                solana_sdk::message::VersionedMessage::Legacy(message) => &message.account_keys,
-                                solana_sdk::message::VersionedMessage::V0(message) => &message.account_keys,
-                            };
-
-                            for (i, instruction) in instructions.iter().enumerate() {
-                                // Get the real program ID for this instruction
-                                let program_id_index = instruction.program_id_index as usize;
-                                if program_id_index >= account_keys.len() {
-                                    warn!("Invalid program ID index {} in instruction {}", program_id_index, i);
-                                    continue;
-                                }
-                                
-                                let instruction_program_id = account_keys[program_id_index].to_string();
-                                
-                                let accounts = instruction.accounts.clone();
-                                let data = instruction.data.clone();
-                                
-                                // Skip instructions with insufficient data
-                                if data.len() < 8 {
-                                    debug!("Instruction data too short in transaction {}, instruction {}", signature.signature, i);
-                                    continue;
-                                }
-                                
-                                let discriminator_data = data[0..8].to_vec();
-                                let instruction_data = data[8..].to_vec();
-                                
-                                // Use the first account as user ID, or fall back to the transaction signature
-                                let user_id = if !accounts.is_empty() && (accounts[0] as usize) < account_keys.len() {
-                                    account_keys[accounts[0] as usize].to_string()
-                                } else {
-                                    signature.signature.clone()
-                                };
-
-                                // Store the extracted data in the database
-                                if let Err(e) = db.upload_discriminator(
-                                    &instruction_program_id,
-                                    discriminator_data,
-                                    instruction_data,
-                                    &user_id,
-                                ).await {
-                                    error!("Failed to store transaction data for {}: {}", signature.signature, e);
-                                } else {
-                                    debug!("Successfully stored discriminator from transaction {}", signature.signature);
-                                }
-                            }
-                        } else {
-                            warn!("Could not decode transaction {}", signature.signature);
-                        }
-                        
-                        // Mark this signature as processed
-                        processed_signatures.insert(signature.signature);
-                        
-                        // Limit the size of the processed signatures set
-                        if processed_signatures.len() > 1000 {
-                            processed_signatures.clear();
-                        }
-                    }
+            if let Some(ws_url) = &self.ws_url {
+                match self.stream_program_logs(db.as_ref(), &program_id, ws_url, &mut cursor).await {
+                    Ok(()) => warn!("Log subscription for program {} closed, falling back to polling", program_id),
+                    Err(e) => {
+                        warn!("Log subscription for program {} failed, falling back to polling: {}", program_id, e);
+                        self.listeners.failed(&program_id, e.to_string());
+                    },
                 }
-                Err(e) => error!("Error fetching transactions for program {}: {:?}", program_id, e),
             }
 
+            self.poll_transactions(db.as_ref(), &program_id, &mut cursor).await;
+
             debug!("Waiting before next polling cycle for program {}", program_id);
-            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    // Subscribe to the logs of every transaction mentioning the program and ingest
+    // each one as its signature arrives. Returns once the subscription ends.
+    async fn stream_program_logs<T>(
+        &self,
+        db: &T,
+        program_id: &str,
+        ws_url: &str,
+        cursor: &mut Option<ProgramCursor>,
+    ) -> Result<(), SolanaError>
+    where
+        T: DatabaseInterface + ?Sized,
+    {
+        let pubsub = PubsubClient::new(ws_url)
+            .await
+            .map_err(|e| SolanaError::SubscriptionError(e.to_string()))?;
+
+        let (mut notifications, unsubscribe) = pubsub
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]),
+                RpcTransactionLogsConfig { commitment: Some(CommitmentConfig::confirmed()) },
+            )
+            .await
+            .map_err(|e| SolanaError::SubscriptionError(e.to_string()))?;
+        info!("Streaming logs for program {} from {}", program_id, ws_url);
+
+        // Pick up whatever landed while the socket was down
+        self.poll_transactions(db, program_id, cursor).await;
+
+        while let Some(notification) = notifications.next().await {
+            let signature = notification.value.signature;
+            if cursor.as_ref().is_some_and(|cursor| cursor.signature == signature) {
+                continue;
+            }
+
+            debug!("Received signature {} for program {} at slot {}", signature, program_id, notification.context.slot);
+            if let Err(e) = self.ingest_signature(db, &signature).await {
+                // Leave the cursor behind this transaction so polling retries it
+                error!("Failed to get transaction {}: {}", signature, e);
+                self.listeners.failed(program_id, e.to_string());
+                break;
+            }
+            self.advance_cursor(db, program_id, cursor, signature, notification.context.slot).await;
+        }
+
+        unsubscribe().await;
+        drop(notifications);
+        if let Err(e) = pubsub.shutdown().await {
+            debug!("Error closing log subscription for program {}: {}", program_id, e);
+        }
+        Ok(())
+    }
+
+    // Fetch and ingest every signature newer than the cursor, oldest first
+    pub async fn poll_transactions<T>(&self, db: &T, program_id: &str, cursor: &mut Option<ProgramCursor>)
+    where
+        T: DatabaseInterface + ?Sized,
+    {
+        let until = cursor.as_ref().map(|cursor| cursor.signature.clone());
+        match self.get_transactions(program_id, until.as_deref()).await {
+            Ok(signatures) => {
+                info!("Fetched {} new signatures for program {}", signatures.len(), program_id);
+                
+                // Process oldest first so the cursor only ever moves forward
+                for signature in signatures.into_iter().rev() {
+                    if let Err(e) = self.ingest_signature(db, &signature.signature).await {
+                        // Retry from here on the next cycle rather than skip the transaction
+                        error!("Failed to get transaction {}: {}", signature.signature, e);
+                        self.listeners.failed(program_id, e.to_string());
+                        break;
+                    }
+                    self.advance_cursor(db, program_id, cursor, signature.signature, signature.slot).await;
+                }
+            }
+            Err(e) => {
+                error!("Error fetching transactions for program {}: {:?}", program_id, e);
+                self.listeners.failed(program_id, e.to_string());
+            },
+        }
+    }
+
+    // Fetch and process a single transaction. Only fetch failures are returned;
+    // an unparseable signature is logged and skipped.
+    async fn ingest_signature<T>(&self, db: &T, signature: &str) -> Result<(), SolanaError>
+    where
+        T: DatabaseInterface + ?Sized,
+    {
+        match Signature::from_str(signature) {
+            Ok(tx_signature) => {
+                let transaction = self.get_transaction(&tx_signature).await?;
+                self.process_transaction(db, signature, &transaction).await;
+            },
+            Err(e) => error!("Failed to parse signature {}: {}", signature, e),
         }
+        Ok(())
+    }
+
+    pub async fn advance_cursor<T>(
+        &self,
+        db: &T,
+        program_id: &str,
+        cursor: &mut Option<ProgramCursor>,
+        signature: String,
+        slot: u64,
+    )
+    where
+        T: DatabaseInterface + ?Sized,
+    {
+        if let Err(e) = db.update_program_cursor(&self.cluster, program_id, &signature, slot).await {
+            error!("Failed to save signature cursor for program {}: {}", program_id, e);
+        }
+        self.listeners.advanced(program_id, &signature, slot);
+        *cursor = Some(ProgramCursor {
+            cluster: self.cluster.clone(),
+            program_id: program_id.to_string(),
+            signature,
+            slot,
+        });
     }
 }
 
-// Implement the Clone trait manually
 impl Clone for SolanaConnection {
     fn clone(&self) -> Self {
         SolanaConnection {
-            client: Arc::clone(&self.client),
+            chain: Arc::clone(&self.chain),
+            cluster: self.cluster.clone(),
+            ws_url: self.ws_url.clone(),
+            poll_interval: self.poll_interval,
+            active_backfills: Arc::clone(&self.active_backfills),
+            listeners: Arc::clone(&self.listeners),
         }
     }
 }
 
+// Callers that only need raw chain data can use the connection directly
+#[async_trait]
+impl ChainSource for SolanaConnection {
+    async fn get_program_accounts(&self, program_id: &str) -> Result<Vec<(Pubkey, Account)>, SolanaError> {
+        self.chain.get_program_accounts(program_id).await
+    }
+
+    async fn get_signatures_page(
+        &self,
+        program_id: &str,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, SolanaError> {
+        self.chain.get_signatures_page(program_id, before, until).await
+    }
+
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, SolanaError> {
+        self.chain.get_account(address).await
+    }
+
+    async fn get_transaction(&self, signature: &Signature) -> Result<EncodedConfirmedTransactionWithStatusMeta, SolanaError> {
+        self.chain.get_transaction(signature).await
+    }
+
+    async fn get_slot(&self) -> Result<u64, SolanaError> {
+        self.chain.get_slot().await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Write;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use flate2::{write::ZlibEncoder, Compression};
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::{v0, MessageHeader, v0::MessageAddressTableLookup};
+    use crate::chain_fixture::{confirmed_transaction, ChainFixture, FixtureChainSource};
+    use crate::graph_disc::MockDatabaseInterface;
+    use crate::memory_db::MemoryDatabase;
+
+    const IDL_JSON: &str = r#"{
+        "version": "0.1.0",
+        "name": "counter",
+        "instructions": [{ "name": "initialize" }, { "name": "incrementBy" }],
+        "accounts": [{ "name": "Counter" }]
+    }"#;
+
+    // Build IDL account data the way `anchor idl init` lays it out
+    fn idl_account_data(authority: &Pubkey) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(IDL_JSON.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut data = idl::sighash("account", "IdlAccount").to_vec();
+        data.extend_from_slice(authority.as_ref());
+        data.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        data.extend_from_slice(&compressed);
+        data
+    }
+
+    // Stand-in RPC node that answers getAccountInfo with a canned IDL account
+    fn connection_with_idl_account(data: &[u8], program_id: &Pubkey) -> SolanaConnection {
+        let mut mocks = HashMap::new();
+        mocks.insert(RpcRequest::GetAccountInfo, json!({
+            "context": { "slot": 1 },
+            "value": {
+                "data": [STANDARD.encode(data), "base64"],
+                "executable": false,
+                "lamports": 1_000_000,
+                "owner": program_id.to_string(),
+                "rentEpoch": 0,
+                "space": data.len(),
+            }
+        }));
+
+        SolanaConnection::with_client(RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks))
+    }
+
+    #[test]
+    fn parses_idl_account() {
+        let authority = Pubkey::new_unique();
+        let (parsed_authority, idl) = parse_idl_account(&idl_account_data(&authority)).unwrap();
+
+        assert_eq!(parsed_authority, authority);
+        assert_eq!(idl.program_name(), Some("counter"));
+        assert_eq!(idl.discriminators().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn reads_upgrade_authority_from_programdata() {
+        let program_id = Pubkey::new_unique();
+        let immutable_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let fixture = ChainFixture::default()
+            .with_upgradeable_program(&program_id, Some(&authority))
+            .with_upgradeable_program(&immutable_id, None);
+        let connection = SolanaConnection::with_source(Arc::new(FixtureChainSource::new(fixture)));
+
+        assert_eq!(connection.get_upgrade_authority(&program_id.to_string()).await.unwrap(), Some(authority));
+        assert_eq!(connection.get_upgrade_authority(&immutable_id.to_string()).await.unwrap(), None);
+        assert!(matches!(
+            connection.get_upgrade_authority(&Pubkey::new_unique().to_string()).await,
+            Err(SolanaError::NotUpgradeable(_))
+        ));
+
+        assert!(parse_program_account(&[2, 0, 0, 0]).is_err());
+        assert!(parse_programdata_account(&[3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_idl_account() {
+        let data = idl_account_data(&Pubkey::new_unique());
+        assert!(matches!(parse_idl_account(&data[..data.len() - 1]), Err(SolanaError::InvalidIdl(_))));
+        assert!(matches!(parse_idl_account(&data[..10]), Err(SolanaError::InvalidIdl(_))));
+    }
+
+    #[tokio::test]
+    async fn fetches_idl_from_rpc() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let connection = connection_with_idl_account(&idl_account_data(&authority), &program_id);
+
+        let on_chain = connection.fetch_idl(&program_id.to_string()).await.unwrap().unwrap();
+
+        assert_eq!(on_chain.address, idl_address(&program_id).unwrap());
+        assert_eq!(on_chain.authority, authority);
+
+        let increment = on_chain.idl.discriminators()
+            .into_iter()
+            .find(|d| d.name == "incrementBy")
+            .unwrap();
+        assert_eq!(increment.discriminator_data, idl::sighash("global", "increment_by").to_vec());
+    }
+
+    #[tokio::test]
+    async fn syncing_idls_names_observed_discriminators() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let connection = connection_with_idl_account(&idl_account_data(&authority), &program_id);
+        let discriminator = idl::sighash("global", "increment_by").to_vec();
+        let observed = 5u64.to_le_bytes().to_vec();
+
+        let db = MemoryDatabase::new();
+        db.upload_discriminator("mainnet", &program_id.to_string(), discriminator.clone(), observed.clone(), "signature", Default::default()).await.unwrap();
+        assert_eq!(connection.sync_program_idls(&db).await.unwrap(), 1);
+
+        let stored = db.get_discriminator("mainnet", &program_id.to_string(), &hex::encode(&discriminator)).await.unwrap().unwrap();
+        assert_eq!(stored.name.as_deref(), Some("incrementBy"));
+        assert_eq!(stored.source.as_deref(), Some("anchor_idl_account"));
+        assert_eq!(stored.instruction.instruction_data, observed);
+    }
+
+    #[tokio::test]
+    async fn records_how_deep_inner_instructions_were_invoked() {
+        let payer = Pubkey::new_unique();
+        let router = Pubkey::new_unique();
+        let pool = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let legacy = Pubkey::new_unique();
+
+        let message = v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 4,
+            },
+            account_keys: vec![payer, router, pool, vault, legacy],
+            recent_blockhash: Hash::default(),
+            instructions: vec![
+                CompiledInstruction::new_from_raw_parts(1, vec![1; 9], vec![0]),
+                CompiledInstruction::new_from_raw_parts(4, vec![5; 9], vec![0]),
+            ],
+            address_table_lookups: vec![],
+        };
+        let inner = |program_id_index: u8, data: u8, stack_height: Option<u32>| json!({
+            "programIdIndex": program_id_index,
+            "accounts": [0],
+            "data": bs58::encode([data; 9]).into_string(),
+            "stackHeight": stack_height,
+        });
+        // The router calls the pool, which calls the vault; the legacy program's CPI was
+        // recorded by a node that did not report stack heights
+        let fixture = confirmed_transaction(message, json!({
+            "innerInstructions": [
+                {
+                    "index": 0,
+                    "instructions": [inner(2, 2, Some(2)), inner(3, 3, Some(3)), inner(3, 4, Some(2))],
+                },
+                {
+                    "index": 1,
+                    "instructions": [inner(3, 6, None)],
+                },
+            ],
+        }));
+
+        let db = MemoryDatabase::new();
+        let connection = SolanaConnection::with_client(RpcClient::new_mock("succeeds".to_string()));
+        connection.process_transaction(&db, "fixture", &fixture).await;
+
+        let invocation = |program_id: Pubkey, data: u8| {
+            let db = &db;
+            async move {
+                db.get_discriminator("mainnet", &program_id.to_string(), &hex::encode([data; 8])).await
+                    .unwrap()
+                    .unwrap()
+                    .invocation
+            }
+        };
+        assert_eq!(invocation(router, 1).await, Some(Invocation { cpi: false, stack_height: Some(1) }));
+        assert_eq!(invocation(pool, 2).await, Some(Invocation { cpi: true, stack_height: Some(2) }));
+        assert_eq!(invocation(vault, 3).await, Some(Invocation { cpi: true, stack_height: Some(3) }));
+        assert_eq!(invocation(vault, 4).await, Some(Invocation { cpi: true, stack_height: Some(2) }));
+        assert_eq!(invocation(legacy, 5).await, Some(Invocation { cpi: false, stack_height: Some(1) }));
+        assert_eq!(invocation(vault, 6).await, Some(Invocation { cpi: true, stack_height: None }));
+    }
+
+    #[tokio::test]
+    async fn resumes_polling_from_the_stored_cursor() {
+        let program_id = Pubkey::new_from_array([7; 32]);
+        let recorded = || -> ChainFixture {
+            let json = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/chain.json")).unwrap();
+            serde_json::from_str(&json).unwrap()
+        };
+        // Signatures at slots 103, 102 and 101, newest first
+        let signatures = recorded().signatures[&program_id.to_string()].clone();
+
+        let message = v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![Pubkey::new_unique(), program_id],
+            recent_blockhash: Hash::default(),
+            instructions: vec![CompiledInstruction::new_from_raw_parts(1, vec![1; 9], vec![0])],
+            address_table_lookups: vec![],
+        };
+        let transaction = serde_json::to_value(confirmed_transaction(message, json!({}))).unwrap();
+        let connection = |fetchable: &[usize]| {
+            let mut fixture = recorded();
+            for &i in fetchable {
+                fixture.transactions.insert(signatures[i].signature.clone(), transaction.clone());
+            }
+            SolanaConnection::with_source(Arc::new(FixtureChainSource::new(fixture)))
+        };
+
+        // Listened to up to slot 101 before a restart
+        let db = MemoryDatabase::new();
+        let program = program_id.to_string();
+        db.upload_discriminator("mainnet", &program, vec![9; 8], vec![], "signer", Default::default()).await.unwrap();
+        db.update_program_cursor("mainnet", &program, &signatures[2].signature, 101).await.unwrap();
+
+        // After a restart the listener picks up from the stored cursor. The transaction at
+        // slot 102 cannot be fetched, so the cursor stays put even though slot 103's can.
+        let mut cursor = db.get_program_cursor("mainnet", &program).await.unwrap();
+        connection(&[0]).poll_transactions(&db, &program, &mut cursor).await;
+        assert_eq!(cursor.as_ref().unwrap().slot, 101);
+        assert_eq!(db.get_program_cursor("mainnet", &program).await.unwrap().unwrap().slot, 101);
+        assert!(db.get_discriminator("mainnet", &program, "0101010101010101").await.unwrap().is_none());
+
+        // The next cycle retries from there
+        let mut cursor = db.get_program_cursor("mainnet", &program).await.unwrap();
+        connection(&[0, 1]).poll_transactions(&db, &program, &mut cursor).await;
+        let stored = db.get_program_cursor("mainnet", &program).await.unwrap().unwrap();
+        assert_eq!(stored.signature, signatures[0].signature);
+        assert_eq!(stored.slot, 103);
+        assert!(db.get_discriminator("mainnet", &program, "0101010101010101").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn spaces_out_rate_limited_requests() {
+        let program_id = Pubkey::new_unique();
+        let connection = connection_with_idl_account(&idl_account_data(&Pubkey::new_unique()), &program_id)
+            .with_rate_limit(Some(20));
+
+        let started = tokio::time::Instant::now();
+        for _ in 0..3 {
+            connection.fetch_idl(&program_id.to_string()).await.unwrap();
+        }
+
+        // The first request goes out at once, the next two wait 50ms each
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    // v0 transaction whose CPI target program and first instruction account
+    // are only reachable through an address lookup table
+    #[tokio::test]
+    async fn resolves_lookup_table_addresses_in_v0_transactions() {
+        let payer = Pubkey::new_unique();
+        let outer_program = Pubkey::new_unique();
+        let lookup_table = Pubkey::new_unique();
+        let loaded_state = Pubkey::new_unique();
+        let loaded_program = Pubkey::new_unique();
+
+        let message = v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![payer, outer_program],
+            recent_blockhash: Hash::default(),
+            instructions: vec![CompiledInstruction::new_from_raw_parts(1, vec![1; 9], vec![2, 0])],
+            address_table_lookups: vec![MessageAddressTableLookup {
+                account_key: lookup_table,
+                writable_indexes: vec![0],
+                readonly_indexes: vec![1],
+            }],
+        };
+        let fixture = confirmed_transaction(message, json!({
+            "innerInstructions": [{
+                "index": 0,
+                "instructions": [{
+                    "programIdIndex": 3,
+                    "accounts": [2],
+                    "data": bs58::encode([2u8; 9]).into_string(),
+                    "stackHeight": 2,
+                }],
+            }],
+            "loadedAddresses": {
+                "writable": [loaded_state.to_string()],
+                "readonly": [loaded_program.to_string()],
+            },
+        }));
+
+        let uploads = Arc::new(Mutex::new(Vec::new()));
+        let recorded = uploads.clone();
+        // The CPI target tags its instructions with a single byte
+        let u8_program = loaded_program.to_string();
+        let mut db = MockDatabaseInterface::new();
+        db.expect_get_discriminator_scheme()
+            .returning(move |_, program_id| Ok(if program_id == u8_program {
+                DiscriminatorScheme::U8
+            } else {
+                DiscriminatorScheme::Anchor
+            }));
+        db.expect_upload_discriminator()
+            .returning(move |_, program_id, discriminator_data, _, user_id, metadata| {
+                recorded.lock().unwrap().push((
+                    program_id.to_string(),
+                    discriminator_data,
+                    user_id.to_string(),
+                    metadata.invocation,
+                ));
+                Ok(())
+            });
+
+        let connection = SolanaConnection::with_client(RpcClient::new_mock("succeeds".to_string()));
+        connection.process_transaction(&db, "fixture", &fixture).await;
+
+        let uploads = uploads.lock().unwrap();
+        assert_eq!(*uploads, vec![
+            (
+                outer_program.to_string(),
+                vec![1; 8],
+                loaded_state.to_string(),
+                Some(Invocation { cpi: false, stack_height: Some(1) }),
+            ),
+            (
+                loaded_program.to_string(),
+                vec![2],
+                loaded_state.to_string(),
+                Some(Invocation { cpi: true, stack_height: Some(2) }),
+            ),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn catalogues_events_only_from_anchor_programs_invoking_themselves() {
+        let payer = Pubkey::new_unique();
+        let anchor_program = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let token_like_program = Pubkey::new_unique();
+
+        let event_ix = |discriminator: u8| {
+            let mut data = EVENT_IX_TAG.to_vec();
+            data.extend_from_slice(&[discriminator; 8]);
+            bs58::encode(data).into_string()
+        };
+        let message = v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 3,
+            },
+            account_keys: vec![payer, anchor_program, other_program, token_like_program],
+            recent_blockhash: Hash::default(),
+            instructions: vec![CompiledInstruction::new_from_raw_parts(1, vec![1; 9], vec![0])],
+            address_table_lookups: vec![],
+        };
+        let fixture = confirmed_transaction(message, json!({
+            "logMessages": [
+                format!("Program {} invoke [1]", anchor_program),
+                format!("Program data: {}", STANDARD.encode([7u8; 9])),
+                format!("Program {} invoke [2]", token_like_program),
+                format!("Program data: {}", STANDARD.encode([8u8; 9])),
+                format!("Program {} success", token_like_program),
+                format!("Program {} success", anchor_program),
+            ],
+            "innerInstructions": [{
+                "index": 0,
+                "instructions": [
+                    // `emit_cpi!`: the program invokes itself
+                    { "programIdIndex": 1, "accounts": [], "data": event_ix(5), "stackHeight": 2 },
+                    { "programIdIndex": 2, "accounts": [], "data": bs58::encode([2u8; 9]).into_string(), "stackHeight": 2 },
+                    // Another program cannot emit events on its behalf
+                    { "programIdIndex": 1, "accounts": [], "data": event_ix(6), "stackHeight": 3 },
+                ],
+            }],
+        }));
+
+        let uploads = Arc::new(Mutex::new(Vec::new()));
+        let recorded = uploads.clone();
+        let u8_program = token_like_program.to_string();
+        let mut db = MockDatabaseInterface::new();
+        db.expect_get_discriminator_scheme()
+            .returning(move |_, program_id| Ok(if program_id == u8_program {
+                DiscriminatorScheme::U8
+            } else {
+                DiscriminatorScheme::Anchor
+            }));
+        db.expect_upload_discriminator()
+            .returning(move |_, program_id, discriminator_data, _, _, metadata| {
+                recorded.lock().unwrap().push((program_id.to_string(), discriminator_data, metadata.kind));
+                Ok(())
+            });
+
+        let connection = SolanaConnection::with_client(RpcClient::new_mock("succeeds".to_string()));
+        connection.process_transaction(&db, "fixture", &fixture).await;
+
+        let uploads = uploads.lock().unwrap();
+        assert_eq!(*uploads, vec![
+            (anchor_program.to_string(), vec![1; 8], DiscriminatorKind::Instruction),
+            (other_program.to_string(), vec![2; 8], DiscriminatorKind::Instruction),
+            (anchor_program.to_string(), vec![7; 8], DiscriminatorKind::Event),
+            (anchor_program.to_string(), vec![5; 8], DiscriminatorKind::Event),
+        ]);
+    }
+
+    // Stand-in pubsub server: acknowledges the logs subscription, notifies one signature, then hangs up
+    async fn serve_log_notification(listener: tokio::net::TcpListener, signature: String, slot: u64) {
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+        let request = loop {
+            if let Some(Ok(Message::Text(text))) = ws.next().await {
+                break serde_json::from_str::<serde_json::Value>(&text).unwrap();
+            }
+        };
+        assert_eq!(request["method"], "logsSubscribe");
+
+        let ack = json!({ "jsonrpc": "2.0", "result": 7, "id": request["id"] });
+        ws.send(Message::Text(ack.to_string())).await.unwrap();
+
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "logsNotification",
+            "params": {
+                "subscription": 7,
+                "result": {
+                    "context": { "slot": slot },
+                    "value": { "signature": signature, "err": null, "logs": [] },
+                },
+            },
+        });
+        ws.send(Message::Text(notification.to_string())).await.unwrap();
+        ws.close(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn streams_signatures_from_log_subscription() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ws_url = format!("ws://{}", listener.local_addr().unwrap());
+        let program_id = Pubkey::new_unique().to_string();
+        let signature = Signature::from([3u8; 64]).to_string();
+        tokio::spawn(serve_log_notification(listener, signature.clone(), 77));
+
+        // Nothing to catch up on, so only the streamed signature moves the cursor
+        let mut mocks = HashMap::new();
+        mocks.insert(RpcRequest::GetSignaturesForAddress, json!([]));
+        let connection = SolanaConnection::with_client(RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks));
+
+        let mut db = MockDatabaseInterface::new();
+        let expected = (program_id.clone(), signature.clone());
+        db.expect_update_program_cursor()
+            .withf(move |cluster, program_id, signature, slot| {
+                cluster == "mainnet" && (program_id.to_string(), signature.to_string()) == expected && *slot == 77
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut cursor = None;
+        connection.stream_program_logs(&db, &program_id, &ws_url, &mut cursor).await.unwrap();
+
+        let cursor = cursor.unwrap();
+        assert_eq!(cursor.signature, signature);
+        assert_eq!(cursor.slot, 77);
+    }
+}
@@ -0,0 +1,772 @@
+use std::error::Error;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use log::{info, debug};
+use sqlx::{Executor, SqlitePool, sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow}, Row, types::Json};
+use sqlx::migrate::{Migration, Migrator};
+
+use crate::graph_disc::{
+    hash_key, ApiKey, BackfillJob, DatabaseError, DatabaseInterface, Discriminator, DiscriminatorKind,
+    DiscriminatorMetadata, Instruction, InstructionRecord, Invocation, ProgramCursor,
+};
+use crate::idl::ArgsLayout;
+use crate::scheme::DiscriminatorScheme;
+use crate::migrations::{AppliedMigration, SchemaMigrations, SchemaStatus, SQLITE_MIGRATIONS};
+use crate::status::{DatabaseHealth, PoolStatus};
+
+// Struct for interacting with a SQLite database file, using the same schema and
+// upsert rules as `GraphDatabase`
+pub struct SqliteDatabase {
+    pool: SqlitePool,
+}
+
+impl Clone for SqliteDatabase {
+    fn clone(&self) -> Self {
+        SqliteDatabase {
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl DatabaseInterface for SqliteDatabase {
+    async fn upload_discriminator(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        instruction_data: Vec<u8>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        debug!("Uploading discriminator for program {} on {}", program_id, cluster);
+        self.upsert_discriminator(cluster, program_id, discriminator_data, Some(instruction_data), user_id, metadata).await
+    }
+
+    async fn set_discriminator_metadata(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        debug!("Setting metadata of discriminator for program {} on {}", program_id, cluster);
+        self.upsert_discriminator(cluster, program_id, discriminator_data, None, user_id, metadata).await
+    }
+
+    async fn query_discriminators_and_instructions(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<Discriminator>, DatabaseError> {
+        debug!("Querying discriminators for program {}", program_id);
+
+        let rows = sqlx::query(r#"
+            SELECT d.id, d.discriminator_id, d.discriminator_data, d.cluster, d.program_id, d.user_id,
+                   d.name, d.kind, d.source, d.args_layout, d.via_cpi, d.stack_height, d.authoritative,
+                   i.id as instruction_id, i.instruction_id as instruction_id_value, i.instruction_data
+            FROM discriminators d
+            JOIN instructions i ON d.instruction_id = i.id
+            WHERE d.cluster = ?1 AND d.program_id = ?2
+              AND (?3 IS NULL OR d.kind = ?3)
+            ORDER BY d.authoritative DESC, d.discriminator_id
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .bind(kind.map(|kind| kind.as_str()))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        let discriminators = rows.iter()
+            .map(Self::discriminator_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        info!("Found {} discriminators for program {}", discriminators.len(), program_id);
+        Ok(discriminators)
+    }
+
+    async fn get_all_program_ids(&self, cluster: &str) -> Result<Vec<String>, DatabaseError> {
+        debug!("Fetching all program IDs on {}", cluster);
+
+        let rows = sqlx::query("SELECT id FROM programs WHERE cluster = ?1")
+            .bind(cluster)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        let program_ids: Vec<String> = rows.iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        info!("Retrieved {} program IDs", program_ids.len());
+        Ok(program_ids)
+    }
+
+    async fn query_instructions_by_discriminator(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<String>, DatabaseError> {
+        debug!("Querying instructions for discriminator {}", discriminator_id);
+
+        let rows = sqlx::query(r#"
+            SELECT i.instruction_data
+            FROM instructions i
+            JOIN discriminators d ON i.id = d.instruction_id
+            WHERE d.cluster = ?1 AND d.discriminator_id = ?2
+              AND (?3 IS NULL OR d.kind = ?3)
+        "#)
+        .bind(cluster)
+        .bind(discriminator_id)
+        .bind(kind.map(|kind| kind.as_str()))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        let instructions: Vec<String> = rows.iter()
+            .map(|row| {
+                let instruction_data: Vec<u8> = row.get("instruction_data");
+                hex::encode(instruction_data)
+            })
+            .collect();
+
+        info!("Found {} instructions for discriminator {}", instructions.len(), discriminator_id);
+        Ok(instructions)
+    }
+
+    async fn get_discriminator(&self, cluster: &str, program_id: &str, discriminator_id: &str) -> Result<Option<Discriminator>, DatabaseError> {
+        debug!("Fetching discriminator {} for program {}", discriminator_id, program_id);
+
+        let row = sqlx::query(r#"
+            SELECT d.id, d.discriminator_id, d.discriminator_data, d.cluster, d.program_id, d.user_id,
+                   d.name, d.kind, d.source, d.args_layout, d.via_cpi, d.stack_height, d.authoritative,
+                   i.id as instruction_id, i.instruction_id as instruction_id_value, i.instruction_data
+            FROM discriminators d
+            JOIN instructions i ON d.instruction_id = i.id
+            WHERE d.cluster = ?1 AND d.program_id = ?2 AND d.discriminator_id = ?3
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .bind(discriminator_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        row.as_ref().map(Self::discriminator_from_row).transpose()
+    }
+
+    async fn query_instruction_records(
+        &self,
+        cluster: &str,
+        discriminator_id: &str,
+        kind: Option<DiscriminatorKind>,
+    ) -> Result<Vec<InstructionRecord>, DatabaseError> {
+        debug!("Querying instruction records for discriminator {}", discriminator_id);
+
+        let rows = sqlx::query(r#"
+            SELECT d.program_id, d.discriminator_id, d.name, d.args_layout, i.instruction_data
+            FROM instructions i
+            JOIN discriminators d ON i.id = d.instruction_id
+            WHERE d.cluster = ?1 AND d.discriminator_id = ?2
+              AND (?3 IS NULL OR d.kind = ?3)
+        "#)
+        .bind(cluster)
+        .bind(discriminator_id)
+        .bind(kind.map(|kind| kind.as_str()))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        let records: Vec<InstructionRecord> = rows.iter()
+            .map(|row| {
+                let args_layout: Option<Json<ArgsLayout>> = row.get("args_layout");
+                InstructionRecord {
+                    program_id: row.get("program_id"),
+                    discriminator_id: row.get("discriminator_id"),
+                    name: row.get("name"),
+                    instruction_data: row.get("instruction_data"),
+                    args_layout: args_layout.map(|layout| layout.0),
+                }
+            })
+            .collect();
+
+        info!("Found {} instruction records for discriminator {}", records.len(), discriminator_id);
+        Ok(records)
+    }
+
+    async fn get_program_cursor(&self, cluster: &str, program_id: &str) -> Result<Option<ProgramCursor>, DatabaseError> {
+        debug!("Fetching signature cursor for program {} on {}", program_id, cluster);
+
+        let row = sqlx::query("SELECT signature, slot FROM program_cursors WHERE cluster = ?1 AND program_id = ?2")
+            .bind(cluster)
+            .bind(program_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        Ok(row.map(|row| {
+            let slot: i64 = row.get("slot");
+            ProgramCursor {
+                cluster: cluster.to_string(),
+                program_id: program_id.to_string(),
+                signature: row.get("signature"),
+                slot: slot as u64,
+            }
+        }))
+    }
+
+    async fn update_program_cursor(&self, cluster: &str, program_id: &str, signature: &str, slot: u64) -> Result<(), DatabaseError> {
+        debug!("Advancing signature cursor for program {} on {} to {}", program_id, cluster, signature);
+
+        sqlx::query(r#"
+            INSERT INTO program_cursors (cluster, program_id, signature, slot, updated_at)
+            VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+            ON CONFLICT (cluster, program_id) DO UPDATE
+            SET signature = excluded.signature,
+                slot = excluded.slot,
+                updated_at = excluded.updated_at
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .bind(signature)
+        .bind(slot as i64)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn get_backfill_job(&self, cluster: &str, program_id: &str) -> Result<Option<BackfillJob>, DatabaseError> {
+        debug!("Fetching backfill job for program {} on {}", program_id, cluster);
+
+        let row = sqlx::query(r#"
+            SELECT cluster, program_id, status, stop_slot, before_signature, last_slot, processed, last_error
+            FROM backfill_jobs
+            WHERE cluster = ?1 AND program_id = ?2
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        row.as_ref().map(Self::backfill_job_from_row).transpose()
+    }
+
+    async fn get_running_backfill_jobs(&self) -> Result<Vec<BackfillJob>, DatabaseError> {
+        debug!("Fetching running backfill jobs");
+
+        let rows = sqlx::query(r#"
+            SELECT cluster, program_id, status, stop_slot, before_signature, last_slot, processed, last_error
+            FROM backfill_jobs
+            WHERE status = 'running'
+        "#)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        rows.iter().map(Self::backfill_job_from_row).collect()
+    }
+
+    async fn save_backfill_job(&self, job: &BackfillJob) -> Result<(), DatabaseError> {
+        debug!("Saving backfill job for program {} ({} processed)", job.program_id, job.processed);
+
+        sqlx::query(r#"
+            INSERT INTO backfill_jobs (program_id, status, stop_slot, before_signature, last_slot, processed, last_error, cluster, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, CURRENT_TIMESTAMP)
+            ON CONFLICT (cluster, program_id) DO UPDATE
+            SET status = excluded.status,
+                stop_slot = excluded.stop_slot,
+                before_signature = excluded.before_signature,
+                last_slot = excluded.last_slot,
+                processed = excluded.processed,
+                last_error = excluded.last_error,
+                updated_at = excluded.updated_at
+        "#)
+        .bind(&job.program_id)
+        .bind(job.status.as_str())
+        .bind(job.stop_slot.map(|slot| slot as i64))
+        .bind(&job.before_signature)
+        .bind(job.last_slot.map(|slot| slot as i64))
+        .bind(job.processed as i64)
+        .bind(&job.last_error)
+        .bind(&job.cluster)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn set_args_layout(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_id: &str,
+        args_layout: ArgsLayout,
+    ) -> Result<(), DatabaseError> {
+        debug!("Setting args layout for discriminator {} of program {}", discriminator_id, program_id);
+
+        let result = sqlx::query(r#"
+            UPDATE discriminators
+            SET args_layout = ?1
+            WHERE cluster = ?2 AND program_id = ?3 AND discriminator_id = ?4
+        "#)
+        .bind(Json(args_layout))
+        .bind(cluster)
+        .bind(program_id)
+        .bind(discriminator_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound(format!(
+                "discriminator {} for program {} on {}", discriminator_id, program_id, cluster
+            )));
+        }
+
+        info!("Stored args layout for discriminator {} of program {}", discriminator_id, program_id);
+        Ok(())
+    }
+
+    async fn get_discriminator_scheme(&self, cluster: &str, program_id: &str) -> Result<DiscriminatorScheme, DatabaseError> {
+        let stored: Option<Option<String>> = sqlx::query_scalar(
+            "SELECT discriminator_scheme FROM programs WHERE cluster = ?1 AND id = ?2"
+        )
+        .bind(cluster)
+        .bind(program_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        let stored = stored.flatten().map(|scheme| scheme.parse()).transpose()?;
+        Ok(DiscriminatorScheme::for_program(program_id, stored))
+    }
+
+    async fn set_discriminator_scheme(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        scheme: DiscriminatorScheme,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(r#"
+            INSERT INTO programs (cluster, id, discriminator_scheme)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (cluster, id) DO UPDATE
+            SET discriminator_scheme = excluded.discriminator_scheme
+        "#)
+        .bind(cluster)
+        .bind(program_id)
+        .bind(scheme.as_str())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        info!("Set discriminator scheme of program {} on {} to {}", program_id, cluster, scheme);
+        Ok(())
+    }
+
+    async fn create_api_key(&self, key_hash: &str, name: &str, user_id: &str, admin: bool) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+
+        sqlx::query("INSERT INTO users (id) VALUES (?1) ON CONFLICT (id) DO NOTHING")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        sqlx::query("INSERT INTO api_keys (key_hash, name, user_id, admin) VALUES (?1, ?2, ?3, ?4)")
+            .bind(key_hash)
+            .bind(name)
+            .bind(user_id)
+            .bind(admin)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        tx.commit().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+
+        info!("Created {} API key {} for user {}", if admin { "admin" } else { "upload" }, name, user_id);
+        Ok(())
+    }
+
+    async fn get_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, DatabaseError> {
+        let row = sqlx::query("SELECT user_id, admin FROM api_keys WHERE key_hash = ?1")
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        Ok(row.map(|row| ApiKey {
+            user_id: row.get("user_id"),
+            admin: row.get("admin"),
+        }))
+    }
+
+    async fn revoke_api_keys(&self, name: &str) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE name = ?1")
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+
+        info!("Revoked {} API keys named {}", result.rows_affected(), name);
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl SchemaMigrations for SqliteDatabase {
+    fn migrator(&self) -> &'static Migrator {
+        &SQLITE_MIGRATIONS
+    }
+
+    async fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, DatabaseError> {
+        sqlx::query(r#"
+            CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY,
+                description TEXT NOT NULL,
+                checksum BLOB NOT NULL,
+                applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+        "#)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+        let rows = sqlx::query("SELECT version, checksum FROM schema_migrations ORDER BY version")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+        Ok(rows.iter()
+            .map(|row| AppliedMigration {
+                version: row.get("version"),
+                checksum: row.get("checksum"),
+            })
+            .collect())
+    }
+
+    async fn apply_migration(&self, migration: &Migration) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+
+        tx.execute(&*migration.sql).await
+            .map_err(|e| DatabaseError::MigrationError(format!("migration {} failed: {}", migration.version, e)))?;
+
+        sqlx::query("INSERT INTO schema_migrations (version, description, checksum) VALUES (?1, ?2, ?3)")
+            .bind(migration.version)
+            .bind(&*migration.description)
+            .bind(&*migration.checksum)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+
+        tx.commit().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl DatabaseHealth for SqliteDatabase {
+    fn backend(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        Some(PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+            max_connections: self.pool.options().get_max_connections(),
+        })
+    }
+
+    async fn ping(&self) -> Result<(), DatabaseError> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::QueryError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn schema_status(&self) -> Result<Option<SchemaStatus>, DatabaseError> {
+        SchemaMigrations::schema_status(self).await.map(Some)
+    }
+}
+
+impl SqliteDatabase {
+    // Open (or create) the database file named by a `sqlite:` URL
+    pub async fn new(database_url: &str, max_connections: u32) -> Result<Self, Box<dyn Error>> {
+        let db = Self::connect(database_url, max_connections).await?;
+
+        // Bring the schema up to date, refusing to run against one this build does not know
+        let applied = db.run_migrations().await?;
+        if !applied.is_empty() {
+            info!("Applied schema migrations {:?}", applied);
+        }
+
+        info!("Successfully initialized database connection");
+
+        Ok(db)
+    }
+
+    // Open the database without touching the schema
+    pub async fn connect(database_url: &str, max_connections: u32) -> Result<Self, Box<dyn Error>> {
+        info!("Opening SQLite database at {}", database_url);
+
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true);
+
+        // Every connection to `sqlite::memory:` opens a separate database, so keep just one
+        let max_connections = if database_url.contains(":memory:") { 1 } else { max_connections };
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+
+        Ok(SqliteDatabase { pool })
+    }
+
+    // Insert or update a discriminator and the instruction data seen with it, if any
+    async fn upsert_discriminator(
+        &self,
+        cluster: &str,
+        program_id: &str,
+        discriminator_data: Vec<u8>,
+        instruction_data: Option<Vec<u8>>,
+        user_id: &str,
+        metadata: DiscriminatorMetadata,
+    ) -> Result<(), DatabaseError> {
+        self.get_discriminator_scheme(cluster, program_id).await?
+            .for_kind(metadata.kind)
+            .validate(&discriminator_data)?;
+
+        // Without instruction data, the discriminator keeps the instruction it was seen with
+        let observed = instruction_data.is_some();
+        let instruction_data = instruction_data.unwrap_or_default();
+        let discriminator_id = hex::encode(&discriminator_data);
+        let instruction_id = hex::encode(&instruction_data);
+
+        // Create a transaction so we can rollback if any part fails
+        let mut tx = self.pool.begin().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+
+        sqlx::query("INSERT INTO programs (cluster, id) VALUES (?1, ?2) ON CONFLICT (cluster, id) DO NOTHING")
+            .bind(cluster)
+            .bind(program_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        sqlx::query("INSERT INTO users (id) VALUES (?1) ON CONFLICT (id) DO NOTHING")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        // Generate unique IDs for discriminator and instruction
+        let discriminator_unique_id = format!("{}_{}_{}", cluster, program_id, discriminator_id);
+        let instruction_unique_id = format!("{}_{}", program_id, hash_key(&instruction_id));
+
+        sqlx::query(r#"
+            INSERT INTO instructions (id, instruction_id, instruction_data)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (id) DO UPDATE
+            SET instruction_id = excluded.instruction_id,
+                instruction_data = excluded.instruction_data
+        "#)
+        .bind(&instruction_unique_id)
+        .bind(&instruction_id)
+        .bind(&instruction_data)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        // A known name, kind or source is never overwritten by an anonymous upload, and
+        // labels from a verified upgrade authority are never overwritten by anyone else
+        sqlx::query(r#"
+            INSERT INTO discriminators (id, discriminator_id, discriminator_data, instruction_id, user_id, program_id, name, kind, source, args_layout, via_cpi, stack_height, cluster, authoritative)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+            ON CONFLICT (id) DO UPDATE
+            SET discriminator_id = excluded.discriminator_id,
+                discriminator_data = excluded.discriminator_data,
+                instruction_id = CASE WHEN ?15 THEN excluded.instruction_id ELSE discriminators.instruction_id END,
+                user_id = CASE WHEN discriminators.authoritative AND NOT excluded.authoritative THEN discriminators.user_id ELSE excluded.user_id END,
+                name = CASE WHEN discriminators.authoritative AND NOT excluded.authoritative THEN discriminators.name ELSE COALESCE(excluded.name, discriminators.name) END,
+                kind = CASE WHEN (discriminators.authoritative AND NOT excluded.authoritative) OR excluded.kind = 'unknown' THEN discriminators.kind ELSE excluded.kind END,
+                source = CASE WHEN discriminators.authoritative AND NOT excluded.authoritative THEN discriminators.source ELSE COALESCE(excluded.source, discriminators.source) END,
+                args_layout = CASE WHEN discriminators.authoritative AND NOT excluded.authoritative THEN discriminators.args_layout ELSE COALESCE(excluded.args_layout, discriminators.args_layout) END,
+                via_cpi = COALESCE(excluded.via_cpi, discriminators.via_cpi),
+                stack_height = CASE WHEN excluded.via_cpi IS NULL THEN discriminators.stack_height ELSE excluded.stack_height END,
+                authoritative = discriminators.authoritative OR excluded.authoritative
+        "#)
+        .bind(&discriminator_unique_id)
+        .bind(&discriminator_id)
+        .bind(&discriminator_data)
+        .bind(&instruction_unique_id)
+        .bind(user_id)
+        .bind(program_id)
+        .bind(&metadata.name)
+        .bind(metadata.kind.as_str())
+        .bind(&metadata.source)
+        .bind(metadata.args_layout.map(Json))
+        .bind(metadata.invocation.map(|invocation| invocation.cpi))
+        .bind(metadata.invocation.and_then(|invocation| invocation.stack_height).map(|height| height as i32))
+        .bind(cluster)
+        .bind(metadata.authoritative)
+        .bind(observed)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DatabaseError::InsertionError(e.to_string()))?;
+
+        tx.commit().await
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+
+        info!("Successfully uploaded discriminator {} for program {} on {}", discriminator_id, program_id, cluster);
+        Ok(())
+    }
+
+    // Map a discriminators row joined with its instruction
+    fn discriminator_from_row(row: &SqliteRow) -> Result<Discriminator, DatabaseError> {
+        let kind: String = row.get("kind");
+        let args_layout: Option<Json<ArgsLayout>> = row.get("args_layout");
+        let via_cpi: Option<bool> = row.get("via_cpi");
+        let stack_height: Option<i32> = row.get("stack_height");
+
+        Ok(Discriminator {
+            id: row.get("id"),
+            discriminator_id: row.get("discriminator_id"),
+            discriminator_data: row.get("discriminator_data"),
+            cluster: row.get("cluster"),
+            program_id: row.get("program_id"),
+            user_id: row.get("user_id"),
+            name: row.get("name"),
+            kind: kind.parse()?,
+            source: row.get("source"),
+            authoritative: row.get("authoritative"),
+            args_layout: args_layout.map(|layout| layout.0),
+            invocation: via_cpi.map(|cpi| Invocation {
+                cpi,
+                stack_height: stack_height.map(|height| height as u32),
+            }),
+            instruction: Instruction {
+                id: row.get("instruction_id"),
+                instruction_id: row.get("instruction_id_value"),
+                instruction_data: row.get("instruction_data"),
+            },
+        })
+    }
+
+    fn backfill_job_from_row(row: &SqliteRow) -> Result<BackfillJob, DatabaseError> {
+        let status: String = row.get("status");
+        let stop_slot: Option<i64> = row.get("stop_slot");
+        let last_slot: Option<i64> = row.get("last_slot");
+        let processed: i64 = row.get("processed");
+
+        Ok(BackfillJob {
+            cluster: row.get("cluster"),
+            program_id: row.get("program_id"),
+            status: status.parse()?,
+            stop_slot: stop_slot.map(|slot| slot as u64),
+            before_signature: row.get("before_signature"),
+            last_slot: last_slot.map(|slot| slot as u64),
+            processed: processed as u64,
+            last_error: row.get("last_error"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph_disc::BackfillStatus;
+
+    #[tokio::test]
+    async fn round_trips_discriminators_with_postgres_upsert_rules() {
+        let db = SqliteDatabase::new("sqlite::memory:", 1).await.unwrap();
+        let discriminator = vec![1, 2, 3, 4, 5, 6, 7, 8];
+
+        // Already at the newest version, so nothing is left to apply
+        assert!(db.run_migrations().await.unwrap().is_empty());
+        assert!(db.migration_status().await.unwrap().iter().all(|migration| migration.applied));
+
+        assert!(db.upload_discriminator("devnet", "program", vec![1; 4], vec![], "user", Default::default()).await.is_err());
+
+        db.upload_discriminator("devnet", "program", discriminator.clone(), vec![9], "idl-user", DiscriminatorMetadata {
+            name: Some("initialize".to_string()),
+            kind: DiscriminatorKind::Instruction,
+            args_layout: Some(ArgsLayout::new(vec![], &[])),
+            ..Default::default()
+        }).await.unwrap();
+        db.upload_discriminator("devnet", "program", discriminator, vec![10], "signer", DiscriminatorMetadata {
+            invocation: Some(Invocation { cpi: true, stack_height: Some(2) }),
+            ..Default::default()
+        }).await.unwrap();
+
+        let stored = db.get_discriminator("devnet", "program", "0102030405060708").await.unwrap().unwrap();
+        assert_eq!(stored.name.as_deref(), Some("initialize"));
+        assert_eq!(stored.kind, DiscriminatorKind::Instruction);
+        assert!(stored.args_layout.is_some());
+        assert_eq!(stored.invocation, Some(Invocation { cpi: true, stack_height: Some(2) }));
+        assert_eq!(stored.user_id, "signer");
+        assert_eq!(db.query_instructions_by_discriminator("devnet", "0102030405060708", Some(DiscriminatorKind::Instruction)).await.unwrap(), vec!["0a"]);
+        assert!(db.query_discriminators_and_instructions("devnet", "program", Some(DiscriminatorKind::Event)).await.unwrap().is_empty());
+
+        // Renaming from an IDL keeps the instruction data seen on chain
+        db.set_discriminator_metadata("devnet", "program", vec![1, 2, 3, 4, 5, 6, 7, 8], "idl-user", DiscriminatorMetadata {
+            name: Some("init".to_string()),
+            kind: DiscriminatorKind::Instruction,
+            source: Some("idl".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        let renamed = db.get_discriminator("devnet", "program", "0102030405060708").await.unwrap().unwrap();
+        assert_eq!(renamed.name.as_deref(), Some("init"));
+        assert_eq!(renamed.instruction.instruction_data, vec![10]);
+
+        db.update_program_cursor("devnet", "program", "signature", 42).await.unwrap();
+        assert_eq!(db.get_program_cursor("devnet", "program").await.unwrap().unwrap().slot, 42);
+
+        db.save_backfill_job(&BackfillJob::new("devnet", "program", Some(7))).await.unwrap();
+        let jobs = db.get_running_backfill_jobs().await.unwrap();
+        assert_eq!(jobs[0].status, BackfillStatus::Running);
+        assert_eq!(jobs[0].stop_slot, Some(7));
+
+        // A stored scheme outlives later uploads
+        db.set_discriminator_scheme("devnet", "program", DiscriminatorScheme::U8).await.unwrap();
+        db.upload_discriminator("devnet", "program", vec![4], vec![], "user", Default::default()).await.unwrap();
+        assert_eq!(db.get_discriminator_scheme("devnet", "program").await.unwrap(), DiscriminatorScheme::U8);
+        assert_eq!(db.get_discriminator_scheme("devnet", "11111111111111111111111111111111").await.unwrap(), DiscriminatorScheme::U32Le);
+    }
+
+    #[tokio::test]
+    async fn authoritative_labels_outrank_community_uploads() {
+        let db = SqliteDatabase::new("sqlite::memory:", 1).await.unwrap();
+        let named = |name: &str, authoritative: bool| DiscriminatorMetadata {
+            name: Some(name.to_string()),
+            kind: DiscriminatorKind::Instruction,
+            authoritative,
+            ..Default::default()
+        };
+
+        db.upload_discriminator("devnet", "program", vec![1; 8], vec![], "community", named("first", false)).await.unwrap();
+        db.upload_discriminator("devnet", "program", vec![2; 8], vec![], "community", named("guess", false)).await.unwrap();
+        db.upload_discriminator("devnet", "program", vec![2; 8], vec![], "authority", named("swap", true)).await.unwrap();
+        db.upload_discriminator("devnet", "program", vec![2; 8], vec![], "community", named("fake_swap", false)).await.unwrap();
+
+        let discriminators = db.query_discriminators_and_instructions("devnet", "program", None).await.unwrap();
+        assert_eq!(discriminators[0].name.as_deref(), Some("swap"));
+        assert_eq!(discriminators[0].user_id, "authority");
+        assert!(discriminators[0].authoritative);
+        assert_eq!(discriminators[1].name.as_deref(), Some("first"));
+        assert!(!discriminators[1].authoritative);
+    }
+}
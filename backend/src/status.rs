@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse};
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::cluster::Clusters;
+use crate::graph_disc::DatabaseError;
+use crate::ingest::ListenerStatus;
+use crate::migrations::SchemaStatus;
+use crate::readiness::{Readiness, ReadinessState};
+use crate::solana_connection::{ChainSource, SolanaConnection};
+
+// How long a cluster's RPC node gets to answer a status check
+const RPC_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Connection pool usage of a storage backend
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PoolStatus {
+    pub size: u32,
+    pub idle: usize,
+    pub max_connections: u32,
+}
+
+// What a storage backend can tell about its own health
+#[async_trait]
+pub trait DatabaseHealth: Send + Sync {
+    fn backend(&self) -> &'static str;
+
+    // None for backends without a connection pool
+    fn pool_status(&self) -> Option<PoolStatus>;
+
+    // Make a round trip to the database
+    async fn ping(&self) -> Result<(), DatabaseError>;
+
+    // None for backends without a versioned schema
+    async fn schema_status(&self) -> Result<Option<SchemaStatus>, DatabaseError>;
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    // `ok`, `degraded` (a cluster is unreachable or a listener stopped) or `unavailable` (no database)
+    pub status: &'static str,
+    pub database: DatabaseReport,
+    pub clusters: Vec<ClusterReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseReport {
+    pub readiness: ReadinessState,
+    pub backend: Option<&'static str>,
+    pub reachable: bool,
+    pub error: Option<String>,
+    pub pool: Option<PoolStatus>,
+    pub schema: Option<SchemaStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterReport {
+    pub name: String,
+    pub reachable: bool,
+    pub slot: Option<u64>,
+    pub error: Option<String>,
+    pub listeners: Vec<ListenerReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListenerReport {
+    #[serde(flatten)]
+    pub listener: ListenerStatus,
+    // Slots between the cluster's newest slot and the listener's last transaction
+    pub lag: Option<u64>,
+}
+
+async fn database_report(readiness: &Readiness) -> DatabaseReport {
+    let mut report = DatabaseReport {
+        readiness: readiness.state(),
+        backend: None,
+        reachable: false,
+        error: None,
+        pool: None,
+        schema: None,
+    };
+
+    let Some(health) = readiness.health() else {
+        return report;
+    };
+    report.backend = Some(health.backend());
+    report.pool = health.pool_status();
+
+    let checked = match health.ping().await {
+        Ok(()) => health.schema_status().await,
+        Err(e) => Err(e),
+    };
+    match checked {
+        Ok(schema) => {
+            report.reachable = true;
+            report.schema = schema;
+        },
+        Err(e) => report.error = Some(e.to_string()),
+    }
+    report
+}
+
+async fn cluster_report(solana_client: &SolanaConnection) -> ClusterReport {
+    let (slot, error) = match tokio::time::timeout(RPC_CHECK_TIMEOUT, solana_client.get_slot()).await {
+        Ok(Ok(slot)) => (Some(slot), None),
+        Ok(Err(e)) => (None, Some(e.to_string())),
+        Err(_) => (None, Some(format!("No answer within {:?}", RPC_CHECK_TIMEOUT))),
+    };
+
+    let listeners = solana_client.listeners().snapshot()
+        .into_iter()
+        .map(|listener| ListenerReport {
+            lag: slot.zip(listener.last_slot).map(|(slot, last_slot)| slot.saturating_sub(last_slot)),
+            listener,
+        })
+        .collect();
+
+    ClusterReport {
+        name: solana_client.cluster().to_string(),
+        reachable: slot.is_some(),
+        slot,
+        error,
+        listeners,
+    }
+}
+
+// Check the database and every cluster, concurrently
+pub async fn status_report(readiness: &Readiness, clusters: &Clusters) -> StatusReport {
+    let (database, clusters) = futures_util::join!(
+        database_report(readiness),
+        join_all(clusters.iter().map(cluster_report)),
+    );
+
+    let degraded = clusters.iter().any(|cluster| {
+        !cluster.reachable || cluster.listeners.iter().any(|listener| !listener.listener.running)
+    });
+    let status = match (database.reachable, degraded) {
+        (false, _) => "unavailable",
+        (true, true) => "degraded",
+        (true, false) => "ok",
+    };
+
+    StatusReport { status, database, clusters }
+}
+
+// Liveness: the process answers, and not every listener it started has died
+pub async fn healthz(clusters: web::Data<Clusters>) -> HttpResponse {
+    let listeners: Vec<ListenerStatus> = clusters.iter()
+        .flat_map(|solana_client| solana_client.listeners().snapshot())
+        .collect();
+
+    if !listeners.is_empty() && listeners.iter().all(|listener| !listener.running) {
+        return HttpResponse::ServiceUnavailable().json(json!({"status": "listeners_stopped"}));
+    }
+    HttpResponse::Ok().json(json!({"status": "alive"}))
+}
+
+// Readiness: the database is connected and answers
+pub async fn readyz(readiness: web::Data<Readiness>) -> HttpResponse {
+    let Some(health) = readiness.health() else {
+        return HttpResponse::ServiceUnavailable().json(json!({"status": "not_ready", "database": readiness.state()}));
+    };
+
+    match health.ping().await {
+        Ok(()) => HttpResponse::Ok().json(json!({"status": "ready"})),
+        Err(e) => HttpResponse::ServiceUnavailable().json(json!({"status": "not_ready", "error": e.to_string()})),
+    }
+}
+
+pub async fn status_endpoint(readiness: web::Data<Readiness>, clusters: web::Data<Clusters>) -> HttpResponse {
+    HttpResponse::Ok().json(status_report(&readiness, &clusters).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use actix_web::{http::StatusCode, test, App};
+    use crate::chain_fixture::FixtureChainSource;
+    use crate::memory_db::MemoryDatabase;
+
+    #[actix_web::test]
+    async fn reports_database_clusters_and_listeners() {
+        let source = FixtureChainSource::load(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/chain.json")).unwrap();
+        let solana_client = SolanaConnection::with_source(Arc::new(source));
+        solana_client.listeners().started("program", "rpc");
+        solana_client.listeners().advanced("program", "signature", 100);
+
+        let readiness = Arc::new(Readiness::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(readiness.clone()))
+                .app_data(web::Data::new(Clusters::new(vec![solana_client.clone()])))
+                .route("/healthz", web::get().to(healthz))
+                .route("/readyz", web::get().to(readyz))
+                .route("/status", web::get().to(status_endpoint))
+        ).await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        assert_eq!(test::call_service(&app, get("/healthz")).await.status(), StatusCode::OK);
+        assert_eq!(test::call_service(&app, get("/readyz")).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let database = Arc::new(MemoryDatabase::new());
+        readiness.set_ready(database.clone(), database);
+        assert_eq!(test::call_service(&app, get("/readyz")).await.status(), StatusCode::OK);
+
+        let report: serde_json::Value = test::call_and_read_body_json(&app, get("/status")).await;
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["database"]["backend"], "memory");
+        assert_eq!(report["clusters"][0]["name"], "mainnet");
+        assert_eq!(report["clusters"][0]["slot"], 103);
+        assert_eq!(report["clusters"][0]["listeners"][0]["last_signature"], "signature");
+        assert_eq!(report["clusters"][0]["listeners"][0]["lag"], 3);
+
+        solana_client.listeners().stopped("program", Some("socket closed".to_string()));
+        assert_eq!(test::call_service(&app, get("/healthz")).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let report: serde_json::Value = test::call_and_read_body_json(&app, get("/status")).await;
+        assert_eq!(report["status"], "degraded");
+    }
+}
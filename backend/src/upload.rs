@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::graph_disc::{DiscriminatorKind, DiscriminatorMetadata};
+use crate::idl::ArgsLayout;
+use crate::scheme::DiscriminatorScheme;
+
+// Sources the backend records itself, for IDLs and the seeded catalogue. Uploads may not claim
+// them, or their labels would pass for ones derived from an IDL.
+const RESERVED_SOURCES: &[&str] = &["idl", "anchor_idl_account"];
+const RESERVED_SOURCE_PREFIX: &str = "catalogue";
+
+fn is_reserved_source(source: &str) -> bool {
+    let source = source.trim().to_ascii_lowercase();
+    RESERVED_SOURCES.contains(&source.as_str()) || source.starts_with(RESERVED_SOURCE_PREFIX)
+}
+
+// How the byte strings of an upload are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataEncoding {
+    // Case-insensitive, with an optional `0x` prefix and any whitespace between bytes
+    #[default]
+    Hex,
+    Base58,
+    Base64,
+}
+
+impl DataEncoding {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "hex" => Some(DataEncoding::Hex),
+            "base58" => Some(DataEncoding::Base58),
+            "base64" => Some(DataEncoding::Base64),
+            _ => None,
+        }
+    }
+
+    pub fn decode(&self, data: &str) -> Result<Vec<u8>, String> {
+        match self {
+            DataEncoding::Hex => {
+                let digits: String = data.split_whitespace().collect();
+                let digits = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")).unwrap_or(&digits);
+                hex::decode(digits).map_err(|e| format!("Invalid hex: {}", e))
+            },
+            DataEncoding::Base58 => bs58::decode(data.trim()).into_vec()
+                .map_err(|e| format!("Invalid base58: {}", e)),
+            DataEncoding::Base64 => STANDARD.decode(data.trim())
+                .map_err(|e| format!("Invalid base64: {}", e)),
+        }
+    }
+}
+
+// Body of `POST /upload_discriminator/{program_id}`. Fields are kept loosely typed so
+// that every problem can be reported against the field that caused it.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiscriminatorUpload {
+    pub discriminator: Option<String>,
+    // `hex` (the default), `base58` or `base64`; applies to `discriminator` and `instruction_data`
+    pub encoding: Option<String>,
+    pub name: Option<String>,
+    pub kind: Option<String>,
+    pub args_layout: Option<Value>,
+    pub source: Option<String>,
+    // Bytes following the discriminator in an observed instruction or account
+    pub instruction_data: Option<String>,
+}
+
+// Problems with an upload, keyed by field name
+pub type FieldErrors = BTreeMap<&'static str, String>;
+
+// An upload whose fields all decoded and whose discriminator fits the program's scheme
+#[derive(Debug)]
+pub struct ValidatedUpload {
+    pub discriminator_data: Vec<u8>,
+    pub instruction_data: Vec<u8>,
+    pub metadata: DiscriminatorMetadata,
+}
+
+impl DiscriminatorUpload {
+    pub fn validate(self, scheme: DiscriminatorScheme) -> Result<ValidatedUpload, FieldErrors> {
+        let mut errors = FieldErrors::new();
+
+        let encoding = match self.encoding.as_deref().map(DataEncoding::parse) {
+            None => Some(DataEncoding::default()),
+            Some(Some(encoding)) => Some(encoding),
+            Some(None) => {
+                errors.insert("encoding", "Expected one of hex, base58 or base64".to_string());
+                None
+            },
+        };
+
+        // The scheme only applies to instruction tags
+        let kind = match self.kind.as_deref().map(str::parse::<DiscriminatorKind>) {
+            None => DiscriminatorKind::Unknown,
+            Some(Ok(kind)) => kind,
+            Some(Err(_)) => {
+                errors.insert("kind", "Expected one of instruction, account, event or unknown".to_string());
+                DiscriminatorKind::Unknown
+            },
+        };
+
+        let discriminator_data = match (&self.discriminator, encoding) {
+            (None, _) => {
+                errors.insert("discriminator", "Required".to_string());
+                None
+            },
+            (Some(discriminator), Some(encoding)) => match encoding.decode(discriminator) {
+                Ok(data) => match scheme.for_kind(kind).validate(&data) {
+                    Ok(()) => Some(data),
+                    Err(e) => {
+                        errors.insert("discriminator", e.to_string());
+                        None
+                    },
+                },
+                Err(e) => {
+                    errors.insert("discriminator", e);
+                    None
+                },
+            },
+            // Cannot be decoded without a valid encoding
+            (Some(_), None) => None,
+        };
+
+        let instruction_data = match (&self.instruction_data, encoding) {
+            (Some(data), Some(encoding)) => encoding.decode(data).unwrap_or_else(|e| {
+                errors.insert("instruction_data", e);
+                Vec::new()
+            }),
+            _ => Vec::new(),
+        };
+
+        let args_layout = match self.args_layout.map(serde_json::from_value::<ArgsLayout>) {
+            None => None,
+            Some(Ok(layout)) => Some(layout),
+            Some(Err(e)) => {
+                errors.insert("args_layout", format!("Invalid layout: {}", e));
+                None
+            },
+        };
+
+        if self.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+            errors.insert("name", "Must not be empty".to_string());
+        }
+        if self.source.as_deref().is_some_and(is_reserved_source) {
+            errors.insert("source", "Reserved for discriminators derived by the backend".to_string());
+        }
+
+        match discriminator_data {
+            Some(discriminator_data) if errors.is_empty() => Ok(ValidatedUpload {
+                discriminator_data,
+                instruction_data,
+                metadata: DiscriminatorMetadata {
+                    name: self.name,
+                    kind,
+                    source: self.source,
+                    authoritative: false,
+                    args_layout,
+                    invocation: None,
+                },
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn upload(body: Value) -> DiscriminatorUpload {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn decodes_discriminators_in_every_encoding() {
+        let expected = vec![0xaf, 0xaf, 0x6d, 0x1f, 0x0d, 0x98, 0x9b, 0xed];
+        for (discriminator, encoding) in [
+            ("af af 6d 1f 0d 98 9b ed", "hex"),
+            ("0xAFAF6D1F0D989BED", "hex"),
+            ("0Xafaf6d1f0d989bed", "hex"),
+            (bs58::encode(&expected).into_string().as_str(), "base58"),
+            (STANDARD.encode(&expected).as_str(), "base64"),
+        ] {
+            let validated = upload(json!({"discriminator": discriminator, "encoding": encoding}))
+                .validate(DiscriminatorScheme::Anchor)
+                .unwrap();
+            assert_eq!(validated.discriminator_data, expected);
+        }
+
+        let validated = upload(json!({
+            "discriminator": "03",
+            "instruction_data": "e803000000000000",
+            "name": "transfer",
+            "kind": "instruction",
+            "args_layout": {"fields": [{"name": "amount", "type": "u64"}]},
+            "source": "explorer",
+        }))
+        .validate(DiscriminatorScheme::U8)
+        .unwrap();
+        assert_eq!(validated.discriminator_data, vec![3]);
+        assert_eq!(validated.instruction_data, 1000u64.to_le_bytes().to_vec());
+        assert_eq!(validated.metadata.kind, DiscriminatorKind::Instruction);
+        assert!(validated.metadata.args_layout.is_some());
+
+        // Events keep their 8-byte discriminator whatever the instruction scheme
+        let validated = upload(json!({"discriminator": "0102030405060708", "kind": "event"}))
+            .validate(DiscriminatorScheme::U8)
+            .unwrap();
+        assert_eq!(validated.discriminator_data.len(), 8);
+    }
+
+    #[test]
+    fn reports_errors_per_field() {
+        let errors = upload(json!({
+            "discriminator": "zz",
+            "kind": "program",
+            "args_layout": {"fields": 1},
+            "name": " ",
+        }))
+        .validate(DiscriminatorScheme::Anchor)
+        .unwrap_err();
+        assert_eq!(errors.keys().copied().collect::<Vec<_>>(), vec!["args_layout", "discriminator", "kind", "name"]);
+
+        let errors = upload(json!({"discriminator": "0102", "encoding": "hex"}))
+            .validate(DiscriminatorScheme::Anchor)
+            .unwrap_err();
+        assert!(errors["discriminator"].contains("expected 8 bytes"));
+
+        let errors = upload(json!({"encoding": "base32"})).validate(DiscriminatorScheme::Anchor).unwrap_err();
+        assert_eq!(errors.keys().copied().collect::<Vec<_>>(), vec!["discriminator", "encoding"]);
+
+        for source in ["idl", " IDL", "anchor_idl_account", "catalogue-v1"] {
+            let errors = upload(json!({"discriminator": "0102030405060708", "source": source}))
+                .validate(DiscriminatorScheme::Anchor)
+                .unwrap_err();
+            assert_eq!(errors.keys().copied().collect::<Vec<_>>(), vec!["source"]);
+        }
+        assert!(upload(json!({"discriminator": "0102030405060708", "source": "explorer"})).validate(DiscriminatorScheme::Anchor).is_ok());
+    }
+}
@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::stream;
+use futures_util::StreamExt;
+use log::{info, error, warn, debug};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::{legacy, v0, MessageHeader, VersionedMessage};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{TransactionError, VersionedTransaction};
+use solana_transaction_status::{
+    ConfirmedTransactionWithStatusMeta, EncodedConfirmedTransactionWithStatusMeta, InnerInstruction,
+    InnerInstructions, TransactionStatusMeta, TransactionWithStatusMeta, UiTransactionEncoding,
+    VersionedTransactionWithStatusMeta,
+};
+use tonic::metadata::AsciiMetadataValue;
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, ClientTlsConfig};
+
+use crate::graph_disc::{DatabaseInterface, ProgramCursor};
+use crate::ingest::IngestionSource;
+use crate::solana_connection::{SolanaConnection, SolanaError};
+
+use proto::geyser::{
+    geyser_client::GeyserClient, subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
+    SubscribeRequestFilterTransactions, SubscribeUpdateTransaction,
+};
+use proto::solana::storage::confirmed_block;
+
+// Code generated from the vendored Geyser protocol in `proto/`
+#[allow(clippy::all, dead_code)]
+pub mod proto {
+    pub mod geyser {
+        tonic::include_proto!("geyser");
+    }
+
+    pub mod solana {
+        pub mod storage {
+            pub mod confirmed_block {
+                tonic::include_proto!("solana.storage.confirmed_block");
+            }
+        }
+    }
+}
+
+// Delay before reconnecting after the stream drops
+const RECONNECT_DELAY_SECS: u64 = 5;
+
+// Streams the transactions of tracked programs from a Yellowstone gRPC (Geyser plugin) endpoint.
+// Transactions that land while the stream is down are caught up over RPC on reconnect.
+pub struct YellowstoneSource {
+    endpoint: String,
+    x_token: Option<String>,
+    solana_client: SolanaConnection,
+}
+
+impl YellowstoneSource {
+    pub fn new(endpoint: &str, x_token: Option<String>, solana_client: SolanaConnection) -> Self {
+        YellowstoneSource {
+            endpoint: endpoint.to_string(),
+            x_token,
+            solana_client,
+        }
+    }
+
+    async fn connect(&self) -> Result<Channel, SolanaError> {
+        let mut endpoint = Channel::from_shared(self.endpoint.clone())
+            .map_err(|e| SolanaError::SubscriptionError(format!("Invalid gRPC endpoint {}: {}", self.endpoint, e)))?;
+
+        if self.endpoint.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())
+                .map_err(|e| SolanaError::SubscriptionError(e.to_string()))?;
+        }
+
+        endpoint.connect()
+            .await
+            .map_err(|e| SolanaError::SubscriptionError(format!("Failed to connect to {}: {}", self.endpoint, e)))
+    }
+
+    // Subscribe once and ingest transactions until the stream ends or fails
+    async fn stream_transactions(
+        &self,
+        db: &dyn DatabaseInterface,
+        program_ids: &[String],
+        cursors: &mut HashMap<String, Option<ProgramCursor>>,
+    ) -> Result<(), SolanaError> {
+        let x_token = self.x_token.as_deref()
+            .map(AsciiMetadataValue::try_from)
+            .transpose()
+            .map_err(|e| SolanaError::SubscriptionError(format!("Invalid x-token: {}", e)))?;
+
+        let channel = self.connect().await?;
+        let mut client = GeyserClient::with_interceptor(channel, XToken(x_token));
+
+        // Yellowstone ends the subscription when the request stream closes, so keep it open
+        let requests = stream::iter([subscribe_request(program_ids)]).chain(stream::pending());
+        let mut updates = client.subscribe(requests)
+            .await
+            .map_err(|e| SolanaError::SubscriptionError(e.to_string()))?
+            .into_inner();
+        info!("Streaming transactions for {} programs from {}", program_ids.len(), self.endpoint);
+
+        // Pick up whatever landed while the stream was down
+        for program_id in program_ids {
+            let cursor = cursors.entry(program_id.clone()).or_default();
+            self.solana_client.poll_transactions(db, program_id, cursor).await;
+        }
+
+        while let Some(update) = updates.message()
+            .await
+            .map_err(|e| SolanaError::SubscriptionError(e.to_string()))?
+        {
+            let Some(UpdateOneof::Transaction(update)) = update.update_oneof else {
+                continue;
+            };
+            let slot = update.slot;
+
+            let (signature, transaction) = match decode_transaction_update(update) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    warn!("Skipping undecodable transaction update at slot {}: {}", slot, e);
+                    continue;
+                }
+            };
+            let account_keys = transaction.transaction.message.static_account_keys().iter()
+                .chain(&transaction.meta.loaded_addresses.writable)
+                .chain(&transaction.meta.loaded_addresses.readonly)
+                .map(|key| key.to_string())
+                .collect::<Vec<_>>();
+
+            let encoded = match encode_transaction(slot, transaction) {
+                Ok(encoded) => encoded,
+                Err(e) => {
+                    warn!("Skipping transaction {}: {}", signature, e);
+                    continue;
+                }
+            };
+            debug!("Received transaction {} at slot {}", signature, slot);
+            self.solana_client.process_transaction(db, &signature, &encoded).await;
+
+            // Keep the cursors of the programs it mentions current for the RPC catch-up
+            for program_id in program_ids.iter().filter(|program_id| account_keys.contains(program_id)) {
+                let cursor = cursors.entry(program_id.clone()).or_default();
+                self.solana_client.advance_cursor(db, program_id, cursor, signature.clone(), slot).await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Attaches the access token hosted Yellowstone endpoints expect in the `x-token` header
+#[derive(Clone)]
+struct XToken(Option<AsciiMetadataValue>);
+
+impl Interceptor for XToken {
+    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        if let Some(x_token) = self.0.clone() {
+            request.metadata_mut().insert("x-token", x_token);
+        }
+        Ok(request)
+    }
+}
+
+#[async_trait]
+impl IngestionSource for YellowstoneSource {
+    fn name(&self) -> &'static str {
+        "yellowstone"
+    }
+
+    async fn run(&self, db: Arc<dyn DatabaseInterface>, program_ids: Vec<String>) -> Result<(), SolanaError> {
+        let listeners = self.solana_client.listeners();
+        let mut cursors = HashMap::new();
+        for program_id in &program_ids {
+            listeners.started(program_id, self.name());
+            match db.get_program_cursor(self.solana_client.cluster(), program_id).await {
+                Ok(cursor) => {
+                    if let Some(cursor) = &cursor {
+                        listeners.advanced(program_id, &cursor.signature, cursor.slot);
+                    }
+                    cursors.insert(program_id.clone(), cursor);
+                },
+                Err(e) => error!("Failed to load signature cursor for program {}: {}", program_id, e),
+            }
+        }
+
+        loop {
+            match self.stream_transactions(db.as_ref(), &program_ids, &mut cursors).await {
+                Ok(()) => warn!("Geyser stream from {} closed, reconnecting", self.endpoint),
+                Err(e) => {
+                    warn!("Geyser stream from {} failed, reconnecting: {}", self.endpoint, e);
+                    for program_id in &program_ids {
+                        listeners.failed(program_id, e.to_string());
+                    }
+                },
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(RECONNECT_DELAY_SECS)).await;
+        }
+    }
+}
+
+// Subscribe to every confirmed non-vote transaction mentioning a tracked program
+fn subscribe_request(program_ids: &[String]) -> SubscribeRequest {
+    let filter = SubscribeRequestFilterTransactions {
+        vote: Some(false),
+        failed: None,
+        signature: None,
+        account_include: program_ids.to_vec(),
+        account_exclude: Vec::new(),
+        account_required: Vec::new(),
+    };
+
+    SubscribeRequest {
+        transactions: HashMap::from([("tracked_programs".to_string(), filter)]),
+        commitment: Some(CommitmentLevel::Confirmed as i32),
+        ping: None,
+    }
+}
+
+// Rebuild the transaction and status meta carried by a Geyser update
+fn decode_transaction_update(
+    update: SubscribeUpdateTransaction,
+) -> Result<(String, VersionedTransactionWithStatusMeta), String> {
+    let info = update.transaction.ok_or("update without transaction")?;
+    let signature = Signature::try_from(info.signature.as_slice())
+        .map_err(|e| format!("invalid signature: {}", e))?
+        .to_string();
+
+    let transaction = info.transaction.ok_or("update without transaction body")?;
+    let message = transaction.message.ok_or("transaction without message")?;
+    let meta = info.meta.ok_or("transaction without status meta")?;
+
+    let signatures = transaction.signatures.iter()
+        .map(|signature| Signature::try_from(signature.as_slice()).map_err(|e| format!("invalid signature: {}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let transaction = VersionedTransaction {
+        signatures,
+        message: decode_message(message)?,
+    };
+
+    Ok((signature, VersionedTransactionWithStatusMeta { transaction, meta: decode_meta(meta)? }))
+}
+
+fn decode_message(message: confirmed_block::Message) -> Result<VersionedMessage, String> {
+    let header = message.header.ok_or("message without header")?;
+    let header = MessageHeader {
+        num_required_signatures: to_u8(header.num_required_signatures)?,
+        num_readonly_signed_accounts: to_u8(header.num_readonly_signed_accounts)?,
+        num_readonly_unsigned_accounts: to_u8(header.num_readonly_unsigned_accounts)?,
+    };
+
+    let account_keys = message.account_keys.iter()
+        .map(|key| to_pubkey(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let recent_blockhash = <[u8; 32]>::try_from(message.recent_blockhash.as_slice())
+        .map(Hash::new_from_array)
+        .map_err(|_| "invalid recent blockhash".to_string())?;
+    let instructions = message.instructions.into_iter()
+        .map(|instruction| Ok(CompiledInstruction {
+            program_id_index: to_u8(instruction.program_id_index)?,
+            accounts: instruction.accounts,
+            data: instruction.data,
+        }))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if !message.versioned {
+        return Ok(VersionedMessage::Legacy(legacy::Message {
+            header,
+            account_keys,
+            recent_blockhash,
+            instructions,
+        }));
+    }
+
+    let address_table_lookups = message.address_table_lookups.into_iter()
+        .map(|lookup| Ok(v0::MessageAddressTableLookup {
+            account_key: to_pubkey(&lookup.account_key)?,
+            writable_indexes: lookup.writable_indexes,
+            readonly_indexes: lookup.readonly_indexes,
+        }))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(VersionedMessage::V0(v0::Message {
+        header,
+        account_keys,
+        recent_blockhash,
+        instructions,
+        address_table_lookups,
+    }))
+}
+
+fn decode_meta(meta: confirmed_block::TransactionStatusMeta) -> Result<TransactionStatusMeta, String> {
+    let status = match meta.err {
+        Some(err) => Err(bincode::deserialize::<TransactionError>(&err.err)
+            .map_err(|e| format!("invalid transaction error: {}", e))?),
+        None => Ok(()),
+    };
+
+    let inner_instructions = if meta.inner_instructions_none {
+        None
+    } else {
+        let inner_instructions = meta.inner_instructions.into_iter()
+            .map(|inner| Ok(InnerInstructions {
+                index: to_u8(inner.index)?,
+                instructions: inner.instructions.into_iter()
+                    .map(|instruction| Ok(InnerInstruction {
+                        instruction: CompiledInstruction {
+                            program_id_index: to_u8(instruction.program_id_index)?,
+                            accounts: instruction.accounts,
+                            data: instruction.data,
+                        },
+                        stack_height: instruction.stack_height,
+                    }))
+                    .collect::<Result<Vec<_>, String>>()?,
+            }))
+            .collect::<Result<Vec<_>, String>>()?;
+        Some(inner_instructions)
+    };
+
+    let loaded_addresses = v0::LoadedAddresses {
+        writable: meta.loaded_writable_addresses.iter().map(|key| to_pubkey(key)).collect::<Result<_, _>>()?,
+        readonly: meta.loaded_readonly_addresses.iter().map(|key| to_pubkey(key)).collect::<Result<_, _>>()?,
+    };
+
+    Ok(TransactionStatusMeta {
+        status,
+        fee: meta.fee,
+        pre_balances: meta.pre_balances,
+        post_balances: meta.post_balances,
+        inner_instructions,
+        log_messages: (!meta.log_messages_none).then_some(meta.log_messages),
+        loaded_addresses,
+        compute_units_consumed: meta.compute_units_consumed,
+        ..Default::default()
+    })
+}
+
+// Encode the transaction the way `getTransaction` returns it, so it goes through the same upload path
+fn encode_transaction(
+    slot: u64,
+    transaction: VersionedTransactionWithStatusMeta,
+) -> Result<EncodedConfirmedTransactionWithStatusMeta, String> {
+    ConfirmedTransactionWithStatusMeta {
+        slot,
+        tx_with_meta: TransactionWithStatusMeta::Complete(transaction),
+        block_time: None,
+    }
+    .encode(UiTransactionEncoding::Base64, Some(0))
+    .map_err(|e| e.to_string())
+}
+
+fn to_pubkey(bytes: &[u8]) -> Result<Pubkey, String> {
+    Pubkey::try_from(bytes).map_err(|_| format!("invalid public key of {} bytes", bytes.len()))
+}
+
+fn to_u8(value: u32) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("index {} out of range", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+    use futures_util::Stream;
+    use solana_client::rpc_client::RpcClient;
+    use solana_client::rpc_request::RpcRequest;
+    use tonic::{Request, Response, Status, Streaming};
+    use crate::graph_disc::{Invocation, MockDatabaseInterface};
+    use crate::scheme::DiscriminatorScheme;
+    use proto::geyser::{
+        geyser_server::{Geyser, GeyserServer}, SubscribeUpdate, SubscribeUpdateTransactionInfo,
+    };
+
+    // Stand-in Geyser server: answers a subscription with canned updates, then ends the stream
+    struct StandInGeyser {
+        updates: Vec<SubscribeUpdate>,
+        requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+    }
+
+    #[tonic::async_trait]
+    impl Geyser for StandInGeyser {
+        type SubscribeStream = Pin<Box<dyn Stream<Item = Result<SubscribeUpdate, Status>> + Send>>;
+
+        async fn subscribe(
+            &self,
+            request: Request<Streaming<SubscribeRequest>>,
+        ) -> Result<Response<Self::SubscribeStream>, Status> {
+            let subscription = request.into_inner().message().await?.expect("subscribe request");
+            self.requests.lock().unwrap().push(subscription);
+
+            let updates = self.updates.clone().into_iter().map(Ok);
+            Ok(Response::new(Box::pin(stream::iter(updates))))
+        }
+    }
+
+    async fn serve(geyser: StandInGeyser) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = stream::unfold(listener, |listener| async move {
+            Some((listener.accept().await.map(|(stream, _)| stream), listener))
+        });
+
+        tokio::spawn(tonic::transport::Server::builder()
+            .add_service(GeyserServer::new(geyser))
+            .serve_with_incoming(incoming));
+        endpoint
+    }
+
+    // A v0 transaction calling a program that is only reachable through a lookup table
+    fn transaction_update(payer: &Pubkey, outer_program: &Pubkey, lookup_table: &Pubkey, loaded_program: &Pubkey) -> SubscribeUpdate {
+        let message = confirmed_block::Message {
+            header: Some(confirmed_block::MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            }),
+            account_keys: vec![payer.to_bytes().to_vec(), outer_program.to_bytes().to_vec()],
+            recent_blockhash: vec![0; 32],
+            instructions: vec![confirmed_block::CompiledInstruction {
+                program_id_index: 1,
+                accounts: vec![0],
+                data: vec![1; 9],
+            }],
+            versioned: true,
+            address_table_lookups: vec![confirmed_block::MessageAddressTableLookup {
+                account_key: lookup_table.to_bytes().to_vec(),
+                writable_indexes: vec![],
+                readonly_indexes: vec![0],
+            }],
+        };
+        let meta = confirmed_block::TransactionStatusMeta {
+            inner_instructions: vec![confirmed_block::InnerInstructions {
+                index: 0,
+                instructions: vec![confirmed_block::InnerInstruction {
+                    program_id_index: 2,
+                    accounts: vec![0],
+                    data: vec![2; 9],
+                    stack_height: Some(2),
+                }],
+            }],
+            loaded_readonly_addresses: vec![loaded_program.to_bytes().to_vec()],
+            ..Default::default()
+        };
+
+        SubscribeUpdate {
+            filters: vec!["tracked_programs".to_string()],
+            update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+                transaction: Some(SubscribeUpdateTransactionInfo {
+                    signature: vec![5; 64],
+                    is_vote: false,
+                    transaction: Some(confirmed_block::Transaction {
+                        signatures: vec![vec![5; 64]],
+                        message: Some(message),
+                    }),
+                    meta: Some(meta),
+                    index: 0,
+                }),
+                slot: 42,
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn ingests_transactions_streamed_over_grpc() {
+        let payer = Pubkey::new_unique();
+        let outer_program = Pubkey::new_unique();
+        let lookup_table = Pubkey::new_unique();
+        let loaded_program = Pubkey::new_unique();
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let endpoint = serve(StandInGeyser {
+            updates: vec![transaction_update(&payer, &outer_program, &lookup_table, &loaded_program)],
+            requests: requests.clone(),
+        }).await;
+
+        let uploads = Arc::new(Mutex::new(Vec::new()));
+        let recorded = uploads.clone();
+        let mut db = MockDatabaseInterface::new();
+        db.expect_get_discriminator_scheme()
+            .returning(|_, _| Ok(DiscriminatorScheme::Anchor));
+        db.expect_upload_discriminator()
+            .returning(move |_, program_id, discriminator_data, _, _, metadata| {
+                recorded.lock().unwrap().push((program_id.to_string(), discriminator_data, metadata.invocation));
+                Ok(())
+            });
+        let signature = Signature::from([5u8; 64]).to_string();
+        let expected = (loaded_program.to_string(), signature.clone());
+        db.expect_update_program_cursor()
+            .withf(move |cluster, program_id, signature, slot| {
+                cluster == "mainnet" && (program_id.to_string(), signature.to_string()) == expected && *slot == 42
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        // Nothing to catch up on over RPC
+        let mut mocks = HashMap::new();
+        mocks.insert(RpcRequest::GetSignaturesForAddress, serde_json::json!([]));
+        let solana_client = SolanaConnection::with_client(RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks));
+
+        let source = YellowstoneSource::new(&endpoint, None, solana_client);
+        let program_ids = vec![loaded_program.to_string()];
+        let mut cursors = HashMap::new();
+        source.stream_transactions(&db, &program_ids, &mut cursors).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].transactions["tracked_programs"].account_include, program_ids);
+        assert_eq!(*uploads.lock().unwrap(), vec![
+            (outer_program.to_string(), vec![1; 8], Some(Invocation { cpi: false, stack_height: Some(1) })),
+            (loaded_program.to_string(), vec![2; 8], Some(Invocation { cpi: true, stack_height: Some(2) })),
+        ]);
+        assert_eq!(cursors[&loaded_program.to_string()].as_ref().unwrap().slot, 42);
+    }
+}
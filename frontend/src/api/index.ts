@@ -10,6 +10,17 @@ const api = axios.create({
   },
 });
 
+// Session token from a wallet sign-in, sent with every upload
+const SESSION_KEY = 'dd_session';
+
+api.interceptors.request.use((config) => {
+  const token = localStorage.getItem(SESSION_KEY);
+  if (token) {
+    config.headers.Authorization = `Bearer ${token}`;
+  }
+  return config;
+});
+
 // Types
 export interface Instruction {
   id: string;
@@ -23,19 +34,85 @@ export interface Discriminator {
   discriminator_data: number[];
   program_id: string;
   user_id: string;
+  // Labelled by the program's verified upgrade authority; listed first
+  authoritative?: boolean;
   instruction: Instruction | null;
 }
 
+export type DataEncoding = 'hex' | 'base58' | 'base64';
+
+export type DiscriminatorKind = 'instruction' | 'account' | 'event' | 'unknown';
+
 export interface DiscriminatorUploadData {
   program_id: string;
-  discriminator_data: number[];
-  instruction_data: number[] | null;
-  instruction_id?: string | null;
+  discriminator: string;
+  encoding?: DataEncoding;
+  name?: string | null;
+  kind?: DiscriminatorKind;
+  source?: string | null;
+  instruction_data?: string | null;
+}
+
+// Error body of a rejected upload; `fields` maps each invalid field to its problem
+export interface UploadError {
+  error: string;
+  fields?: Record<string, string>;
+}
+
+// Wallet injected by browser extensions such as Phantom or Solflare
+interface InjectedWallet {
+  connect: () => Promise<{ publicKey: { toString: () => string } }>;
+  signMessage: (message: Uint8Array, display?: 'utf8') => Promise<{ signature: Uint8Array }>;
+}
+
+declare global {
+  interface Window {
+    solana?: InjectedWallet;
+  }
 }
 
+const BASE58_ALPHABET = '123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz';
+
+const encodeBase58 = (bytes: Uint8Array): string => {
+  let value = bytes.reduce((acc, byte) => acc * 256n + BigInt(byte), 0n);
+  let encoded = '';
+  while (value > 0n) {
+    encoded = BASE58_ALPHABET[Number(value % 58n)] + encoded;
+    value /= 58n;
+  }
+  const leadingZeros = bytes.findIndex((byte) => byte !== 0);
+  return '1'.repeat(leadingZeros === -1 ? bytes.length : leadingZeros) + encoded;
+};
+
 // API functions
-export const uploadDiscriminator = async (data: DiscriminatorUploadData): Promise<void> => {
-  await api.post(`/upload_discriminator/${data.program_id}`, data);
+export const isSignedIn = (): boolean => localStorage.getItem(SESSION_KEY) !== null;
+
+// Prove control of the connected wallet by signing a server-issued nonce
+export const signIn = async (): Promise<string> => {
+  if (!window.solana) {
+    throw new Error('No Solana wallet found. Install a wallet extension such as Phantom.');
+  }
+  const { publicKey } = await window.solana.connect();
+  const pubkey = publicKey.toString();
+
+  const challenge = await api.post<{ nonce: string; message: string }>('/auth/nonce', { pubkey });
+  const { signature } = await window.solana.signMessage(new TextEncoder().encode(challenge.data.message), 'utf8');
+  const session = await api.post<{ token: string }>('/auth/verify', {
+    pubkey,
+    nonce: challenge.data.nonce,
+    signature: encodeBase58(signature),
+  });
+
+  localStorage.setItem(SESSION_KEY, session.data.token);
+  return pubkey;
+};
+
+export const signOut = (): void => {
+  localStorage.removeItem(SESSION_KEY);
+};
+
+export const uploadDiscriminator = async ({ program_id, ...body }: DiscriminatorUploadData): Promise<void> => {
+  await api.post(`/upload_discriminator/${program_id}`, body);
 };
 
 export const queryDiscriminatorsByProgramId = async (programId: string): Promise<Discriminator[]> => {
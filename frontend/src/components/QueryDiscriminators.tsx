@@ -10,6 +10,8 @@ interface Discriminator {
   discriminator_data: number[];
   program_id: string;
   user_id: string;
+  // Labelled by the program's verified upgrade authority
+  authoritative?: boolean;
   instruction: {
     id: string;
     instruction_id: string;
@@ -193,7 +195,12 @@ const QueryDiscriminators = () => {
                         <InstructionDataView data={disc.discriminator_data} />
                       </td>
                       <td className="px-6 py-4 whitespace-nowrap text-sm text-gray-300">{disc.program_id}</td>
-                      <td className="px-6 py-4 whitespace-nowrap text-sm text-gray-300">{disc.user_id}</td>
+                      <td className="px-6 py-4 whitespace-nowrap text-sm text-gray-300">
+                        {disc.user_id}
+                        {disc.authoritative && (
+                          <span className="ml-2 bg-purple-900/50 text-purple-300 py-1 px-2 rounded-full text-xs font-medium" title="Uploaded by the program's upgrade authority">Official</span>
+                        )}
+                      </td>
                       <td className="px-6 py-4 whitespace-nowrap text-sm">
                         {disc.instruction ? (
                           <span className="bg-green-900/50 text-green-300 py-1 px-2 rounded-full text-xs font-medium cursor-pointer hover:bg-green-800/50" title="Click to view instruction details">Available</span>
@@ -1,7 +1,7 @@
 // src/components/UploadDiscriminator.tsx
 import { useState } from 'react';
 import ExplanationCard from './common/ExplanationCard';
-import { uploadDiscriminator } from '../api';
+import { isSignedIn, signIn, signOut, uploadDiscriminator, UploadError } from '../api';
 
 const UploadDiscriminator = () => {
   const [programId, setProgramId] = useState('');
@@ -12,28 +12,11 @@ const UploadDiscriminator = () => {
   const [success, setSuccess] = useState<string | null>(null);
   const [error, setError] = useState<string | null>(null);
 
-  // Function to parse hex string into byte array
-  const parseHexString = (hexString: string): number[] => {
-    // Remove "0x" prefix if present
-    const cleanedHex = hexString.startsWith('0x') ? hexString.substring(2) : hexString;
-    
-    // Ensure even number of characters
-    const paddedHex = cleanedHex.length % 2 === 0 ? cleanedHex : '0' + cleanedHex;
-    
-    // Convert to byte array
-    const result: number[] = [];
-    for (let i = 0; i < paddedHex.length; i += 2) {
-      result.push(parseInt(paddedHex.substring(i, i + 2), 16));
-    }
-    
-    return result;
-  };
-
   // Validate that input is a valid hex string
   const validateHexData = (input: string): boolean => {
-    // Remove "0x" prefix if present
-    const hexData = input.startsWith('0x') ? input.substring(2) : input;
-    return /^[0-9a-fA-F]*$/.test(hexData);
+    // Remove "0x" prefix if present; bytes may be separated by whitespace
+    const hexData = input.trim().startsWith('0x') ? input.trim().substring(2) : input;
+    return /^[0-9a-fA-F\s]*$/.test(hexData);
   };
 
   const handleSubmit = async (e: React.FormEvent) => {
@@ -64,24 +47,23 @@ const UploadDiscriminator = () => {
       return;
     }
     
-    const discriminatorBytes = parseHexString(discriminatorData);
-    if (discriminatorBytes.length !== 8) {
-      setError('Discriminator data must be exactly 8 bytes (16 hex characters)');
-      return;
-    }
-    
+    // The backend checks the length against the program's discriminator scheme
     setLoading(true);
     
     try {
       // Prepare data for API
       const uploadData = {
         program_id: programId,
-        discriminator_data: discriminatorBytes,
-        instruction_data: instructionData ? parseHexString(instructionData) : null,
-        instruction_id: instructionName || null
+        discriminator: discriminatorData,
+        encoding: 'hex' as const,
+        name: instructionName || null,
+        instruction_data: instructionData || null
       };
       
-      // Call the real API
+      // Uploads are attributed to a verified wallet
+      if (!isSignedIn()) {
+        await signIn();
+      }
       await uploadDiscriminator(uploadData);
       
       // Clear form on success
@@ -93,10 +75,20 @@ const UploadDiscriminator = () => {
       setSuccess('Discriminator successfully uploaded!');
     } catch (err: any) {
       console.error('Error uploading discriminator:', err);
-      if (err.code === 'ECONNREFUSED' || err.code === 'ERR_NETWORK') {
+      if (err.response?.status === 401) {
+        // The session expired or the server restarted
+        signOut();
+        setError('Please sign in with your wallet again');
+      } else if (err.code === 'ECONNREFUSED' || err.code === 'ERR_NETWORK') {
         setError('Cannot connect to backend server. Please ensure it is running at http://localhost:8080');
+      } else if (!err.response) {
+        setError(err.message || 'Failed to sign in with your wallet');
       } else {
-        setError(err.response?.data || 'Failed to upload discriminator');
+        const body: UploadError | undefined = err.response?.data;
+        const fields = body?.fields
+          ? Object.entries(body.fields).map(([field, problem]) => `${field}: ${problem}`).join('; ')
+          : null;
+        setError(fields || body?.error || 'Failed to upload discriminator');
       }
     } finally {
       setLoading(false);
@@ -147,7 +139,7 @@ const UploadDiscriminator = () => {
               type="text"
               value={discriminatorData}
               onChange={(e) => setDiscriminatorData(e.target.value)}
-              placeholder="8-byte discriminator in hex format"
+              placeholder="Discriminator in hex format (8 bytes for Anchor programs)"
               className="border border-gray-600 bg-gray-800 text-white p-3 w-full rounded-md shadow-sm focus:ring-2 focus:ring-purple-500 focus:border-purple-500 focus:outline-none"
               required
             />
@@ -5,8 +5,13 @@ const api = axios.create({
   baseURL: 'http://127.0.0.1:8080',
 });
 
-export const uploadDiscriminator = (programId: string, discriminator: string, instruction: string, userId: string) => {
-  return api.post(`/upload_discriminator/${programId}`, { discriminator, instruction, user_id: userId });
+// `sessionToken` comes from signing in with a wallet through `/auth/nonce` and `/auth/verify`
+export const uploadDiscriminator = (programId: string, discriminator: string, instruction: string, sessionToken: string) => {
+  return api.post(
+    `/upload_discriminator/${programId}`,
+    { discriminator, encoding: 'hex', instruction_data: instruction },
+    { headers: { Authorization: `Bearer ${sessionToken}` } },
+  );
 };
 
 export const queryDiscriminators = (programId: string) => {